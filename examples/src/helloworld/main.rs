@@ -32,7 +32,7 @@ struct HelloWorld;
 
 #[async_trait]
 impl Filesystem for HelloWorld {
-    async fn init(&self, _req: Request) -> Result<()> {
+    async fn init(&self, _req: Request, _config: &mut KernelConfig) -> Result<()> {
         Ok(())
     }
 
@@ -142,9 +142,7 @@ impl Filesystem for HelloWorld {
         }
 
         if offset as usize >= CONTENT.len() {
-            Ok(ReplyData {
-                data: Box::new(b""),
-            })
+            Ok(ReplyData::Data(Box::new(b"")))
         } else {
             let mut data = &CONTENT.as_bytes()[offset as usize..];
 
@@ -152,9 +150,7 @@ impl Filesystem for HelloWorld {
                 data = &data[..size as usize];
             }
 
-            Ok(ReplyData {
-                data: Box::new(data),
-            })
+            Ok(ReplyData::Data(Box::new(data)))
         }
     }
 
@@ -195,7 +191,9 @@ impl Filesystem for HelloWorld {
         ];
 
         Ok(ReplyDirectory {
-            entries: Box::pin(stream::iter(entries.into_iter().skip(offset as usize))),
+            entries: Box::pin(stream::iter(
+                entries.into_iter().skip(offset as usize).map(Ok),
+            )),
         })
     }
 
@@ -302,7 +300,9 @@ impl Filesystem for HelloWorld {
         ];
 
         Ok(ReplyDirectoryPlus {
-            entries: Box::pin(stream::iter(entries.into_iter().skip(offset as usize))),
+            entries: Box::pin(stream::iter(
+                entries.into_iter().skip(offset as usize).map(Ok),
+            )),
         })
     }
 
@@ -329,6 +329,8 @@ async fn main() {
         .mount_with_unprivileged(HelloWorld {}, mount_path)
         .await
         .unwrap()
+        .await
+        .unwrap();
 }
 
 fn log_init() {
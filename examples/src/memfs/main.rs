@@ -179,7 +179,7 @@ impl Default for FS {
 
 #[async_trait]
 impl Filesystem for FS {
-    async fn init(&self, _req: Request) -> Result<()> {
+    async fn init(&self, _req: Request, _config: &mut KernelConfig) -> Result<()> {
         Ok(())
     }
 
@@ -456,9 +456,7 @@ impl Filesystem for FS {
             let file = file.read().await;
 
             if file.content.len() <= offset as _ {
-                return Ok(ReplyData {
-                    data: Box::new(b""),
-                });
+                return Ok(ReplyData::Data(Box::new(b"")));
             }
 
             let mut data = &file.content[offset as _..];
@@ -467,9 +465,7 @@ impl Filesystem for FS {
                 data = &data[..size as _];
             }
 
-            Ok(ReplyData {
-                data: Box::new(data.to_vec()),
-            })
+            Ok(ReplyData::Data(Box::new(data.to_vec())))
         } else {
             Err(libc::EISDIR.into())
         }
@@ -481,9 +477,11 @@ impl Filesystem for FS {
         inode: u64,
         _fh: u64,
         offset: u64,
-        data: &[u8],
+        data: WriteData,
         _flags: u32,
     ) -> Result<ReplyWrite> {
+        let data = data.into_bytes()?;
+
         let inner = self.0.read().await;
 
         let entry = inner
@@ -590,7 +588,7 @@ impl Filesystem for FS {
                 .await;
 
             Ok(ReplyDirectory {
-                entries: Box::pin(stream::iter(children)),
+                entries: Box::pin(stream::iter(children.into_iter().map(Ok))),
             })
         } else {
             Err(libc::ENOTDIR.into())
@@ -763,7 +761,7 @@ impl Filesystem for FS {
                 .await;
 
             Ok(ReplyDirectoryPlus {
-                entries: Box::pin(stream::iter(children)),
+                entries: Box::pin(stream::iter(children.into_iter().map(Ok))),
             })
         } else {
             Err(libc::ENOTDIR.into())
@@ -832,12 +830,13 @@ impl Filesystem for FS {
         length: u64,
         flags: u64,
     ) -> Result<ReplyCopyFileRange> {
-        let data = self.read(req, inode, fh_in, off_in, length as _).await?;
-
-        let data = data.data.as_ref().as_ref();
+        let data = match self.read(req, inode, fh_in, off_in, length as _).await? {
+            ReplyData::Data(data) => data.as_ref().as_ref().to_vec(),
+            ReplyData::Fd { .. } => unreachable!("memfs only replies in memory data"),
+        };
 
         let ReplyWrite { written } = self
-            .write(req, inode_out, fh_out, off_out, data, flags as _)
+            .write(req, inode_out, fh_out, off_out, data.into(), flags as _)
             .await?;
 
         Ok(ReplyCopyFileRange { copied: written })
@@ -871,5 +870,7 @@ async fn main() {
     Session::new(mount_options)
         .mount(FS::default(), mount_path)
         .await
+        .unwrap()
+        .await
         .unwrap();
 }
@@ -34,7 +34,7 @@ struct Poll {
 
 #[async_trait]
 impl Filesystem for Poll {
-    async fn init(&self, _req: Request) -> Result<()> {
+    async fn init(&self, _req: Request, _config: &mut KernelConfig) -> Result<()> {
         Ok(())
     }
 
@@ -144,9 +144,7 @@ impl Filesystem for Poll {
         }
 
         if offset as usize >= CONTENT.len() {
-            Ok(ReplyData {
-                data: Box::new(b""),
-            })
+            Ok(ReplyData::Data(Box::new(b"")))
         } else {
             let mut data = &CONTENT.as_bytes()[offset as usize..];
 
@@ -154,9 +152,7 @@ impl Filesystem for Poll {
                 data = &data[..size as usize];
             }
 
-            Ok(ReplyData {
-                data: Box::new(data),
-            })
+            Ok(ReplyData::Data(Box::new(data)))
         }
     }
 
@@ -197,7 +193,9 @@ impl Filesystem for Poll {
         ];
 
         Ok(ReplyDirectory {
-            entries: Box::pin(stream::iter(entries.into_iter().skip(offset as usize))),
+            entries: Box::pin(stream::iter(
+                entries.into_iter().skip(offset as usize).map(Ok),
+            )),
         })
     }
 
@@ -304,7 +302,9 @@ impl Filesystem for Poll {
         ];
 
         Ok(ReplyDirectoryPlus {
-            entries: Box::pin(stream::iter(entries.into_iter().skip(offset as usize))),
+            entries: Box::pin(stream::iter(
+                entries.into_iter().skip(offset as usize).map(Ok),
+            )),
         })
     }
 
@@ -383,6 +383,8 @@ async fn main() {
     session
         .mount_with_unprivileged(poll, mount_path)
         .await
+        .unwrap()
+        .await
         .unwrap();
 }
 
@@ -0,0 +1,29 @@
+#![no_main]
+
+//! feed arbitrary bodies of known opcodes to the request decoder. The `fuse_in_header` is built
+//! with a consistent len, so the fuzzer spends its time on the body decoding.
+
+use libfuzzer_sys::fuzz_target;
+
+/// the size of `fuse_in_header`.
+const FUSE_IN_HEADER_SIZE: usize = 40;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+
+    let (minor, opcode, body) = (data[0], data[1], &data[2..]);
+
+    let mut request = Vec::with_capacity(FUSE_IN_HEADER_SIZE + body.len());
+
+    // len, opcode, unique, nodeid, uid, gid, pid, padding
+    request.extend_from_slice(&((FUSE_IN_HEADER_SIZE + body.len()) as u32).to_le_bytes());
+    request.extend_from_slice(&(opcode as u32).to_le_bytes());
+    request.extend_from_slice(&1u64.to_le_bytes());
+    request.extend_from_slice(&1u64.to_le_bytes());
+    request.extend_from_slice(&[0; 16]);
+    request.extend_from_slice(body);
+
+    fuse3::fuzz_decode(&request, minor as u32);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+//! feed arbitrary bytes to the request decoder, the first byte is the negotiated protocol minor
+//! version.
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((minor, request)) = data.split_first() {
+        fuse3::fuzz_decode(request, *minor as u32);
+    }
+});
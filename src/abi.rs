@@ -30,9 +30,18 @@ use serde::{Deserialize, Serialize};
 /// and 128k on other systems.
 pub const MAX_WRITE_SIZE: usize = 16 * 1024 * 1024;
 
-/// Size of the buffer for reading a request from the kernel. Since the kernel may send
-/// up to MAX_WRITE_SIZE bytes in a write request, we use that value plus some extra space.
-pub const BUFFER_SIZE: usize = MAX_WRITE_SIZE + 4096;
+/// The absolute minimum size of write requests from the kernel.
+pub const MIN_WRITE_SIZE: usize = 4096;
+
+/// Extra space of the buffer for reading a request from the kernel. Since the kernel may send
+/// up to max_write bytes in a write request, the buffer size is max_write plus this value.
+pub const BUFFER_EXTRA_SIZE: usize = 4096;
+
+/// The default size of the reply queue of a worker channel.
+pub const DEFAULT_REPLY_QUEUE_SIZE: usize = 1024;
+
+/// The ioctl magic of `/dev/fuse`, `FUSE_DEV_IOC_CLONE` is `_IOR(229, 0, uint32_t)`.
+pub const FUSE_DEV_IOC_MAGIC: u8 = 229;
 
 pub const FUSE_KERNEL_VERSION: u32 = 7;
 
@@ -46,6 +55,12 @@ pub const DEFAULT_TIME_GRAN: u32 = 1;
 
 pub const DEFAULT_MAX_PAGES: u16 = u16::max_value();
 
+/// The max pages of a request if `FUSE_MAX_PAGES` is not negotiated.
+pub const FUSE_DEFAULT_MAX_PAGES_PER_REQ: u16 = 32;
+
+/// The kernel limit of the max pages of a request.
+pub const FUSE_MAX_MAX_PAGES: u16 = 256;
+
 // TODO find valid value
 pub const DEFAULT_MAP_ALIGNMENT: u16 = 0;
 
@@ -297,7 +312,8 @@ pub struct fuse_file_lock {
 #[derive(Debug)]
 pub struct UnknownOpcodeError(pub u32);
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
+/// the fuse request opcode.
+#[derive(Debug, Copy, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum fuse_opcode {
     FUSE_LOOKUP = 1,
@@ -493,6 +509,9 @@ impl TryFrom<u32> for fuse_notify_code {
 
 pub const FUSE_ENTRY_OUT_SIZE: usize = mem::size_of::<fuse_entry_out>();
 
+/// the size of `fuse_entry_out` before protocol 7.9, `fuse_attr` has no `blksize`.
+pub const FUSE_COMPAT_ENTRY_OUT_SIZE: usize = 120;
+
 #[derive(Debug, Serialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_entry_out {
@@ -529,6 +548,8 @@ pub struct fuse_batch_forget_in {
     pub dummy: u32,
 }
 
+pub const FUSE_GETATTR_IN_SIZE: usize = mem::size_of::<fuse_getattr_in>();
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_getattr_in {
@@ -539,6 +560,9 @@ pub struct fuse_getattr_in {
 
 pub const FUSE_ATTR_OUT_SIZE: usize = mem::size_of::<fuse_attr_out>();
 
+/// the size of `fuse_attr_out` before protocol 7.9, `fuse_attr` has no `blksize`.
+pub const FUSE_COMPAT_ATTR_OUT_SIZE: usize = 96;
+
 #[derive(Debug, Serialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_attr_out {
@@ -560,6 +584,9 @@ pub struct fuse_getxtimes_out {
 
 pub const FUSE_MKNOD_IN_SIZE: usize = mem::size_of::<fuse_mknod_in>();
 
+/// the size of `fuse_mknod_in` before protocol 7.12, it has no `umask`.
+pub const FUSE_COMPAT_MKNOD_IN_SIZE: usize = 8;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_mknod_in {
@@ -657,6 +684,10 @@ pub struct fuse_open_in {
 
 pub const FUSE_CREATE_IN_SIZE: usize = mem::size_of::<fuse_create_in>();
 
+/// the size of `fuse_create_in` before protocol 7.12, it is a `fuse_open_in` with `flags` and
+/// `mode`.
+pub const FUSE_COMPAT_CREATE_IN_SIZE: usize = 8;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_create_in {
@@ -694,6 +725,11 @@ pub struct fuse_flush_in {
     pub lock_owner: u64,
 }
 
+pub const FUSE_READ_IN_SIZE: usize = mem::size_of::<fuse_read_in>();
+
+/// the size of `fuse_read_in` before protocol 7.9, it has no `lock_owner` and `flags`.
+pub const FUSE_COMPAT_READ_IN_SIZE: usize = 24;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_read_in {
@@ -708,6 +744,9 @@ pub struct fuse_read_in {
 
 pub const FUSE_WRITE_IN_SIZE: usize = mem::size_of::<fuse_write_in>();
 
+/// the size of `fuse_write_in` before protocol 7.9, it has no `lock_owner` and `flags`.
+pub const FUSE_COMPAT_WRITE_IN_SIZE: usize = 24;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_write_in {
@@ -722,7 +761,7 @@ pub struct fuse_write_in {
 
 pub const FUSE_WRITE_OUT_SIZE: usize = mem::size_of::<fuse_write_out>();
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_write_out {
     pub size: u32,
@@ -808,6 +847,11 @@ pub struct fuse_access_in {
     pub padding: u32,
 }
 
+pub const FUSE_INIT_IN_SIZE: usize = mem::size_of::<fuse_init_in>();
+
+/// the size of `fuse_init_in` before protocol 7.6, it only has `major` and `minor`.
+pub const FUSE_COMPAT_INIT_IN_SIZE: usize = 8;
+
 #[derive(Debug, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_init_in {
@@ -819,6 +863,13 @@ pub struct fuse_init_in {
 
 pub const FUSE_INIT_OUT_SIZE: usize = mem::size_of::<fuse_init_out>();
 
+/// the size of `fuse_init_out` before protocol 7.5, it only has `major` and `minor`.
+pub const FUSE_COMPAT_INIT_OUT_SIZE: usize = 8;
+
+/// the size of `fuse_init_out` before protocol 7.23, it has no `time_gran` and the fields after
+/// it.
+pub const FUSE_COMPAT_22_INIT_OUT_SIZE: usize = 24;
+
 #[derive(Debug, Serialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_init_out {
@@ -962,7 +1013,7 @@ pub struct fuse_in_header {
 
 pub const FUSE_OUT_HEADER_SIZE: usize = mem::size_of::<fuse_out_header>();
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub struct fuse_out_header {
     pub len: u32,
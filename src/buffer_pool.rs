@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+/// the max number of idle buffers kept by a pool.
+const MAX_IDLE_BUFFERS: usize = 8;
+
+/// a pool of request buffers. The buffer of a request is handed to the handler as [`Bytes`],
+/// when all the [`Bytes`] of the request are dropped, the buffer is given back to the pool.
+pub(crate) struct BufferPool {
+    buffer_size: usize,
+    idle_buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl BufferPool {
+    pub(crate) fn new(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            idle_buffers: Arc::new(Mutex::new(Vec::with_capacity(MAX_IDLE_BUFFERS))),
+        }
+    }
+
+    /// get an idle buffer, or allocate a new one if there is no idle buffer.
+    pub(crate) fn get(&self) -> Vec<u8> {
+        match self.idle_buffers.lock().unwrap().pop() {
+            None => vec![0; self.buffer_size],
+            Some(buffer) => buffer,
+        }
+    }
+
+    /// freeze the first `len` bytes of the buffer, the buffer will be given back to the pool
+    /// when the returned [`Bytes`] and all its slices are dropped.
+    pub(crate) fn freeze(&self, buffer: Vec<u8>, len: usize) -> Bytes {
+        Bytes::from_owner(PooledBuffer {
+            buffer,
+            len,
+            idle_buffers: self.idle_buffers.clone(),
+        })
+    }
+}
+
+struct PooledBuffer {
+    buffer: Vec<u8>,
+    len: usize,
+    idle_buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut idle_buffers = self.idle_buffers.lock().unwrap();
+
+        if idle_buffers.len() < MAX_IDLE_BUFFERS {
+            idle_buffers.push(std::mem::take(&mut self.buffer));
+        }
+    }
+}
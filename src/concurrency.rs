@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_lock::{Semaphore, SemaphoreGuardArc};
+
+use crate::abi::fuse_opcode;
+use crate::MountOptions;
+
+/// the limits of the request handlers running at the same time, and the same limits of the
+/// requests waiting for the previous ordered requests.
+pub(crate) struct ConcurrencyLimits {
+    running: Limits,
+    queued: Limits,
+}
+
+struct Limits {
+    total: Option<Arc<Semaphore>>,
+    opcodes: HashMap<fuse_opcode, Arc<Semaphore>>,
+}
+
+/// the permits of a request handler, they are released when it is dropped.
+#[derive(Default)]
+pub(crate) struct Permits {
+    _permits: Vec<SemaphoreGuardArc>,
+}
+
+impl ConcurrencyLimits {
+    pub(crate) fn new(mount_options: &MountOptions) -> Self {
+        Self {
+            running: Limits::new(mount_options),
+            queued: Limits::new(mount_options),
+        }
+    }
+
+    /// wait until the request handler of the `opcode` can run.
+    pub(crate) async fn acquire(&self, opcode: fuse_opcode) -> Permits {
+        if is_unlimited(opcode) {
+            return Permits::default();
+        }
+
+        self.running.acquire(opcode).await
+    }
+
+    /// wait until the request of the `opcode` can wait for the previous ordered requests. The
+    /// waiting requests don't take the permits of the running handlers, otherwise the handlers
+    /// they wait for may be never run.
+    pub(crate) async fn acquire_queued(&self, opcode: fuse_opcode) -> Permits {
+        if is_unlimited(opcode) {
+            return Permits::default();
+        }
+
+        self.queued.acquire(opcode).await
+    }
+}
+
+impl Limits {
+    fn new(mount_options: &MountOptions) -> Self {
+        Self {
+            total: mount_options
+                .max_concurrency
+                .map(|max_concurrency| Arc::new(Semaphore::new(max_concurrency))),
+            opcodes: mount_options
+                .opcode_max_concurrency
+                .iter()
+                .map(|(&opcode, &max_concurrency)| {
+                    (opcode, Arc::new(Semaphore::new(max_concurrency)))
+                })
+                .collect(),
+        }
+    }
+
+    async fn acquire(&self, opcode: fuse_opcode) -> Permits {
+        let mut permits = Vec::with_capacity(2);
+
+        // acquire the opcode permit first, so a blocked opcode doesn't hold a total permit
+        if let Some(semaphore) = self.opcodes.get(&opcode) {
+            permits.push(semaphore.acquire_arc().await);
+        }
+
+        if let Some(semaphore) = &self.total {
+            permits.push(semaphore.acquire_arc().await);
+        }
+
+        Permits { _permits: permits }
+    }
+}
+
+fn is_unlimited(opcode: fuse_opcode) -> bool {
+    // these requests manage the session itself, limit them may block the session forever
+    if let fuse_opcode::FUSE_INIT | fuse_opcode::FUSE_DESTROY | fuse_opcode::FUSE_INTERRUPT = opcode
+    {
+        return true;
+    }
+
+    // the blocking lock waits for the unlock request, it can't hold a permit while waiting
+    waits_for_later_request(opcode)
+}
+
+/// whether the request handler of the `opcode` may wait for a later request, such as the
+/// blocking lock waits for the unlock request. It can't hold the limits the later request needs.
+pub(crate) fn waits_for_later_request(opcode: fuse_opcode) -> bool {
+    match opcode {
+        #[cfg(feature = "file-lock")]
+        fuse_opcode::FUSE_SETLKW => true,
+
+        _ => false,
+    }
+}
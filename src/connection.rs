@@ -1,18 +1,23 @@
 #[cfg(feature = "unprivileged")]
 use std::ffi::OsString;
 use std::io;
+use std::io::ErrorKind;
+use std::io::IoSlice;
+use std::os::raw::c_int;
 use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
 use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::OwnedFd;
 use std::os::unix::io::RawFd;
-#[cfg(feature = "unprivileged")]
 use std::path::Path;
 #[cfg(feature = "unprivileged")]
 use std::process::Command;
 
 #[cfg(feature = "async-std-runtime")]
-use async_std::sync::Mutex;
-#[cfg(feature = "unprivileged")]
-use log::debug;
+use async_io::Async;
+use log::{debug, error};
+use nix::fcntl::{self, FcntlArg, OFlag, SpliceFFlags};
+use nix::mount::{self, MsFlags};
 #[cfg(feature = "unprivileged")]
 use nix::sys::socket;
 #[cfg(feature = "unprivileged")]
@@ -21,23 +26,104 @@ use nix::sys::socket::{AddressFamily, ControlMessageOwned, MsgFlags, SockFlag, S
 use nix::sys::uio::IoVec;
 use nix::unistd;
 #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
-use tokio::sync::Mutex;
+use tokio::io::unix::AsyncFd;
 
+use crate::abi::FUSE_DEV_IOC_MAGIC;
 use crate::helper::io_error_from_nix_error;
-use crate::spawn::spawn_blocking;
 #[cfg(feature = "unprivileged")]
+use crate::spawn::spawn_blocking;
 use crate::MountOptions;
 
+nix::ioctl_read!(fuse_dev_ioc_clone, FUSE_DEV_IOC_MAGIC, 0, u32);
+
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
+/// the `/dev/fuse` connection, the fd is set to `O_NONBLOCK` and registered with the runtime
+/// reactor, so read and write never block the runtime thread.
 pub struct FuseConnection {
-    fd: RawFd,
-    read: Mutex<()>,
-    write: Mutex<()>,
+    #[cfg(feature = "async-std-runtime")]
+    fd: Async<OwnedFd>,
+    #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+    fd: AsyncFd<OwnedFd>,
 }
 
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
 impl FuseConnection {
-    pub async fn new() -> io::Result<Self> {
+    pub async fn new(
+        mut mount_options: MountOptions,
+        mount_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let fd = Self::open_dev_fuse().await?;
+
+        let options = mount_options.build(fd);
+
+        let fs_name = if let Some(fs_name) = mount_options.fs_name.as_ref() {
+            Some(fs_name.as_str())
+        } else {
+            Some("fuse")
+        };
+
+        debug!("mount options {:?}", options);
+
+        if let Err(err) = mount::mount(
+            fs_name,
+            mount_path.as_ref(),
+            Some("fuse"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+            Some(options.as_os_str()),
+        ) {
+            error!("mount {:?} failed", mount_path.as_ref());
+
+            let _ = unistd::close(fd);
+
+            return Err(io_error_from_nix_error(err));
+        }
+
+        // the fd can only be polled after it is attached to a fuse connection, so register it
+        // with the reactor after mount.
+        Self::from_raw_fd(fd)
+    }
+
+    /// clone a new connection which is attached to the same fuse session by the
+    /// `FUSE_DEV_IOC_CLONE` ioctl. Requests read from the cloned connection must be replied
+    /// through it too.
+    pub async fn try_clone(&self) -> io::Result<Self> {
+        let fd = Self::open_dev_fuse().await?;
+
+        let mut session_fd = self.as_raw_fd() as u32;
+
+        if let Err(err) = unsafe { fuse_dev_ioc_clone(fd, &mut session_fd) } {
+            let _ = unistd::close(fd);
+
+            return Err(io_error_from_nix_error(err));
+        }
+
+        Self::from_raw_fd(fd)
+    }
+
+    /// take the ownership of `fd`, set it to non-blocking mode and register it with the runtime
+    /// reactor.
+    fn from_raw_fd(fd: RawFd) -> io::Result<Self> {
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let flags =
+            fcntl::fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL).map_err(io_error_from_nix_error)?;
+
+        fcntl::fcntl(
+            fd.as_raw_fd(),
+            FcntlArg::F_SETFL(OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK),
+        )
+        .map_err(io_error_from_nix_error)?;
+
+        #[cfg(feature = "async-std-runtime")]
+        let fd = Async::new(fd)?;
+
+        #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+        let fd = AsyncFd::new(fd)?;
+
+        Ok(Self { fd })
+    }
+
+    async fn open_dev_fuse() -> io::Result<RawFd> {
         const DEV_FUSE: &str = "/dev/fuse";
 
         #[cfg(feature = "async-std-runtime")]
@@ -58,11 +144,7 @@ impl FuseConnection {
             .await
             .into_raw_fd();
 
-        Ok(Self {
-            fd,
-            read: Mutex::new(()),
-            write: Mutex::new(()),
-        })
+        Ok(fd)
     }
 
     #[cfg(feature = "unprivileged")]
@@ -150,52 +232,144 @@ impl FuseConnection {
             return Err(io_error_from_nix_error(err));
         }
 
-        Ok(Self {
-            fd,
-            read: Mutex::new(()),
-            write: Mutex::new(()),
-        })
+        Self::from_raw_fd(fd)
+    }
+
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            #[cfg(feature = "async-std-runtime")]
+            let result = self
+                .fd
+                .read_with(|fd| unistd::read(fd.as_raw_fd(), buf).map_err(io_error_from_nix_error))
+                .await;
+
+            #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+            let result = {
+                let mut guard = self.fd.readable().await?;
+
+                match guard
+                    .try_io(|fd| unistd::read(fd.as_raw_fd(), buf).map_err(io_error_from_nix_error))
+                {
+                    Err(_would_block) => continue,
+                    Ok(result) => result,
+                }
+            };
+
+            match result {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
 
-    pub async fn read(&self, mut buf: Vec<u8>) -> Result<(Vec<u8>, usize), (Vec<u8>, io::Error)> {
-        let _guard = self.read.lock().await;
+    /// splice a request to the pipe `pipe_fd`, `len` is the max size of the request.
+    pub async fn splice_read(&self, pipe_fd: RawFd, len: usize) -> io::Result<usize> {
+        let splice = |fd: &OwnedFd| {
+            fcntl::splice(
+                fd.as_raw_fd(),
+                None,
+                pipe_fd,
+                None,
+                len,
+                SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+            )
+            .map_err(io_error_from_nix_error)
+        };
 
-        let fd = self.fd;
+        loop {
+            #[cfg(feature = "async-std-runtime")]
+            let result = self.fd.read_with(|fd| splice(fd)).await;
 
-        spawn_blocking(move || match unistd::read(fd, &mut buf) {
-            Err(err) => Err((buf, io_error_from_nix_error(err))),
-            Ok(n) => Ok((buf, n)),
-        })
-        .await
+            #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+            let result = {
+                let mut guard = self.fd.readable().await?;
+
+                match guard.try_io(|fd| splice(fd.get_ref())) {
+                    Err(_would_block) => continue,
+                    Ok(result) => result,
+                }
+            };
+
+            match result {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
 
-    pub async fn write(
-        &self,
-        buf: Vec<u8>,
-        n: usize,
-    ) -> Result<(Vec<u8>, usize), (Vec<u8>, io::Error)> {
-        let _guard = self.write.lock().await;
+    /// write the buffers as one message by `writev`.
+    pub async fn writev(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let writev = |fd: &OwnedFd| {
+            // IoSlice is ABI compatible with iovec on unix
+            let n = unsafe {
+                libc::writev(
+                    fd.as_raw_fd(),
+                    bufs.as_ptr() as *const libc::iovec,
+                    bufs.len() as c_int,
+                )
+            };
 
-        let fd = self.fd;
+            if n < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(n as usize)
+            }
+        };
 
-        spawn_blocking(move || match unistd::write(fd, &buf[..n]) {
-            Err(err) => Err((buf, io_error_from_nix_error(err))),
-            Ok(n) => Ok((buf, n)),
-        })
-        .await
+        loop {
+            #[cfg(feature = "async-std-runtime")]
+            let result = self.fd.write_with(|fd| writev(fd)).await;
+
+            #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+            let result = {
+                let mut guard = self.fd.writable().await?;
+
+                match guard.try_io(|fd| writev(fd.get_ref())) {
+                    Err(_would_block) => continue,
+                    Ok(result) => result,
+                }
+            };
+
+            match result {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
-}
 
-#[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
-impl AsRawFd for FuseConnection {
-    fn as_raw_fd(&self) -> RawFd {
-        self.fd
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            #[cfg(feature = "async-std-runtime")]
+            let result = self
+                .fd
+                .write_with(|fd| {
+                    unistd::write(fd.as_raw_fd(), buf).map_err(io_error_from_nix_error)
+                })
+                .await;
+
+            #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+            let result = {
+                let mut guard = self.fd.writable().await?;
+
+                match guard.try_io(|fd| {
+                    unistd::write(fd.as_raw_fd(), buf).map_err(io_error_from_nix_error)
+                }) {
+                    Err(_would_block) => continue,
+                    Ok(result) => result,
+                }
+            };
+
+            match result {
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
 }
 
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
-impl Drop for FuseConnection {
-    fn drop(&mut self) {
-        let _ = unistd::close(self.fd);
+impl AsRawFd for FuseConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
     }
 }
@@ -0,0 +1,1365 @@
+//! the request decoder, it turns the raw bytes read from `/dev/fuse` into an [`Operation`].
+//!
+//! The decoder validates every length and offset before slicing, so a malformed request is
+//! turned into a [`DecodeError`] instead of a panic.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::os::unix::ffi::OsStrExt;
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+
+use crate::abi::*;
+use crate::helper::get_first_null_position;
+use crate::SetAttr;
+
+/// the bincode options of the fuse structs, they are little endian and have fixed size, the
+/// trailing names and data are decoded separately.
+pub(crate) fn binary() -> impl Options {
+    bincode::options()
+        .with_little_endian()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+/// the error of decoding a request.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DecodeError {
+    /// the request is shorter than `fuse_in_header`.
+    ShortHeader { len: usize },
+    /// the len of `fuse_in_header` doesn't match the received data.
+    InvalidLength { header_len: u32, received: usize },
+    /// the opcode is unknown.
+    UnknownOpcode(u32),
+    /// the body is shorter than the in struct of the opcode.
+    ShortBody { opcode: fuse_opcode },
+    /// a name in the body is not terminated by null.
+    MissingNull { opcode: fuse_opcode },
+    /// the size in the in struct doesn't match the data in the body.
+    SizeMismatch {
+        opcode: fuse_opcode,
+        expected: usize,
+        actual: usize,
+    },
+    /// a timestamp in the in struct can't be represented.
+    InvalidTimestamp { opcode: fuse_opcode },
+}
+
+impl DecodeError {
+    /// the errno replied to the kernel, unknown opcodes are replied with `ENOSYS`, other errors
+    /// are replied with `EINVAL`.
+    pub fn errno(&self) -> libc::c_int {
+        match self {
+            DecodeError::UnknownOpcode(_) => libc::ENOSYS,
+            _ => libc::EINVAL,
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::ShortHeader { len } => {
+                write!(f, "request len {} is shorter than fuse_in_header", len)
+            }
+
+            DecodeError::InvalidLength {
+                header_len,
+                received,
+            } => write!(
+                f,
+                "fuse_in_header len {} doesn't match received len {}",
+                header_len, received
+            ),
+
+            DecodeError::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+
+            DecodeError::ShortBody { opcode } => write!(f, "{} body is too short", opcode),
+
+            DecodeError::MissingNull { opcode } => write!(f, "{} body has no null", opcode),
+
+            DecodeError::SizeMismatch {
+                opcode,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} body size {} doesn't match expected size {}",
+                opcode, actual, expected
+            ),
+
+            DecodeError::InvalidTimestamp { opcode } => {
+                write!(f, "{} has an invalid timestamp", opcode)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// a decoded request. Names and data borrow the request buffer.
+#[derive(Debug)]
+pub enum Operation<'a> {
+    /// init the filesystem, it is the first request.
+    Init {
+        major: u32,
+        minor: u32,
+        max_readahead: u32,
+        flags: u32,
+    },
+    /// the filesystem is unmounted.
+    Destroy,
+    /// look up `name` in the directory `parent`.
+    Lookup { parent: u64, name: &'a OsStr },
+    /// the kernel forgets `nlookup` references of the inode.
+    Forget { inode: u64, nlookup: u64 },
+    /// get the attributes of the inode.
+    Getattr {
+        inode: u64,
+        fh: Option<u64>,
+        flags: u32,
+    },
+    /// set the attributes of the inode.
+    Setattr {
+        inode: u64,
+        fh: Option<u64>,
+        set_attr: SetAttr,
+    },
+    /// read the target of the symbolic link.
+    Readlink { inode: u64 },
+    /// create the symbolic link `name` to `link` in the directory `parent`.
+    Symlink {
+        parent: u64,
+        name: &'a OsStr,
+        link: &'a OsStr,
+    },
+    /// create the file node `name` in the directory `parent`.
+    Mknod {
+        parent: u64,
+        name: &'a OsStr,
+        mode: u32,
+        rdev: u32,
+        umask: u32,
+    },
+    /// create the directory `name` in the directory `parent`.
+    Mkdir {
+        parent: u64,
+        name: &'a OsStr,
+        mode: u32,
+        umask: u32,
+    },
+    /// remove the file `name` from the directory `parent`.
+    Unlink { parent: u64, name: &'a OsStr },
+    /// remove the directory `name` from the directory `parent`.
+    Rmdir { parent: u64, name: &'a OsStr },
+    /// rename `name` in `parent` to `new_name` in `new_parent`.
+    Rename {
+        parent: u64,
+        name: &'a OsStr,
+        new_parent: u64,
+        new_name: &'a OsStr,
+    },
+    /// create the hard link `new_name` of the inode in the directory `new_parent`.
+    Link {
+        inode: u64,
+        new_parent: u64,
+        new_name: &'a OsStr,
+    },
+    /// open the file.
+    Open { inode: u64, flags: u32 },
+    /// read data of the file.
+    Read {
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+        flags: u32,
+        lock_owner: Option<u64>,
+    },
+    /// write data to the file.
+    ///
+    /// if the request is spliced, the data is kept in the pipe and `data` only has the part in
+    /// the request buffer.
+    Write {
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+        write_flags: u32,
+        flags: u32,
+        lock_owner: Option<u64>,
+        data: &'a [u8],
+    },
+    /// get the filesystem statistics.
+    Statfs { inode: u64 },
+    /// release the opened file.
+    Release {
+        inode: u64,
+        fh: u64,
+        flags: u32,
+        lock_owner: u64,
+        flush: bool,
+    },
+    /// synchronize the file contents.
+    Fsync { inode: u64, fh: u64, datasync: bool },
+    /// set the extended attribute `name`.
+    Setxattr {
+        inode: u64,
+        name: &'a OsStr,
+        value: &'a OsStr,
+        flags: u32,
+    },
+    /// get the extended attribute `name`, if `size` is 0, reply the size of the value.
+    Getxattr {
+        inode: u64,
+        name: &'a OsStr,
+        size: u32,
+    },
+    /// list the extended attribute names, if `size` is 0, reply the size of the list.
+    Listxattr { inode: u64, size: u32 },
+    /// remove the extended attribute `name`.
+    Removexattr { inode: u64, name: &'a OsStr },
+    /// the file is closed.
+    Flush {
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+    },
+    /// open the directory.
+    Opendir { inode: u64, flags: u32 },
+    /// read the directory entries after `offset`.
+    Readdir {
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+    },
+    /// release the opened directory.
+    Releasedir { inode: u64, fh: u64, flags: u32 },
+    /// synchronize the directory contents.
+    Fsyncdir { inode: u64, fh: u64, datasync: bool },
+    #[cfg(feature = "file-lock")]
+    /// test the POSIX file lock.
+    Getlk {
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+    },
+    #[cfg(feature = "file-lock")]
+    /// acquire or release the POSIX file lock, `block` is `true` for `FUSE_SETLKW`.
+    Setlk {
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+        block: bool,
+    },
+    /// check the access permission of the inode.
+    Access { inode: u64, mask: u32 },
+    /// create and open the file `name` in the directory `parent`.
+    Create {
+        parent: u64,
+        name: &'a OsStr,
+        mode: u32,
+        flags: u32,
+        umask: u32,
+    },
+    /// interrupt the request `unique`.
+    Interrupt { unique: u64 },
+    /// map the block index of the file to the device block index.
+    Bmap {
+        inode: u64,
+        blocksize: u32,
+        idx: u64,
+    },
+    /// poll the file for IO readiness.
+    Poll {
+        inode: u64,
+        fh: u64,
+        kh: Option<u64>,
+        flags: u32,
+        events: u32,
+    },
+    /// the reply of a retrieve notification.
+    NotifyReply {
+        inode: u64,
+        offset: u64,
+        data: &'a [u8],
+    },
+    /// the forgotten inodes and their nlookup.
+    BatchForget { forgets: Vec<(u64, u64)> },
+    /// allocate space for the file.
+    Fallocate {
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        length: u64,
+        mode: u32,
+    },
+    /// read the directory entries with attributes after `offset`.
+    Readdirplus {
+        parent: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+        lock_owner: u64,
+    },
+    /// rename with the `RENAME_*` flags.
+    Rename2 {
+        parent: u64,
+        name: &'a OsStr,
+        new_parent: u64,
+        new_name: &'a OsStr,
+        flags: u32,
+    },
+    /// find the next data or hole after `offset`.
+    Lseek {
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        whence: u32,
+    },
+    /// copy a range of data from one file to another.
+    CopyFileRange {
+        inode: u64,
+        fh_in: u64,
+        off_in: u64,
+        inode_out: u64,
+        fh_out: u64,
+        off_out: u64,
+        length: u64,
+        flags: u64,
+    },
+    #[cfg(target_os = "macos")]
+    /// set the volume name (macOS only).
+    Setvolname,
+    #[cfg(target_os = "macos")]
+    /// get the extended times (macOS only).
+    Getxtimes,
+    #[cfg(target_os = "macos")]
+    /// exchange two files (macOS only).
+    Exchange,
+}
+
+impl Operation<'_> {
+    /// the opcode of the operation.
+    pub fn opcode(&self) -> fuse_opcode {
+        match self {
+            Operation::Init { .. } => fuse_opcode::FUSE_INIT,
+            Operation::Destroy => fuse_opcode::FUSE_DESTROY,
+            Operation::Lookup { .. } => fuse_opcode::FUSE_LOOKUP,
+            Operation::Forget { .. } => fuse_opcode::FUSE_FORGET,
+            Operation::Getattr { .. } => fuse_opcode::FUSE_GETATTR,
+            Operation::Setattr { .. } => fuse_opcode::FUSE_SETATTR,
+            Operation::Readlink { .. } => fuse_opcode::FUSE_READLINK,
+            Operation::Symlink { .. } => fuse_opcode::FUSE_SYMLINK,
+            Operation::Mknod { .. } => fuse_opcode::FUSE_MKNOD,
+            Operation::Mkdir { .. } => fuse_opcode::FUSE_MKDIR,
+            Operation::Unlink { .. } => fuse_opcode::FUSE_UNLINK,
+            Operation::Rmdir { .. } => fuse_opcode::FUSE_RMDIR,
+            Operation::Rename { .. } => fuse_opcode::FUSE_RENAME,
+            Operation::Link { .. } => fuse_opcode::FUSE_LINK,
+            Operation::Open { .. } => fuse_opcode::FUSE_OPEN,
+            Operation::Read { .. } => fuse_opcode::FUSE_READ,
+            Operation::Write { .. } => fuse_opcode::FUSE_WRITE,
+            Operation::Statfs { .. } => fuse_opcode::FUSE_STATFS,
+            Operation::Release { .. } => fuse_opcode::FUSE_RELEASE,
+            Operation::Fsync { .. } => fuse_opcode::FUSE_FSYNC,
+            Operation::Setxattr { .. } => fuse_opcode::FUSE_SETXATTR,
+            Operation::Getxattr { .. } => fuse_opcode::FUSE_GETXATTR,
+            Operation::Listxattr { .. } => fuse_opcode::FUSE_LISTXATTR,
+            Operation::Removexattr { .. } => fuse_opcode::FUSE_REMOVEXATTR,
+            Operation::Flush { .. } => fuse_opcode::FUSE_FLUSH,
+            Operation::Opendir { .. } => fuse_opcode::FUSE_OPENDIR,
+            Operation::Readdir { .. } => fuse_opcode::FUSE_READDIR,
+            Operation::Releasedir { .. } => fuse_opcode::FUSE_RELEASEDIR,
+            Operation::Fsyncdir { .. } => fuse_opcode::FUSE_FSYNCDIR,
+            #[cfg(feature = "file-lock")]
+            Operation::Getlk { .. } => fuse_opcode::FUSE_GETLK,
+            #[cfg(feature = "file-lock")]
+            Operation::Setlk { block: false, .. } => fuse_opcode::FUSE_SETLK,
+            #[cfg(feature = "file-lock")]
+            Operation::Setlk { block: true, .. } => fuse_opcode::FUSE_SETLKW,
+            Operation::Access { .. } => fuse_opcode::FUSE_ACCESS,
+            Operation::Create { .. } => fuse_opcode::FUSE_CREATE,
+            Operation::Interrupt { .. } => fuse_opcode::FUSE_INTERRUPT,
+            Operation::Bmap { .. } => fuse_opcode::FUSE_BMAP,
+            Operation::Poll { .. } => fuse_opcode::FUSE_POLL,
+            Operation::NotifyReply { .. } => fuse_opcode::FUSE_NOTIFY_REPLY,
+            Operation::BatchForget { .. } => fuse_opcode::FUSE_BATCH_FORGET,
+            Operation::Fallocate { .. } => fuse_opcode::FUSE_FALLOCATE,
+            Operation::Readdirplus { .. } => fuse_opcode::FUSE_READDIRPLUS,
+            Operation::Rename2 { .. } => fuse_opcode::FUSE_RENAME2,
+            Operation::Lseek { .. } => fuse_opcode::FUSE_LSEEK,
+            Operation::CopyFileRange { .. } => fuse_opcode::FUSE_COPY_FILE_RANGE,
+            #[cfg(target_os = "macos")]
+            Operation::Setvolname => fuse_opcode::FUSE_SETVOLNAME,
+            #[cfg(target_os = "macos")]
+            Operation::Getxtimes => fuse_opcode::FUSE_GETXTIMES,
+            #[cfg(target_os = "macos")]
+            Operation::Exchange => fuse_opcode::FUSE_EXCHANGE,
+        }
+    }
+}
+
+/// the size of the fuse structs which are changed in the negotiated protocol minor version.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct CompatSizes {
+    pub(crate) entry_out: usize,
+    pub(crate) attr_out: usize,
+    pub(crate) getattr_in: usize,
+    pub(crate) mknod_in: usize,
+    pub(crate) create_in: usize,
+    pub(crate) read_in: usize,
+    pub(crate) write_in: usize,
+    pub(crate) init_out: usize,
+}
+
+impl CompatSizes {
+    pub(crate) fn new(minor: u32) -> Self {
+        let (entry_out, attr_out, getattr_in, read_in, write_in) = if minor < 9 {
+            (
+                FUSE_COMPAT_ENTRY_OUT_SIZE,
+                FUSE_COMPAT_ATTR_OUT_SIZE,
+                0,
+                FUSE_COMPAT_READ_IN_SIZE,
+                FUSE_COMPAT_WRITE_IN_SIZE,
+            )
+        } else {
+            (
+                FUSE_ENTRY_OUT_SIZE,
+                FUSE_ATTR_OUT_SIZE,
+                FUSE_GETATTR_IN_SIZE,
+                FUSE_READ_IN_SIZE,
+                FUSE_WRITE_IN_SIZE,
+            )
+        };
+
+        let (mknod_in, create_in) = if minor < 12 {
+            (FUSE_COMPAT_MKNOD_IN_SIZE, FUSE_COMPAT_CREATE_IN_SIZE)
+        } else {
+            (FUSE_MKNOD_IN_SIZE, FUSE_CREATE_IN_SIZE)
+        };
+
+        let init_out = if minor < 5 {
+            FUSE_COMPAT_INIT_OUT_SIZE
+        } else if minor < 23 {
+            FUSE_COMPAT_22_INIT_OUT_SIZE
+        } else {
+            FUSE_INIT_OUT_SIZE
+        };
+
+        Self {
+            entry_out,
+            attr_out,
+            getattr_in,
+            mknod_in,
+            create_in,
+            read_in,
+            write_in,
+            init_out,
+        }
+    }
+}
+
+/// decode the `fuse_in_header` of a request.
+pub(crate) fn decode_header(data: &[u8]) -> Result<fuse_in_header, DecodeError> {
+    if data.len() < FUSE_IN_HEADER_SIZE {
+        return Err(DecodeError::ShortHeader { len: data.len() });
+    }
+
+    binary()
+        .deserialize(&data[..FUSE_IN_HEADER_SIZE])
+        .map_err(|_| DecodeError::ShortHeader { len: data.len() })
+}
+
+/// decode the operation of a request, `data` is the whole request in the buffer, including the
+/// `fuse_in_header`. `payload_len` is the size of the write payload which is kept in the splice
+/// pipe instead of the buffer.
+pub(crate) fn decode_operation<'a>(
+    in_header: &fuse_in_header,
+    data: &'a [u8],
+    payload_len: usize,
+    compat_sizes: CompatSizes,
+) -> Result<Operation<'a>, DecodeError> {
+    let opcode =
+        fuse_opcode::try_from(in_header.opcode).map_err(|err| DecodeError::UnknownOpcode(err.0))?;
+
+    let invalid_length = DecodeError::InvalidLength {
+        header_len: in_header.len,
+        received: data.len() + payload_len,
+    };
+
+    let body_end = match (in_header.len as usize).checked_sub(payload_len) {
+        Some(body_end) if body_end >= FUSE_IN_HEADER_SIZE && body_end <= data.len() => body_end,
+        _ => return Err(invalid_length),
+    };
+
+    let mut body = Body {
+        opcode,
+        data: &data[FUSE_IN_HEADER_SIZE..body_end],
+    };
+
+    let nodeid = in_header.nodeid;
+
+    let operation = match opcode {
+        fuse_opcode::FUSE_INIT => {
+            let init_in_size = if body.data.len() < FUSE_INIT_IN_SIZE {
+                FUSE_COMPAT_INIT_IN_SIZE
+            } else {
+                FUSE_INIT_IN_SIZE
+            };
+
+            let init_in: fuse_init_in = body.take_compat(init_in_size, FUSE_INIT_IN_SIZE)?;
+
+            Operation::Init {
+                major: init_in.major,
+                minor: init_in.minor,
+                max_readahead: init_in.max_readahead,
+                flags: init_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_DESTROY => Operation::Destroy,
+
+        fuse_opcode::FUSE_LOOKUP => Operation::Lookup {
+            parent: nodeid,
+            name: body.name()?,
+        },
+
+        fuse_opcode::FUSE_FORGET => {
+            let forget_in: fuse_forget_in = body.parse()?;
+
+            Operation::Forget {
+                inode: nodeid,
+                nlookup: forget_in.nlookup,
+            }
+        }
+
+        fuse_opcode::FUSE_GETATTR => {
+            let getattr_in: fuse_getattr_in =
+                body.take_compat(compat_sizes.getattr_in, FUSE_GETATTR_IN_SIZE)?;
+
+            let fh = if getattr_in.getattr_flags & FUSE_GETATTR_FH > 0 {
+                Some(getattr_in.fh)
+            } else {
+                None
+            };
+
+            Operation::Getattr {
+                inode: nodeid,
+                fh,
+                flags: getattr_in.getattr_flags,
+            }
+        }
+
+        fuse_opcode::FUSE_SETATTR => {
+            let setattr_in: fuse_setattr_in = body.parse()?;
+
+            let set_attr = SetAttr::try_from(&setattr_in)
+                .map_err(|_| DecodeError::InvalidTimestamp { opcode })?;
+
+            let fh = if setattr_in.valid & FATTR_FH > 0 {
+                Some(setattr_in.fh)
+            } else {
+                None
+            };
+
+            Operation::Setattr {
+                inode: nodeid,
+                fh,
+                set_attr,
+            }
+        }
+
+        fuse_opcode::FUSE_READLINK => Operation::Readlink { inode: nodeid },
+
+        fuse_opcode::FUSE_SYMLINK => Operation::Symlink {
+            parent: nodeid,
+            name: body.name()?,
+            link: body.name()?,
+        },
+
+        fuse_opcode::FUSE_MKNOD => {
+            let mknod_in: fuse_mknod_in =
+                body.take_compat(compat_sizes.mknod_in, FUSE_MKNOD_IN_SIZE)?;
+
+            Operation::Mknod {
+                parent: nodeid,
+                name: body.name()?,
+                mode: mknod_in.mode,
+                rdev: mknod_in.rdev,
+                umask: mknod_in.umask,
+            }
+        }
+
+        fuse_opcode::FUSE_MKDIR => {
+            let mkdir_in: fuse_mkdir_in = body.take(FUSE_MKDIR_IN_SIZE)?;
+
+            Operation::Mkdir {
+                parent: nodeid,
+                name: body.name()?,
+                mode: mkdir_in.mode,
+                umask: mkdir_in.umask,
+            }
+        }
+
+        fuse_opcode::FUSE_UNLINK => Operation::Unlink {
+            parent: nodeid,
+            name: body.name()?,
+        },
+
+        fuse_opcode::FUSE_RMDIR => Operation::Rmdir {
+            parent: nodeid,
+            name: body.name()?,
+        },
+
+        fuse_opcode::FUSE_RENAME => {
+            let rename_in: fuse_rename_in = body.take(FUSE_RENAME_IN_SIZE)?;
+
+            Operation::Rename {
+                parent: nodeid,
+                name: body.name()?,
+                new_parent: rename_in.newdir,
+                new_name: body.name()?,
+            }
+        }
+
+        fuse_opcode::FUSE_LINK => {
+            let link_in: fuse_link_in = body.take(FUSE_LINK_IN_SIZE)?;
+
+            Operation::Link {
+                inode: link_in.oldnodeid,
+                new_parent: nodeid,
+                new_name: body.name()?,
+            }
+        }
+
+        fuse_opcode::FUSE_OPEN => {
+            let open_in: fuse_open_in = body.parse()?;
+
+            Operation::Open {
+                inode: nodeid,
+                flags: open_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_READ => {
+            let read_in: fuse_read_in =
+                body.take_compat(compat_sizes.read_in, FUSE_READ_IN_SIZE)?;
+
+            let lock_owner = if read_in.read_flags & FUSE_READ_LOCKOWNER > 0 {
+                Some(read_in.lock_owner)
+            } else {
+                None
+            };
+
+            Operation::Read {
+                inode: nodeid,
+                fh: read_in.fh,
+                offset: read_in.offset,
+                size: read_in.size,
+                flags: read_in.flags,
+                lock_owner,
+            }
+        }
+
+        fuse_opcode::FUSE_WRITE => {
+            let write_in: fuse_write_in =
+                body.take_compat(compat_sizes.write_in, FUSE_WRITE_IN_SIZE)?;
+
+            let data = body.rest();
+
+            if write_in.size as usize != data.len() + payload_len {
+                return Err(DecodeError::SizeMismatch {
+                    opcode,
+                    expected: write_in.size as usize,
+                    actual: data.len() + payload_len,
+                });
+            }
+
+            let lock_owner = if write_in.write_flags & FUSE_WRITE_LOCKOWNER > 0 {
+                Some(write_in.lock_owner)
+            } else {
+                None
+            };
+
+            Operation::Write {
+                inode: nodeid,
+                fh: write_in.fh,
+                offset: write_in.offset,
+                size: write_in.size,
+                write_flags: write_in.write_flags,
+                flags: write_in.flags,
+                lock_owner,
+                data,
+            }
+        }
+
+        fuse_opcode::FUSE_STATFS => Operation::Statfs { inode: nodeid },
+
+        fuse_opcode::FUSE_RELEASE => {
+            let release_in: fuse_release_in = body.parse()?;
+
+            Operation::Release {
+                inode: nodeid,
+                fh: release_in.fh,
+                flags: release_in.flags,
+                lock_owner: release_in.lock_owner,
+                flush: release_in.release_flags & FUSE_RELEASE_FLUSH > 0,
+            }
+        }
+
+        fuse_opcode::FUSE_FSYNC => {
+            let fsync_in: fuse_fsync_in = body.parse()?;
+
+            Operation::Fsync {
+                inode: nodeid,
+                fh: fsync_in.fh,
+                datasync: fsync_in.fsync_flags & 1 > 0,
+            }
+        }
+
+        fuse_opcode::FUSE_SETXATTR => {
+            let setxattr_in: fuse_setxattr_in = body.take(FUSE_SETXATTR_IN_SIZE)?;
+
+            let name = body.name()?;
+
+            let value = body.rest();
+
+            if setxattr_in.size as usize != value.len() {
+                return Err(DecodeError::SizeMismatch {
+                    opcode,
+                    expected: setxattr_in.size as usize,
+                    actual: value.len(),
+                });
+            }
+
+            Operation::Setxattr {
+                inode: nodeid,
+                name,
+                value: OsStr::from_bytes(value),
+                flags: setxattr_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_GETXATTR => {
+            let getxattr_in: fuse_getxattr_in = body.take(FUSE_GETXATTR_IN_SIZE)?;
+
+            Operation::Getxattr {
+                inode: nodeid,
+                name: body.name()?,
+                size: getxattr_in.size,
+            }
+        }
+
+        fuse_opcode::FUSE_LISTXATTR => {
+            let listxattr_in: fuse_getxattr_in = body.parse()?;
+
+            Operation::Listxattr {
+                inode: nodeid,
+                size: listxattr_in.size,
+            }
+        }
+
+        fuse_opcode::FUSE_REMOVEXATTR => Operation::Removexattr {
+            inode: nodeid,
+            name: body.name()?,
+        },
+
+        fuse_opcode::FUSE_FLUSH => {
+            let flush_in: fuse_flush_in = body.parse()?;
+
+            Operation::Flush {
+                inode: nodeid,
+                fh: flush_in.fh,
+                lock_owner: flush_in.lock_owner,
+            }
+        }
+
+        fuse_opcode::FUSE_OPENDIR => {
+            let open_in: fuse_open_in = body.parse()?;
+
+            Operation::Opendir {
+                inode: nodeid,
+                flags: open_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_READDIR => {
+            let read_in: fuse_read_in =
+                body.take_compat(compat_sizes.read_in, FUSE_READ_IN_SIZE)?;
+
+            Operation::Readdir {
+                inode: nodeid,
+                fh: read_in.fh,
+                offset: read_in.offset,
+                size: read_in.size,
+            }
+        }
+
+        fuse_opcode::FUSE_RELEASEDIR => {
+            let release_in: fuse_release_in = body.parse()?;
+
+            Operation::Releasedir {
+                inode: nodeid,
+                fh: release_in.fh,
+                flags: release_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_FSYNCDIR => {
+            let fsync_in: fuse_fsync_in = body.parse()?;
+
+            Operation::Fsyncdir {
+                inode: nodeid,
+                fh: fsync_in.fh,
+                datasync: fsync_in.fsync_flags & 1 > 0,
+            }
+        }
+
+        #[cfg(feature = "file-lock")]
+        fuse_opcode::FUSE_GETLK => {
+            let getlk_in: fuse_lk_in = body.parse()?;
+
+            Operation::Getlk {
+                inode: nodeid,
+                fh: getlk_in.fh,
+                lock_owner: getlk_in.owner,
+                start: getlk_in.lk.start,
+                end: getlk_in.lk.end,
+                r#type: getlk_in.lk.r#type,
+                pid: getlk_in.lk.pid,
+            }
+        }
+
+        #[cfg(feature = "file-lock")]
+        fuse_opcode::FUSE_SETLK | fuse_opcode::FUSE_SETLKW => {
+            let setlk_in: fuse_lk_in = body.parse()?;
+
+            Operation::Setlk {
+                inode: nodeid,
+                fh: setlk_in.fh,
+                lock_owner: setlk_in.owner,
+                start: setlk_in.lk.start,
+                end: setlk_in.lk.end,
+                r#type: setlk_in.lk.r#type,
+                pid: setlk_in.lk.pid,
+                block: opcode == fuse_opcode::FUSE_SETLKW,
+            }
+        }
+
+        fuse_opcode::FUSE_ACCESS => {
+            let access_in: fuse_access_in = body.parse()?;
+
+            Operation::Access {
+                inode: nodeid,
+                mask: access_in.mask,
+            }
+        }
+
+        fuse_opcode::FUSE_CREATE => {
+            let create_in: fuse_create_in =
+                body.take_compat(compat_sizes.create_in, FUSE_CREATE_IN_SIZE)?;
+
+            Operation::Create {
+                parent: nodeid,
+                name: body.name()?,
+                mode: create_in.mode,
+                flags: create_in.flags,
+                umask: create_in.umask,
+            }
+        }
+
+        fuse_opcode::FUSE_INTERRUPT => {
+            let interrupt_in: fuse_interrupt_in = body.parse()?;
+
+            Operation::Interrupt {
+                unique: interrupt_in.unique,
+            }
+        }
+
+        fuse_opcode::FUSE_BMAP => {
+            let bmap_in: fuse_bmap_in = body.parse()?;
+
+            Operation::Bmap {
+                inode: nodeid,
+                blocksize: bmap_in.blocksize,
+                idx: bmap_in.block,
+            }
+        }
+
+        fuse_opcode::FUSE_POLL => {
+            let poll_in: fuse_poll_in = body.parse()?;
+
+            let kh = if poll_in.flags & FUSE_POLL_SCHEDULE_NOTIFY > 0 {
+                Some(poll_in.kh)
+            } else {
+                None
+            };
+
+            Operation::Poll {
+                inode: nodeid,
+                fh: poll_in.fh,
+                kh,
+                flags: poll_in.flags,
+                events: poll_in.events,
+            }
+        }
+
+        fuse_opcode::FUSE_NOTIFY_REPLY => {
+            let notify_retrieve_in: fuse_notify_retrieve_in =
+                body.take(FUSE_NOTIFY_RETRIEVE_IN_SIZE)?;
+
+            Operation::NotifyReply {
+                inode: nodeid,
+                offset: notify_retrieve_in.offset,
+                data: body.bytes(notify_retrieve_in.size as usize)?,
+            }
+        }
+
+        fuse_opcode::FUSE_BATCH_FORGET => {
+            let batch_forget_in: fuse_batch_forget_in = body.take(FUSE_BATCH_FORGET_IN_SIZE)?;
+
+            let count = body.data.len() / FUSE_FORGET_ONE_SIZE;
+
+            if count != batch_forget_in.count as usize {
+                return Err(DecodeError::SizeMismatch {
+                    opcode,
+                    expected: batch_forget_in.count as usize,
+                    actual: count,
+                });
+            }
+
+            let mut forgets = Vec::with_capacity(count);
+
+            for _ in 0..count {
+                let forget_one: fuse_forget_one = body.take(FUSE_FORGET_ONE_SIZE)?;
+
+                forgets.push((forget_one.nodeid, forget_one.nlookup));
+            }
+
+            Operation::BatchForget { forgets }
+        }
+
+        fuse_opcode::FUSE_FALLOCATE => {
+            let fallocate_in: fuse_fallocate_in = body.parse()?;
+
+            Operation::Fallocate {
+                inode: nodeid,
+                fh: fallocate_in.fh,
+                offset: fallocate_in.offset,
+                length: fallocate_in.length,
+                mode: fallocate_in.mode,
+            }
+        }
+
+        fuse_opcode::FUSE_READDIRPLUS => {
+            let read_in: fuse_read_in =
+                body.take_compat(compat_sizes.read_in, FUSE_READ_IN_SIZE)?;
+
+            Operation::Readdirplus {
+                parent: nodeid,
+                fh: read_in.fh,
+                offset: read_in.offset,
+                size: read_in.size,
+                lock_owner: read_in.lock_owner,
+            }
+        }
+
+        fuse_opcode::FUSE_RENAME2 => {
+            let rename2_in: fuse_rename2_in = body.take(FUSE_RENAME2_IN_SIZE)?;
+
+            Operation::Rename2 {
+                parent: nodeid,
+                name: body.name()?,
+                new_parent: rename2_in.newdir,
+                new_name: body.name()?,
+                flags: rename2_in.flags,
+            }
+        }
+
+        fuse_opcode::FUSE_LSEEK => {
+            let lseek_in: fuse_lseek_in = body.parse()?;
+
+            Operation::Lseek {
+                inode: nodeid,
+                fh: lseek_in.fh,
+                offset: lseek_in.offset,
+                whence: lseek_in.whence,
+            }
+        }
+
+        fuse_opcode::FUSE_COPY_FILE_RANGE => {
+            let copy_file_range_in: fuse_copy_file_range_in = body.parse()?;
+
+            Operation::CopyFileRange {
+                inode: nodeid,
+                fh_in: copy_file_range_in.fh_in,
+                off_in: copy_file_range_in.off_in,
+                inode_out: copy_file_range_in.nodeid_out,
+                fh_out: copy_file_range_in.fh_out,
+                off_out: copy_file_range_in.off_out,
+                length: copy_file_range_in.len,
+                flags: copy_file_range_in.flags,
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        fuse_opcode::FUSE_SETVOLNAME => Operation::Setvolname,
+
+        #[cfg(target_os = "macos")]
+        fuse_opcode::FUSE_GETXTIMES => Operation::Getxtimes,
+
+        #[cfg(target_os = "macos")]
+        fuse_opcode::FUSE_EXCHANGE => Operation::Exchange,
+    };
+
+    Ok(operation)
+}
+
+/// the body of a request, the decoded parts are consumed from the front.
+struct Body<'a> {
+    opcode: fuse_opcode,
+    data: &'a [u8],
+}
+
+impl<'a> Body<'a> {
+    /// deserialize the in struct at the front without consuming it, the in struct is the whole
+    /// body.
+    fn parse<T: DeserializeOwned>(&self) -> Result<T, DecodeError> {
+        binary()
+            .deserialize(self.data)
+            .map_err(|_| DecodeError::ShortBody {
+                opcode: self.opcode,
+            })
+    }
+
+    /// deserialize and consume the in struct of `size` bytes at the front.
+    fn take<T: DeserializeOwned>(&mut self, size: usize) -> Result<T, DecodeError> {
+        let in_struct = self.bytes(size)?;
+
+        binary()
+            .deserialize(in_struct)
+            .map_err(|_| DecodeError::ShortBody {
+                opcode: self.opcode,
+            })
+    }
+
+    /// deserialize and consume the in struct which is `size` bytes in the negotiated protocol,
+    /// old kernels send a prefix of the full struct, it is padded with zero.
+    fn take_compat<T: DeserializeOwned>(
+        &mut self,
+        size: usize,
+        full_size: usize,
+    ) -> Result<T, DecodeError> {
+        let size = size.min(full_size);
+
+        let in_struct = self.bytes(size)?;
+
+        binary()
+            .deserialize(&pad_compat_in(in_struct, full_size))
+            .map_err(|_| DecodeError::ShortBody {
+                opcode: self.opcode,
+            })
+    }
+
+    /// consume a null terminated name.
+    fn name(&mut self) -> Result<&'a OsStr, DecodeError> {
+        let index = get_first_null_position(self.data).ok_or(DecodeError::MissingNull {
+            opcode: self.opcode,
+        })?;
+
+        let name = &self.data[..index];
+
+        self.data = &self.data[index + 1..];
+
+        Ok(OsStr::from_bytes(name))
+    }
+
+    /// consume `len` bytes.
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::ShortBody {
+                opcode: self.opcode,
+            });
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+
+        self.data = rest;
+
+        Ok(bytes)
+    }
+
+    /// consume the rest of the body.
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+}
+
+/// old kernels send a prefix of the fuse in struct, pad it with zero to the full size so it can be
+/// deserialized as the full struct.
+fn pad_compat_in(data: &[u8], full_size: usize) -> Cow<'_, [u8]> {
+    if data.len() >= full_size {
+        return Cow::Borrowed(data);
+    }
+
+    let mut padded = data.to_vec();
+
+    padded.resize(full_size, 0);
+
+    Cow::Owned(padded)
+}
+
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+/// decode a request like the session does, it is the entry of the fuzz targets.
+pub fn fuzz_decode(data: &[u8], proto_minor: u32) {
+    if let Ok(in_header) = decode_header(data) {
+        let _ = decode_operation(&in_header, data, 0, CompatSizes::new(proto_minor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// build a request with a `fuse_in_header` whose len is `len`.
+    fn request(len: u32, opcode: u32, nodeid: u64, body: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(FUSE_IN_HEADER_SIZE + body.len());
+
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&opcode.to_le_bytes());
+        data.extend_from_slice(&7u64.to_le_bytes()); // unique
+        data.extend_from_slice(&nodeid.to_le_bytes());
+        data.extend_from_slice(&[0; 16]); // uid, gid, pid and padding
+        data.extend_from_slice(body);
+
+        data
+    }
+
+    fn write_body(size: u32, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::with_capacity(FUSE_WRITE_IN_SIZE + data.len());
+
+        body.extend_from_slice(&3u64.to_le_bytes()); // fh
+        body.extend_from_slice(&4096u64.to_le_bytes()); // offset
+        body.extend_from_slice(&size.to_le_bytes());
+        body.extend_from_slice(&[0; 20]); // write_flags, lock_owner, flags and padding
+        body.extend_from_slice(data);
+
+        body
+    }
+
+    fn decode(data: &[u8]) -> Result<Operation<'_>, DecodeError> {
+        let in_header = decode_header(data)?;
+
+        decode_operation(
+            &in_header,
+            data,
+            0,
+            CompatSizes::new(FUSE_KERNEL_MINOR_VERSION),
+        )
+    }
+
+    #[test]
+    fn decode_lookup() {
+        let body = b"file\0";
+        let data = request(
+            (FUSE_IN_HEADER_SIZE + body.len()) as u32,
+            fuse_opcode::FUSE_LOOKUP as u32,
+            1,
+            body,
+        );
+
+        match decode(&data).unwrap() {
+            Operation::Lookup { parent, name } => {
+                assert_eq!(parent, 1);
+                assert_eq!(name, "file");
+            }
+            operation => panic!("unexpected operation {:?}", operation),
+        }
+    }
+
+    #[test]
+    fn decode_write_with_spliced_payload() {
+        let body = write_body(8, b"abc");
+        let data = request(
+            (FUSE_IN_HEADER_SIZE + body.len() + 5) as u32,
+            fuse_opcode::FUSE_WRITE as u32,
+            2,
+            &body,
+        );
+        let in_header = decode_header(&data).unwrap();
+
+        match decode_operation(
+            &in_header,
+            &data,
+            5,
+            CompatSizes::new(FUSE_KERNEL_MINOR_VERSION),
+        )
+        .unwrap()
+        {
+            Operation::Write {
+                inode,
+                fh,
+                offset,
+                size,
+                data,
+                ..
+            } => {
+                assert_eq!((inode, fh, offset, size), (2, 3, 4096, 8));
+                assert_eq!(data, b"abc");
+            }
+            operation => panic!("unexpected operation {:?}", operation),
+        }
+    }
+
+    #[test]
+    fn short_header() {
+        let data = request(FUSE_IN_HEADER_SIZE as u32, 1, 1, &[]);
+
+        for len in 0..FUSE_IN_HEADER_SIZE {
+            assert_eq!(
+                decode_header(&data[..len]).unwrap_err(),
+                DecodeError::ShortHeader { len }
+            );
+        }
+    }
+
+    #[test]
+    fn header_len_mismatch() {
+        let body = b"file\0";
+
+        for header_len in [
+            0,
+            FUSE_IN_HEADER_SIZE as u32 - 1,
+            (FUSE_IN_HEADER_SIZE + body.len()) as u32 + 1,
+            u32::MAX,
+        ]
+        .iter()
+        {
+            let data = request(*header_len, fuse_opcode::FUSE_LOOKUP as u32, 1, body);
+
+            assert_eq!(
+                decode(&data).unwrap_err(),
+                DecodeError::InvalidLength {
+                    header_len: *header_len,
+                    received: data.len(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let data = request(FUSE_IN_HEADER_SIZE as u32, 9999, 1, &[]);
+
+        let err = decode(&data).unwrap_err();
+
+        assert_eq!(err, DecodeError::UnknownOpcode(9999));
+        assert_eq!(err.errno(), libc::ENOSYS);
+    }
+
+    #[test]
+    fn short_body() {
+        let body = [0; FUSE_MKDIR_IN_SIZE - 1];
+        let data = request(
+            (FUSE_IN_HEADER_SIZE + body.len()) as u32,
+            fuse_opcode::FUSE_MKDIR as u32,
+            1,
+            &body,
+        );
+
+        let err = decode(&data).unwrap_err();
+
+        assert_eq!(
+            err,
+            DecodeError::ShortBody {
+                opcode: fuse_opcode::FUSE_MKDIR
+            }
+        );
+        assert_eq!(err.errno(), libc::EINVAL);
+    }
+
+    #[test]
+    fn missing_null() {
+        let body = b"file";
+        let data = request(
+            (FUSE_IN_HEADER_SIZE + body.len()) as u32,
+            fuse_opcode::FUSE_LOOKUP as u32,
+            1,
+            body,
+        );
+
+        assert_eq!(
+            decode(&data).unwrap_err(),
+            DecodeError::MissingNull {
+                opcode: fuse_opcode::FUSE_LOOKUP
+            }
+        );
+    }
+
+    #[test]
+    fn write_size_mismatch() {
+        let body = write_body(8, b"abc");
+        let data = request(
+            (FUSE_IN_HEADER_SIZE + body.len()) as u32,
+            fuse_opcode::FUSE_WRITE as u32,
+            2,
+            &body,
+        );
+
+        assert_eq!(
+            decode(&data).unwrap_err(),
+            DecodeError::SizeMismatch {
+                opcode: fuse_opcode::FUSE_WRITE,
+                expected: 8,
+                actual: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_requests_never_panic() {
+        let body = write_body(8, b"abcdefgh");
+        let full = request((FUSE_IN_HEADER_SIZE + body.len()) as u32, 0, 2, &body);
+
+        for opcode in 0..64u32 {
+            let mut data = full.clone();
+            data[4..8].copy_from_slice(&opcode.to_le_bytes());
+
+            for len in 0..=data.len() {
+                // keep the stale header len, and also fix it up so the body decoding is reached.
+                let inconsistent = data[..len].to_vec();
+                let mut consistent = inconsistent.clone();
+                if len >= FUSE_IN_HEADER_SIZE {
+                    consistent[..4].copy_from_slice(&(len as u32).to_le_bytes());
+                }
+
+                for truncated in [inconsistent, consistent].iter() {
+                    let in_header = match decode_header(truncated) {
+                        Err(_) => continue,
+                        Ok(in_header) => in_header,
+                    };
+
+                    for minor in [0, 8, 11, 22, FUSE_KERNEL_MINOR_VERSION].iter() {
+                        let _ =
+                            decode_operation(&in_header, truncated, 0, CompatSizes::new(*minor));
+                    }
+                }
+            }
+        }
+    }
+}
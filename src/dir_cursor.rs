@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use futures_util::stream::{Stream, StreamExt};
+use log::debug;
+
+/// a directory entries stream which is not finished, and the entry which didn't fit in the last
+/// reply.
+pub(crate) struct DirCursor<E> {
+    pending: Option<E>,
+    entries: Pin<Box<dyn Stream<Item = E> + Send>>,
+}
+
+impl<E> DirCursor<E> {
+    pub(crate) fn new(entries: Pin<Box<dyn Stream<Item = E> + Send>>) -> Self {
+        Self {
+            pending: None,
+            entries,
+        }
+    }
+
+    pub(crate) async fn next(&mut self) -> Option<E> {
+        match self.pending.take() {
+            Some(entry) => Some(entry),
+            None => self.entries.next().await,
+        }
+    }
+
+    /// push back the entry, it will be returned by the next [`next`] call.
+    ///
+    /// [`next`]: DirCursor::next
+    pub(crate) fn push_back(&mut self, entry: E) {
+        self.pending.replace(entry);
+    }
+}
+
+/// the directory cursors which can be resumed by the next readdir, the key is the inode, fh and
+/// the offset which the next readdir will start at.
+pub(crate) struct DirCursors<E> {
+    cursors: Mutex<HashMap<(u64, u64, u64), DirCursor<E>>>,
+}
+
+impl<E> DirCursors<E> {
+    pub(crate) fn new() -> Self {
+        Self {
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn take(&self, inode: u64, fh: u64, offset: u64) -> Option<DirCursor<E>> {
+        self.cursors.lock().unwrap().remove(&(inode, fh, offset))
+    }
+
+    /// save the cursor, return the cursor which is replaced by it.
+    ///
+    /// # Notes:
+    ///
+    /// the fh is 0 if the filesystem doesn't set it in opendir, so two directory streams of the
+    /// same directory may stop at the same offset. The entries of the replaced cursor are not
+    /// replied yet, so the kernel doesn't look them up and they needn't be forgotten.
+    pub(crate) fn put(
+        &self,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        cursor: DirCursor<E>,
+    ) -> Option<DirCursor<E>> {
+        let replaced = self
+            .cursors
+            .lock()
+            .unwrap()
+            .insert((inode, fh, offset), cursor);
+
+        if replaced.is_some() {
+            debug!(
+                "directory cursor of inode {} fh {} offset {} is replaced",
+                inode, fh, offset
+            );
+        }
+
+        replaced
+    }
+
+    /// remove the cursors of the released directory.
+    pub(crate) fn release(&self, inode: u64, fh: u64) -> Vec<DirCursor<E>> {
+        let mut cursors = self.cursors.lock().unwrap();
+
+        let keys = cursors
+            .keys()
+            .filter(|&&(cursor_inode, cursor_fh, _)| cursor_inode == inode && cursor_fh == fh)
+            .copied()
+            .collect::<Vec<_>>();
+
+        keys.into_iter()
+            .filter_map(|key| cursors.remove(&key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::FutureExt;
+    use futures_util::stream;
+
+    use super::*;
+
+    fn cursor(entries: Vec<u32>) -> DirCursor<u32> {
+        DirCursor::new(Box::pin(stream::iter(entries)))
+    }
+
+    fn next(cursor: &mut DirCursor<u32>) -> Option<u32> {
+        cursor.next().now_or_never().expect("stream is not ready")
+    }
+
+    #[test]
+    fn push_back_is_returned_first() {
+        let mut cursor = cursor(vec![1, 2, 3]);
+
+        assert_eq!(next(&mut cursor), Some(1));
+        assert_eq!(next(&mut cursor), Some(2));
+
+        cursor.push_back(2);
+
+        assert_eq!(next(&mut cursor), Some(2));
+        assert_eq!(next(&mut cursor), Some(3));
+
+        cursor.push_back(3);
+
+        assert_eq!(next(&mut cursor), Some(3));
+        assert_eq!(next(&mut cursor), None);
+    }
+
+    #[test]
+    fn take_and_put() {
+        let cursors = DirCursors::new();
+
+        assert!(cursors.put(1, 2, 3, cursor(vec![4])).is_none());
+
+        assert!(cursors.take(1, 2, 0).is_none());
+        assert!(cursors.take(1, 0, 3).is_none());
+
+        let mut taken = cursors.take(1, 2, 3).unwrap();
+
+        assert_eq!(next(&mut taken), Some(4));
+        assert!(cursors.take(1, 2, 3).is_none());
+    }
+
+    #[test]
+    fn put_returns_replaced_cursor() {
+        let cursors = DirCursors::new();
+
+        assert!(cursors.put(1, 0, 3, cursor(vec![4])).is_none());
+
+        let mut replaced = cursors.put(1, 0, 3, cursor(vec![5])).unwrap();
+
+        assert_eq!(next(&mut replaced), Some(4));
+        assert_eq!(next(&mut cursors.take(1, 0, 3).unwrap()), Some(5));
+    }
+
+    #[test]
+    fn release_removes_cursors_of_fh() {
+        let cursors = DirCursors::new();
+
+        cursors.put(1, 2, 3, cursor(vec![]));
+        cursors.put(1, 2, 4, cursor(vec![]));
+        cursors.put(1, 3, 3, cursor(vec![]));
+        cursors.put(2, 2, 3, cursor(vec![]));
+
+        assert_eq!(cursors.release(1, 2).len(), 2);
+        assert!(cursors.release(1, 2).is_empty());
+        assert!(cursors.take(1, 3, 3).is_some());
+        assert!(cursors.take(2, 2, 3).is_some());
+    }
+}
@@ -0,0 +1,369 @@
+//! the reply encoder, it turns a [`Response`] into the bytes written to `/dev/fuse`.
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use bincode::Options;
+use serde::Serialize;
+
+use crate::abi::*;
+use crate::decode::{binary, CompatSizes};
+use crate::helper::{get_padding_size, mode_from_kind_and_perm};
+use crate::kernel_config::KernelConfig;
+#[cfg(feature = "file-lock")]
+use crate::reply::ReplyLock;
+use crate::reply::{
+    DirectoryEntry, DirectoryEntryPlus, ReplyAttr, ReplyBmap, ReplyCopyFileRange, ReplyCreated,
+    ReplyEntry, ReplyLSeek, ReplyOpen, ReplyPoll, ReplyStatFs, ReplyWrite, ReplyXAttr,
+};
+use crate::reply_buf::ReplyBuf;
+use crate::Errno;
+
+/// a typed reply of a request.
+///
+/// # Notes:
+///
+/// the response must match the opcode of the request, for example reply a
+/// [`Operation::Lookup`] with [`Response::Entry`] or [`Response::Error`], otherwise the kernel
+/// will reject the reply.
+///
+/// [`Operation::Lookup`]: crate::raw::Operation::Lookup
+pub enum Response {
+    /// reply an error.
+    Error(Errno),
+    /// reply success without data, such as unlink, release and fsync.
+    Empty,
+    /// reply init with the negotiated kernel config.
+    Init(KernelConfig),
+    /// reply lookup, mknod, mkdir, symlink and link.
+    Entry(ReplyEntry),
+    /// reply getattr and setattr.
+    Attr(ReplyAttr),
+    /// reply readlink and read.
+    ///
+    /// # Notes:
+    ///
+    /// the data is sent as is, it must not be longer than the size of the read request.
+    Data(Box<dyn AsRef<[u8]> + Send>),
+    /// reply open and opendir.
+    Open(ReplyOpen),
+    /// reply write.
+    Write(ReplyWrite),
+    /// reply statfs.
+    StatFs(ReplyStatFs),
+    /// reply getxattr and listxattr.
+    XAttr(ReplyXAttr),
+    #[cfg(feature = "file-lock")]
+    /// reply getlk.
+    Lock(ReplyLock),
+    /// reply create.
+    Created(ReplyCreated),
+    /// reply bmap.
+    Bmap(ReplyBmap),
+    /// reply poll.
+    Poll(ReplyPoll),
+    /// reply readdir.
+    ///
+    /// # Notes:
+    ///
+    /// all the entries are encoded, they must fit in the size of the readdir request.
+    Directory(Vec<DirectoryEntry>),
+    /// reply readdirplus.
+    ///
+    /// # Notes:
+    ///
+    /// all the entries are encoded, they must fit in the size of the readdirplus request.
+    DirectoryPlus(Vec<DirectoryEntryPlus>),
+    /// reply lseek.
+    LSeek(ReplyLSeek),
+    /// reply copy_file_range.
+    CopyFileRange(ReplyCopyFileRange),
+}
+
+impl Response {
+    /// encode the reply of the request `unique`, `proto_minor` is the negotiated fuse protocol
+    /// minor version, some reply structs are smaller for old kernels.
+    pub fn encode(self, unique: u64, proto_minor: u32) -> Vec<u8> {
+        self.into_reply_buf(unique, CompatSizes::new(proto_minor))
+            .to_vec()
+    }
+
+    pub(crate) fn into_reply_buf(self, unique: u64, compat_sizes: CompatSizes) -> ReplyBuf {
+        match self {
+            Response::Error(err) => out_header(unique, err.into(), 0).into(),
+
+            Response::Empty => out_header(unique, 0, 0).into(),
+
+            Response::Init(kernel_config) => {
+                let init_out = fuse_init_out {
+                    major: FUSE_KERNEL_VERSION,
+                    minor: kernel_config.minor,
+                    max_readahead: kernel_config.max_readahead,
+                    flags: kernel_config.want,
+                    max_background: kernel_config.max_background,
+                    congestion_threshold: kernel_config.congestion_threshold,
+                    max_write: kernel_config.max_write,
+                    time_gran: kernel_config.time_gran,
+                    max_pages: kernel_config.max_pages,
+                    map_alignment: DEFAULT_MAP_ALIGNMENT,
+                    unused: [0; 8],
+                };
+
+                out_struct(unique, &init_out, compat_sizes.init_out)
+            }
+
+            Response::Entry(entry) => {
+                let entry_out: fuse_entry_out = entry.into();
+
+                out_struct(unique, &entry_out, compat_sizes.entry_out)
+                    .with_lookups(entry_lookups(&entry_out))
+            }
+
+            Response::Attr(attr) => {
+                let attr_out: fuse_attr_out = attr.into();
+
+                out_struct(unique, &attr_out, compat_sizes.attr_out)
+            }
+
+            Response::Data(data) => {
+                let len = data.as_ref().as_ref().len();
+
+                ReplyBuf::with_payload(out_header(unique, 0, len), data, len)
+            }
+
+            Response::Open(open) => {
+                let open_out: fuse_open_out = open.into();
+
+                out_struct(unique, &open_out, FUSE_OPEN_OUT_SIZE)
+            }
+
+            Response::Write(write) => {
+                let write_out: fuse_write_out = write.into();
+
+                out_struct(unique, &write_out, FUSE_WRITE_OUT_SIZE)
+            }
+
+            Response::StatFs(statfs) => {
+                let statfs_out: fuse_statfs_out = statfs.into();
+
+                out_struct(unique, &statfs_out, FUSE_STATFS_OUT_SIZE)
+            }
+
+            Response::XAttr(ReplyXAttr::Size(size)) => {
+                let getxattr_out = fuse_getxattr_out { size, padding: 0 };
+
+                out_struct(unique, &getxattr_out, FUSE_GETXATTR_OUT_SIZE)
+            }
+
+            Response::XAttr(ReplyXAttr::Data(data)) => {
+                let len = data.len();
+
+                ReplyBuf::with_payload(out_header(unique, 0, len), Box::new(data), len)
+            }
+
+            #[cfg(feature = "file-lock")]
+            Response::Lock(lock) => {
+                let lk_out: fuse_lk_out = lock.into();
+
+                out_struct(unique, &lk_out, FUSE_LK_OUT_SIZE)
+            }
+
+            Response::Created(created) => {
+                let (entry_out, open_out): (fuse_entry_out, fuse_open_out) = created.into();
+
+                let mut data = out_header(unique, 0, compat_sizes.entry_out + FUSE_OPEN_OUT_SIZE);
+
+                binary()
+                    .serialize_into(&mut data, &entry_out)
+                    .expect("won't happened");
+
+                data.truncate(FUSE_OUT_HEADER_SIZE + compat_sizes.entry_out);
+
+                binary()
+                    .serialize_into(&mut data, &open_out)
+                    .expect("won't happened");
+
+                ReplyBuf::from(data).with_lookups(entry_lookups(&entry_out))
+            }
+
+            Response::Bmap(bmap) => {
+                let bmap_out: fuse_bmap_out = bmap.into();
+
+                out_struct(unique, &bmap_out, FUSE_BMAP_OUT_SIZE)
+            }
+
+            Response::Poll(poll) => {
+                let poll_out: fuse_poll_out = poll.into();
+
+                out_struct(unique, &poll_out, FUSE_POLL_OUT_SIZE)
+            }
+
+            Response::Directory(entries) => {
+                let mut entry_data = Vec::new();
+
+                for entry in &entries {
+                    encode_dirent(&mut entry_data, entry);
+                }
+
+                out_entries(unique, entry_data)
+            }
+
+            Response::DirectoryPlus(entries) => {
+                let mut entry_data = Vec::new();
+
+                for entry in &entries {
+                    encode_direntplus(&mut entry_data, entry);
+                }
+
+                let lookups = entries.iter().filter_map(direntplus_lookup).collect();
+
+                out_entries(unique, entry_data).with_lookups(lookups)
+            }
+
+            Response::LSeek(lseek) => {
+                let lseek_out: fuse_lseek_out = lseek.into();
+
+                out_struct(unique, &lseek_out, FUSE_LSEEK_OUT_SIZE)
+            }
+
+            Response::CopyFileRange(copied) => {
+                let write_out: fuse_write_out = copied.into();
+
+                out_struct(unique, &write_out, FUSE_WRITE_OUT_SIZE)
+            }
+        }
+    }
+}
+
+/// serialize the `fuse_out_header` of a reply which has `len` bytes after the header.
+pub(crate) fn out_header(unique: u64, error: i32, len: usize) -> Vec<u8> {
+    let out_header = fuse_out_header {
+        len: (FUSE_OUT_HEADER_SIZE + len) as u32,
+        error,
+        unique,
+    };
+
+    let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + len);
+
+    binary()
+        .serialize_into(&mut data, &out_header)
+        .expect("won't happened");
+
+    data
+}
+
+/// serialize a reply with an out struct, only the first `size` bytes of the out struct are sent
+/// for old kernels.
+fn out_struct<T: Serialize>(unique: u64, out: &T, size: usize) -> ReplyBuf {
+    let mut data = out_header(unique, 0, size);
+
+    binary()
+        .serialize_into(&mut data, out)
+        .expect("won't happened");
+
+    data.truncate(FUSE_OUT_HEADER_SIZE + size);
+
+    data.into()
+}
+
+/// the reply of readdir or readdirplus with the encoded entries.
+pub(crate) fn out_entries(unique: u64, entry_data: Vec<u8>) -> ReplyBuf {
+    let len = entry_data.len();
+
+    ReplyBuf::with_payload(out_header(unique, 0, len), Box::new(entry_data), len)
+}
+
+/// the encoded size of a readdir entry, including the padding.
+pub(crate) fn dirent_size(entry: &DirectoryEntry) -> usize {
+    let dir_entry_size = FUSE_DIRENT_SIZE + entry.name.len();
+
+    dir_entry_size + get_padding_size(dir_entry_size)
+}
+
+/// the encoded size of a readdirplus entry whose name is `name`, including the padding.
+pub(crate) fn direntplus_size(name: &OsStr) -> usize {
+    let dir_entry_size = FUSE_DIRENTPLUS_SIZE + name.len();
+
+    dir_entry_size + get_padding_size(dir_entry_size)
+}
+
+/// append a readdir entry to `data`.
+pub(crate) fn encode_dirent(data: &mut Vec<u8>, entry: &DirectoryEntry) {
+    let name = &entry.name;
+
+    let dir_entry = fuse_dirent {
+        ino: entry.inode,
+        off: entry.index,
+        namelen: name.len() as u32,
+        // learn from fuse-rs and golang bazil.org fuse DirentType
+        r#type: mode_from_kind_and_perm(entry.kind, 0) >> 12,
+    };
+
+    binary()
+        .serialize_into(&mut *data, &dir_entry)
+        .expect("won't happened");
+
+    data.extend_from_slice(name.as_bytes());
+
+    // padding
+    let padding_size = get_padding_size(FUSE_DIRENT_SIZE + name.len());
+
+    data.resize(data.len() + padding_size, 0);
+}
+
+/// the inode the kernel looks up when it takes the entry, the zero nodeid is a negative entry
+/// which is not looked up.
+fn entry_lookups(entry_out: &fuse_entry_out) -> Vec<u64> {
+    if entry_out.nodeid == 0 {
+        vec![]
+    } else {
+        vec![entry_out.nodeid]
+    }
+}
+
+/// the inode the kernel looks up when it takes the readdirplus entry, the kernel doesn't look up
+/// `.`, `..` and the entry whose inode is 0.
+pub(crate) fn direntplus_lookup(entry: &DirectoryEntryPlus) -> Option<u64> {
+    if entry.attr.ino == 0 || entry.name == "." || entry.name == ".." {
+        None
+    } else {
+        Some(entry.attr.ino)
+    }
+}
+
+/// append a readdirplus entry to `data`.
+pub(crate) fn encode_direntplus(data: &mut Vec<u8>, entry: &DirectoryEntryPlus) {
+    let name = &entry.name;
+
+    let attr = entry.attr;
+
+    let dir_entry = fuse_direntplus {
+        entry_out: fuse_entry_out {
+            nodeid: attr.ino,
+            generation: entry.generation,
+            entry_valid: entry.entry_ttl.as_secs(),
+            attr_valid: entry.attr_ttl.as_secs(),
+            entry_valid_nsec: entry.entry_ttl.subsec_nanos(),
+            attr_valid_nsec: entry.attr_ttl.subsec_nanos(),
+            attr: attr.into(),
+        },
+        dirent: fuse_dirent {
+            ino: entry.inode,
+            off: entry.index,
+            namelen: name.len() as u32,
+            // learn from fuse-rs and golang bazil.org fuse DirentType
+            r#type: mode_from_kind_and_perm(entry.kind, 0) >> 12,
+        },
+    };
+
+    binary()
+        .serialize_into(&mut *data, &dir_entry)
+        .expect("won't happened");
+
+    data.extend_from_slice(name.as_bytes());
+
+    // padding
+    let padding_size = get_padding_size(FUSE_DIRENTPLUS_SIZE + name.len());
+
+    data.resize(data.len() + padding_size, 0);
+}
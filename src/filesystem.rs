@@ -1,10 +1,13 @@
 use std::ffi::OsStr;
 
 use async_trait::async_trait;
+use bytes::Bytes;
 
+use crate::helper::interruptible_by_default;
+use crate::kernel_config::KernelConfig;
 use crate::reply::*;
 use crate::request::Request;
-use crate::{Result, SetAttr};
+use crate::{Opcode, Result, SetAttr, WriteData};
 
 #[async_trait]
 /// Filesystem trait.
@@ -14,25 +17,53 @@ use crate::{Result, SetAttr};
 /// this trait is defined with async_trait, you can use
 /// [`async_trait`](https://docs.rs/async-trait) to implement it, or just implement it directly.
 pub trait Filesystem {
-    /// initialize filesystem. Called before any other filesystem method.
-    async fn init(&self, req: Request) -> Result<()>;
+    /// initialize filesystem. Called before any other filesystem method. The `config` contains
+    /// the capabilities offered by the kernel and the proposed limits, filesystem can change them
+    /// before they are replied to the kernel.
+    async fn init(&self, req: Request, config: &mut KernelConfig) -> Result<()>;
 
     /// clean up filesystem. Called on filesystem exit.
     async fn destroy(&self, req: Request);
 
-    /// look up a directory entry by name and get its attributes.
+    /// look up a directory entry by name and get its attributes. Don't count the lookup here,
+    /// it is counted by [`remember`] when the entry is replied.
+    ///
+    /// [`remember`]: Filesystem::remember
     async fn lookup(&self, _req: Request, _parent: u64, _name: &OsStr) -> Result<ReplyEntry> {
         Err(libc::ENOSYS.into())
     }
 
     /// forget an inode. The nlookup parameter indicates the number of lookups previously
     /// performed on this inode. If the filesystem implements inode lifetimes, it is recommended
-    /// that inodes acquire a single reference on each lookup, and lose nlookup references on each
-    /// forget. The filesystem may ignore forget calls, if the inodes don't need to have a limited
-    /// lifetime. On unmount it is not guaranteed, that all referenced inodes will receive a forget
-    /// message.
+    /// that inodes acquire a single reference on each [`remember`], and lose nlookup references
+    /// on each forget. The filesystem may ignore forget calls, if the inodes don't need to have a
+    /// limited lifetime. On unmount it is not guaranteed, that all referenced inodes will receive
+    /// a forget message.
+    ///
+    /// [`remember`]: Filesystem::remember
     async fn forget(&self, _req: Request, _inode: u64, _nlookup: u64) {}
 
+    /// the kernel looks up the inode once. It is called by the session when an entry of
+    /// [`lookup`], [`mknod`], [`mkdir`], [`symlink`], [`link`], [`create`] or [`readdirplus`] is
+    /// replied, except the entries whose inode is 0 and the `.` and `..` entries.
+    ///
+    /// # Notes:
+    ///
+    /// it is called just before the reply is written to the kernel, if the write fails, the
+    /// inode is forgotten by [`forget`] with nlookup 1. Count the lookups here instead of in
+    /// [`lookup`], an entry which is not replied, such as the request is interrupted or the
+    /// readdirplus entry doesn't fit in the reply, is never remembered.
+    ///
+    /// [`lookup`]: Filesystem::lookup
+    /// [`mknod`]: Filesystem::mknod
+    /// [`mkdir`]: Filesystem::mkdir
+    /// [`symlink`]: Filesystem::symlink
+    /// [`link`]: Filesystem::link
+    /// [`create`]: Filesystem::create
+    /// [`readdirplus`]: Filesystem::readdirplus
+    /// [`forget`]: Filesystem::forget
+    fn remember(&self, _inode: u64) {}
+
     /// get file attributes. If `fh` is None, means `fh` is not set.
     async fn getattr(
         &self,
@@ -168,13 +199,20 @@ pub trait Filesystem {
     /// return value of the write system call will reflect the return value of this operation. `fh`
     /// will contain the value set by the open method, or will be undefined if the open method
     /// didn't set any value.
+    ///
+    /// # Notes:
+    ///
+    /// if [`MountOptions::splice_read`] is enabled, `data` may be kept in a pipe, use
+    /// [`WriteData::splice_to`] to write it to a file without copying through userspace.
+    ///
+    /// [`MountOptions::splice_read`]: crate::MountOptions::splice_read
     async fn write(
         &self,
         _req: Request,
         _inode: u64,
         _fh: u64,
         _offset: u64,
-        _data: &[u8],
+        _data: WriteData,
         _flags: u32,
     ) -> Result<ReplyWrite> {
         Err(libc::ENOSYS.into())
@@ -290,7 +328,14 @@ pub trait Filesystem {
     /// contain the value set by the [`opendir`] method, or will be undefined if the [`opendir`]
     /// method didn't set any value.
     ///
+    /// # Notes:
+    ///
+    /// the entries which don't fit in the reply are not dropped, the session keeps the stream and
+    /// resumes it when the next readdir starts at the offset of the last replied entry, so this
+    /// method is only called again if the offset doesn't match, or after [`releasedir`].
+    ///
     /// [`opendir`]: Filesystem::opendir
+    /// [`releasedir`]: Filesystem::releasedir
     async fn readdir(
         &self,
         _req: Request,
@@ -398,10 +443,33 @@ pub trait Filesystem {
 
     /// handle interrupt. When a operation is interrupted, an interrupt request will send to fuse
     /// server with the unique id of the operation.
+    ///
+    /// # Notes:
+    ///
+    /// only the operations which are not [`interruptible`] will be passed to this method, the
+    /// interruptible ones are cancelled by the session.
+    ///
+    /// [`interruptible`]: Filesystem::interruptible
     async fn interrupt(&self, _req: Request, _unique: u64) -> Result<()> {
         Err(libc::ENOSYS.into())
     }
 
+    /// whether the operation can be cancelled by the session when it is interrupted. If return
+    /// `true`, the running handler of the interrupted operation will be dropped and `EINTR` will be
+    /// replied; otherwise the interrupt will be passed to [`interrupt`]. Default is `true` for
+    /// read, readdir, getattr, getlk and setlkw only.
+    ///
+    /// # Notes:
+    ///
+    /// don't return `true` for the operations which have side effects, such as write, create or
+    /// lookup, the handler may be dropped after the side effect is done, and the kernel will never
+    /// know it.
+    ///
+    /// [`interrupt`]: Filesystem::interrupt
+    fn interruptible(&self, opcode: Opcode) -> bool {
+        interruptible_by_default(opcode)
+    }
+
     /// map block index within file to block index within device.
     ///
     /// # Notes:
@@ -450,15 +518,16 @@ pub trait Filesystem {
         _req: Request,
         _inode: u64,
         _offset: u64,
-        _data: Vec<u8>,
+        _data: Bytes,
     ) -> Result<()> {
         Err(libc::ENOSYS.into())
     }
 
-    /// forget more than one inode. This is a batch version [`forget`]
+    /// forget more than one inode. This is a batch version [`forget`], every item of `forgets` is
+    /// an inode and its nlookup.
     ///
     /// [`forget`]: Filesystem::forget
-    async fn batch_forget(&self, _req: Request, _inodes: &[u64]) {}
+    async fn batch_forget(&self, _req: Request, _forgets: &[(u64, u64)]) {}
 
     /// allocate space for an open file. This function ensures that required space is allocated for
     /// specified file.
@@ -481,6 +550,10 @@ pub trait Filesystem {
     /// read directory entries, but with their attribute, like [`readdir`] + [`lookup`] at the same
     /// time.
     ///
+    /// # Notes:
+    ///
+    /// the stream is kept and resumed like [`readdir`].
+    ///
     /// [`readdir`]: Filesystem::readdir
     /// [`lookup`]: Filesystem::lookup
     async fn readdirplus(
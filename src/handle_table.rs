@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::warn;
+
+struct Handles<T> {
+    handles: HashMap<u64, Arc<T>>,
+    free: Vec<u64>,
+    next_fh: u64,
+}
+
+/// the table of file handles and their values.
+///
+/// [`insert`] a value in open, opendir or create, and reply the returned fh. Get the value by
+/// [`get`] with the fh of the request, and [`remove`] it in release or releasedir, the released
+/// fh will be reused.
+///
+/// # Notes:
+///
+/// the fh starts from 1, 0 is never allocated.
+///
+/// [`insert`]: HandleTable::insert
+/// [`get`]: HandleTable::get
+/// [`remove`]: HandleTable::remove
+pub struct HandleTable<T> {
+    handles: Mutex<Handles<T>>,
+}
+
+impl<T> HandleTable<T> {
+    /// create an empty handle table.
+    pub fn new() -> Self {
+        Self {
+            handles: Mutex::new(Handles {
+                handles: HashMap::new(),
+                free: vec![],
+                next_fh: 1,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Handles<T>> {
+        self.handles.lock().expect("handle table lock poisoned")
+    }
+
+    /// insert a value and allocate its fh.
+    pub fn insert(&self, value: T) -> u64 {
+        let mut handles = self.lock();
+
+        let fh = match handles.free.pop() {
+            Some(fh) => fh,
+            None => {
+                let fh = handles.next_fh;
+                handles.next_fh += 1;
+
+                fh
+            }
+        };
+
+        handles.handles.insert(fh, Arc::new(value));
+
+        fh
+    }
+
+    /// get the value of the fh.
+    pub fn get(&self, fh: u64) -> Option<Arc<T>> {
+        self.lock().handles.get(&fh).cloned()
+    }
+
+    /// remove the fh, the fh can be allocated again.
+    pub fn remove(&self, fh: u64) -> Option<Arc<T>> {
+        let mut handles = self.lock();
+
+        let value = handles.handles.remove(&fh)?;
+
+        handles.free.push(fh);
+
+        Some(value)
+    }
+
+    /// the number of the allocated fh.
+    pub fn len(&self) -> usize {
+        self.lock().handles.len()
+    }
+
+    /// return `true` if there is no allocated fh.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the allocated fh, the fh not removed at destroy are leaked.
+    pub fn handles(&self) -> Vec<u64> {
+        let mut handles = self.lock().handles.keys().copied().collect::<Vec<_>>();
+
+        handles.sort_unstable();
+
+        handles
+    }
+}
+
+impl<T> Default for HandleTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for HandleTable<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandleTable")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// the fh replied to the kernel and not released yet, the session reports them at destroy.
+#[derive(Debug, Default)]
+pub(crate) struct OpenHandles {
+    /// the key is the fh and whether it is a directory, the value is the open count because the
+    /// filesystem may reply the same fh many times.
+    handles: Mutex<HashMap<(u64, bool), u64>>,
+}
+
+impl OpenHandles {
+    pub(crate) fn opened(&self, fh: u64, dir: bool) {
+        *self.handles.lock().unwrap().entry((fh, dir)).or_insert(0) += 1;
+    }
+
+    pub(crate) fn released(&self, fh: u64, dir: bool) {
+        let mut handles = self.handles.lock().unwrap();
+
+        if let Some(count) = handles.get_mut(&(fh, dir)) {
+            *count -= 1;
+
+            if *count == 0 {
+                handles.remove(&(fh, dir));
+            }
+        }
+    }
+
+    /// warn the fh which are not released.
+    pub(crate) fn report_leaks(&self) {
+        let handles = self.handles.lock().unwrap();
+
+        let mut files = vec![];
+        let mut dirs = vec![];
+
+        for &(fh, dir) in handles.keys() {
+            if dir {
+                dirs.push(fh);
+            } else {
+                files.push(fh);
+            }
+        }
+
+        files.sort_unstable();
+        dirs.sort_unstable();
+
+        if !files.is_empty() {
+            warn!("file handles {:?} are not released at destroy", files);
+        }
+
+        if !dirs.is_empty() {
+            warn!("directory handles {:?} are not released at destroy", dirs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_allocates_from_one() {
+        let table = HandleTable::new();
+
+        assert_eq!(table.insert("a"), 1);
+        assert_eq!(table.insert("b"), 2);
+        assert_eq!(*table.get(1).unwrap(), "a");
+        assert_eq!(*table.get(2).unwrap(), "b");
+        assert!(table.get(0).is_none());
+        assert_eq!(table.handles(), vec![1, 2]);
+    }
+
+    #[test]
+    fn removed_fh_is_reused() {
+        let table = HandleTable::new();
+
+        let a = table.insert("a");
+        let b = table.insert("b");
+
+        assert_eq!(*table.remove(a).unwrap(), "a");
+        assert!(table.get(a).is_none());
+        assert_eq!(table.len(), 1);
+
+        assert_eq!(table.insert("c"), a);
+        assert_eq!(*table.get(a).unwrap(), "c");
+        assert_eq!(table.insert("d"), 3);
+        assert_eq!(table.handles(), vec![a, b, 3]);
+    }
+
+    #[test]
+    fn remove_unknown_fh_is_not_reused() {
+        let table = HandleTable::new();
+
+        let a = table.insert("a");
+
+        assert!(table.remove(5).is_none());
+        assert_eq!(*table.remove(a).unwrap(), "a");
+        assert!(table.remove(a).is_none());
+
+        assert_eq!(table.insert("b"), a);
+        assert_eq!(table.insert("c"), 2);
+        assert!(!table.is_empty());
+    }
+
+    #[test]
+    fn open_handles_count() {
+        let open_handles = OpenHandles::default();
+
+        open_handles.opened(1, false);
+        open_handles.opened(1, false);
+        open_handles.opened(1, true);
+
+        open_handles.released(1, false);
+        open_handles.released(2, false);
+
+        {
+            let handles = open_handles.handles.lock().unwrap();
+
+            assert_eq!(handles.get(&(1, false)), Some(&1));
+            assert_eq!(handles.get(&(1, true)), Some(&1));
+        }
+
+        open_handles.released(1, false);
+        open_handles.released(1, true);
+
+        assert!(open_handles.handles.lock().unwrap().is_empty());
+    }
+}
@@ -4,7 +4,7 @@ use std::mem;
 
 use nix::sys::stat::mode_t;
 
-use crate::FileType;
+use crate::{FileType, Opcode};
 
 pub trait Apply: Sized {
     fn apply<F>(mut self, f: F) -> Self
@@ -53,3 +53,15 @@ pub fn io_error_from_nix_error(err: nix::Error) -> io::Error {
         }
     }
 }
+
+/// the operations which are cancelled by the session when they are interrupted by default. They
+/// are read-only or waiting for a lock, so cancel them and reply `EINTR` won't lose any side
+/// effect.
+pub fn interruptible_by_default(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::FUSE_READ | Opcode::FUSE_READDIR | Opcode::FUSE_GETATTR => true,
+        #[cfg(feature = "file-lock")]
+        Opcode::FUSE_GETLK | Opcode::FUSE_SETLKW => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::warn;
+
+/// the inode of the root directory.
+const ROOT_INODE: u64 = 1;
+
+/// the lookup count of an inode, it is shared by the [`InodeTable`] and the path adapter.
+#[derive(Debug)]
+pub(crate) struct LookupCount(u64);
+
+impl LookupCount {
+    pub(crate) fn new(nlookup: u64) -> Self {
+        Self(nlookup)
+    }
+
+    /// the kernel looks up the inode once.
+    pub(crate) fn remember(&mut self) {
+        self.0 += 1;
+    }
+
+    /// decrease the lookup count by `nlookup`, return `true` if it reaches zero.
+    pub(crate) fn forget(&mut self, inode: u64, nlookup: u64, debug: bool) -> bool {
+        if nlookup > self.0 && debug {
+            warn!(
+                "forget inode {} nlookup {} but only {} lookups are remembered",
+                inode, nlookup, self.0
+            );
+        }
+
+        self.0 = self.0.saturating_sub(nlookup);
+
+        self.is_zero()
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+struct Inode<T> {
+    value: Arc<T>,
+    nlookup: LookupCount,
+}
+
+struct Inodes<T> {
+    inodes: HashMap<u64, Inode<T>>,
+    next_inode: u64,
+}
+
+/// the table of inodes and their values, it counts the lookups of the kernel.
+///
+/// Every entry replied to the kernel by lookup, create, mknod, mkdir, symlink, link or
+/// readdirplus increases the lookup count of its inode, the session calls
+/// [`Filesystem::remember`] when these replies are sent, call [`remember`] in it. Call [`forget`]
+/// in [`Filesystem::forget`] and [`batch_forget`] in [`Filesystem::batch_forget`], the inode is
+/// removed when its lookup count reaches zero.
+///
+/// # Notes:
+///
+/// in debug mode, forgetting an unknown inode or more lookups than remembered will be warned.
+/// The debug mode is enabled by default in debug build.
+///
+/// [`remember`]: InodeTable::remember
+/// [`forget`]: InodeTable::forget
+/// [`batch_forget`]: InodeTable::batch_forget
+/// [`Filesystem::remember`]: crate::Filesystem::remember
+/// [`Filesystem::forget`]: crate::Filesystem::forget
+/// [`Filesystem::batch_forget`]: crate::Filesystem::batch_forget
+pub struct InodeTable<T> {
+    inodes: Mutex<Inodes<T>>,
+    debug: bool,
+}
+
+impl<T> InodeTable<T> {
+    /// create an empty inode table, the inodes are allocated from 2.
+    pub fn new() -> Self {
+        Self {
+            inodes: Mutex::new(Inodes {
+                inodes: HashMap::new(),
+                next_inode: ROOT_INODE + 1,
+            }),
+            debug: cfg!(debug_assertions),
+        }
+    }
+
+    /// create an inode table with the root directory, the root inode is never removed.
+    pub fn with_root(root: T) -> Self {
+        let table = Self::new();
+
+        table.lock().inodes.insert(
+            ROOT_INODE,
+            Inode {
+                value: Arc::new(root),
+                nlookup: LookupCount::new(1),
+            },
+        );
+
+        table
+    }
+
+    /// enable or disable the debug mode.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inodes<T>> {
+        self.inodes.lock().expect("inode table lock poisoned")
+    }
+
+    /// insert a value and allocate its inode, the lookup count of the new inode is zero, it is
+    /// remembered when it is replied to the kernel.
+    ///
+    /// # Notes:
+    ///
+    /// the inode is only removed by [`forget`] after it is remembered, or by [`remove`]. If the
+    /// entry of the inode is not replied, such as the handler returns an error after the insert,
+    /// call [`remove`], otherwise the inode stays in the table forever. Insert the value after
+    /// the last `.await` of the handler, so an interrupted handler or a handler whose deadline
+    /// expires doesn't leave the inode behind.
+    ///
+    /// [`forget`]: InodeTable::forget
+    /// [`remove`]: InodeTable::remove
+    pub fn insert(&self, value: T) -> u64 {
+        let mut inodes = self.lock();
+
+        let inode = inodes.next_inode;
+        inodes.next_inode += 1;
+
+        inodes.inodes.insert(
+            inode,
+            Inode {
+                value: Arc::new(value),
+                nlookup: LookupCount::new(0),
+            },
+        );
+
+        inode
+    }
+
+    /// get the value of the inode.
+    pub fn get(&self, inode: u64) -> Option<Arc<T>> {
+        self.lock()
+            .inodes
+            .get(&inode)
+            .map(|inode| inode.value.clone())
+    }
+
+    /// remove the inode whatever its lookup count is.
+    pub fn remove(&self, inode: u64) -> Option<Arc<T>> {
+        self.lock().inodes.remove(&inode).map(|inode| inode.value)
+    }
+
+    /// the number of the inodes.
+    pub fn len(&self) -> usize {
+        self.lock().inodes.len()
+    }
+
+    /// return `true` if there is no inode.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// increase the lookup count of the inode, return `false` if the inode is not in the table.
+    pub fn remember(&self, inode: u64) -> bool {
+        match self.lock().inodes.get_mut(&inode) {
+            None => {
+                if self.debug {
+                    warn!("remember unknown inode {}", inode);
+                }
+
+                false
+            }
+
+            Some(inode) => {
+                inode.nlookup.remember();
+
+                true
+            }
+        }
+    }
+
+    /// decrease the lookup count of the inode by `nlookup`, return the value if the inode is
+    /// removed.
+    pub fn forget(&self, inode: u64, nlookup: u64) -> Option<Arc<T>> {
+        self.forget_locked(&mut self.lock(), inode, nlookup)
+    }
+
+    /// forget more than one inode, every item of `forgets` is an inode and its nlookup. Return
+    /// the values of the removed inodes.
+    pub fn batch_forget(&self, forgets: &[(u64, u64)]) -> Vec<Arc<T>> {
+        let mut inodes = self.lock();
+
+        forgets
+            .iter()
+            .filter_map(|&(inode, nlookup)| self.forget_locked(&mut inodes, inode, nlookup))
+            .collect()
+    }
+
+    fn forget_locked(&self, inodes: &mut Inodes<T>, inode: u64, nlookup: u64) -> Option<Arc<T>> {
+        let entry = match inodes.inodes.get_mut(&inode) {
+            None => {
+                if self.debug {
+                    warn!("forget unknown inode {} nlookup {}", inode, nlookup);
+                }
+
+                return None;
+            }
+
+            Some(entry) => entry,
+        };
+
+        if !entry.nlookup.forget(inode, nlookup, self.debug) || inode == ROOT_INODE {
+            return None;
+        }
+
+        inodes.inodes.remove(&inode).map(|inode| inode.value)
+    }
+}
+
+impl<T> Default for InodeTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for InodeTable<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InodeTable")
+            .field("len", &self.len())
+            .field("debug", &self.debug)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_allocates_from_two() {
+        let table = InodeTable::with_root("root").debug(false);
+
+        assert_eq!(table.insert("a"), 2);
+        assert_eq!(table.insert("b"), 3);
+        assert_eq!(table.len(), 3);
+        assert_eq!(*table.get(1).unwrap(), "root");
+        assert_eq!(*table.get(3).unwrap(), "b");
+        assert!(table.get(4).is_none());
+    }
+
+    #[test]
+    fn forget_removes_at_zero() {
+        let table = InodeTable::new().debug(false);
+        let inode = table.insert("a");
+
+        assert!(table.remember(inode));
+        assert!(table.remember(inode));
+        assert!(table.remember(inode));
+
+        assert!(table.forget(inode, 2).is_none());
+        assert!(table.get(inode).is_some());
+
+        assert_eq!(*table.forget(inode, 1).unwrap(), "a");
+        assert!(table.get(inode).is_none());
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn forget_more_than_remembered_saturates() {
+        let table = InodeTable::new().debug(false);
+        let inode = table.insert("a");
+
+        assert!(table.remember(inode));
+
+        assert_eq!(*table.forget(inode, 5).unwrap(), "a");
+        assert!(table.forget(inode, 1).is_none());
+    }
+
+    #[test]
+    fn root_is_never_forgotten() {
+        let table = InodeTable::with_root("root").debug(false);
+
+        assert!(table.forget(ROOT_INODE, 10).is_none());
+        assert!(table.get(ROOT_INODE).is_some());
+    }
+
+    #[test]
+    fn unknown_inode() {
+        let table = InodeTable::<&str>::new().debug(false);
+
+        assert!(!table.remember(2));
+        assert!(table.forget(2, 1).is_none());
+    }
+
+    #[test]
+    fn batch_forget() {
+        let table = InodeTable::with_root("root").debug(false);
+        let a = table.insert("a");
+        let b = table.insert("b");
+
+        assert!(table.remember(a));
+        assert!(table.remember(b));
+        assert!(table.remember(b));
+
+        let removed = table.batch_forget(&[(a, 1), (b, 1), (ROOT_INODE, 1), (100, 1)]);
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(*removed[0], "a");
+        assert!(table.get(b).is_some());
+        assert_eq!(table.len(), 2);
+
+        let removed = table.batch_forget(&[(b, 1)]);
+
+        assert_eq!(*removed[0], "b");
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn remove_unreplied_inode() {
+        let table = InodeTable::new().debug(false);
+        let inode = table.insert("a");
+
+        assert_eq!(*table.remove(inode).unwrap(), "a");
+        assert!(!table.remember(inode));
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! kernel config negotiated in `FUSE_INIT`.
+
+#[cfg(feature = "file-lock")]
+pub use crate::abi::FUSE_POSIX_LOCKS;
+use crate::abi::{
+    DEFAULT_CONGESTION_THRESHOLD, DEFAULT_MAX_BACKGROUND, DEFAULT_MAX_PAGES, DEFAULT_TIME_GRAN,
+    FUSE_KERNEL_MINOR_VERSION, MIN_WRITE_SIZE,
+};
+pub use crate::abi::{
+    FUSE_ABORT_ERROR, FUSE_ASYNC_DIO, FUSE_ASYNC_READ, FUSE_ATOMIC_O_TRUNC, FUSE_AUTO_INVAL_DATA,
+    FUSE_BIG_WRITES, FUSE_CACHE_SYMLINKS, FUSE_DONT_MASK, FUSE_DO_READDIRPLUS,
+    FUSE_EXPLICIT_INVAL_DATA, FUSE_EXPORT_SUPPORT, FUSE_FILE_OPS, FUSE_FLOCK_LOCKS,
+    FUSE_HANDLE_KILLPRIV, FUSE_HAS_IOCTL_DIR, FUSE_MAP_ALIGNMENT, FUSE_MAX_PAGES,
+    FUSE_NO_OPENDIR_SUPPORT, FUSE_NO_OPEN_SUPPORT, FUSE_PARALLEL_DIROPS, FUSE_POSIX_ACL,
+    FUSE_READDIRPLUS_AUTO, FUSE_WRITEBACK_CACHE,
+};
+#[cfg(not(target_os = "macos"))]
+pub use crate::abi::{FUSE_SPLICE_MOVE, FUSE_SPLICE_READ, FUSE_SPLICE_WRITE};
+
+/// the kernel config of `FUSE_INIT`.
+///
+/// The session fills it with the capabilities offered by the kernel and the proposed limits, and
+/// passes it to [`init`], the filesystem can change it before it is replied to the kernel.
+///
+/// [`init`]: crate::Filesystem::init
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KernelConfig {
+    pub(crate) major: u32,
+    pub(crate) minor: u32,
+    pub(crate) capable: u32,
+    pub(crate) want: u32,
+    pub(crate) max_readahead: u32,
+    pub(crate) max_background: u16,
+    pub(crate) congestion_threshold: u16,
+    pub(crate) max_write: u32,
+    pub(crate) max_write_limit: u32,
+    pub(crate) time_gran: u32,
+    pub(crate) max_pages: u16,
+}
+
+impl KernelConfig {
+    /// create the kernel config from the fields of an init request, no capability is enabled
+    /// and the limits are the defaults. It is used to reply the init request by a custom
+    /// dispatcher, see [`raw`].
+    ///
+    /// [`raw`]: crate::raw
+    pub fn new(major: u32, minor: u32, capable: u32, max_readahead: u32, max_write: u32) -> Self {
+        let max_write = max_write.max(MIN_WRITE_SIZE as u32);
+
+        Self {
+            major,
+            minor: minor.min(FUSE_KERNEL_MINOR_VERSION),
+            capable,
+            want: 0,
+            max_readahead,
+            max_background: DEFAULT_MAX_BACKGROUND,
+            congestion_threshold: DEFAULT_CONGESTION_THRESHOLD,
+            max_write,
+            max_write_limit: max_write,
+            time_gran: DEFAULT_TIME_GRAN,
+            max_pages: DEFAULT_MAX_PAGES,
+        }
+    }
+
+    /// the fuse protocol major version.
+    pub fn protocol_major(&self) -> u32 {
+        self.major
+    }
+
+    /// the fuse protocol minor version.
+    pub fn protocol_minor(&self) -> u32 {
+        self.minor
+    }
+
+    /// the capability flags offered by the kernel.
+    pub fn capable(&self) -> u32 {
+        self.capable
+    }
+
+    /// the capability flags which will be enabled.
+    pub fn want(&self) -> u32 {
+        self.want
+    }
+
+    /// enable the capability flags. If some flags are not offered by the kernel, nothing is
+    /// changed and the unsupported flags will be returned in `Err`.
+    pub fn add_capabilities(&mut self, flags: u32) -> Result<(), u32> {
+        let unsupported = flags & !self.capable;
+
+        if unsupported != 0 {
+            return Err(unsupported);
+        }
+
+        self.want |= flags;
+
+        Ok(())
+    }
+
+    /// disable the capability flags, for example clear [`FUSE_ASYNC_READ`] if the filesystem
+    /// needs the read requests are sent in order.
+    pub fn clear_capabilities(&mut self, flags: u32) {
+        self.want &= !flags;
+    }
+
+    /// the max readahead size.
+    pub fn max_readahead(&self) -> u32 {
+        self.max_readahead
+    }
+
+    /// set the max readahead size, the kernel will use the smaller one if the value is larger
+    /// than the kernel's.
+    pub fn set_max_readahead(&mut self, max_readahead: u32) {
+        self.max_readahead = max_readahead;
+    }
+
+    /// the max number of pending background requests.
+    pub fn max_background(&self) -> u16 {
+        self.max_background
+    }
+
+    /// set the max number of pending background requests.
+    pub fn set_max_background(&mut self, max_background: u16) {
+        self.max_background = max_background;
+    }
+
+    /// the number of pending background requests when the kernel marks the filesystem as
+    /// congested.
+    pub fn congestion_threshold(&self) -> u16 {
+        self.congestion_threshold
+    }
+
+    /// set the number of pending background requests when the kernel marks the filesystem as
+    /// congested.
+    pub fn set_congestion_threshold(&mut self, congestion_threshold: u16) {
+        self.congestion_threshold = congestion_threshold;
+    }
+
+    /// the max size of write requests.
+    pub fn max_write(&self) -> u32 {
+        self.max_write
+    }
+
+    /// set the max size of write requests.
+    ///
+    /// # Notes:
+    ///
+    /// the read buffer is allocated by [`MountOptions::max_write`] before init, so the value
+    /// can't be larger than it, and the min value is 4 KiB.
+    ///
+    /// [`MountOptions::max_write`]: crate::MountOptions::max_write
+    pub fn set_max_write(&mut self, max_write: u32) {
+        self.max_write = max_write
+            .min(self.max_write_limit)
+            .max(MIN_WRITE_SIZE as u32);
+    }
+
+    /// the timestamp granularity in nanoseconds.
+    pub fn time_gran(&self) -> u32 {
+        self.time_gran
+    }
+
+    /// set the timestamp granularity in nanoseconds.
+    pub fn set_time_gran(&mut self, time_gran: u32) {
+        self.time_gran = time_gran;
+    }
+
+    /// the max pages of a request.
+    pub fn max_pages(&self) -> u16 {
+        self.max_pages
+    }
+
+    /// set the max pages of a request.
+    pub fn set_max_pages(&mut self, max_pages: u16) {
+        self.max_pages = max_pages;
+    }
+}
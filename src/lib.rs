@@ -12,27 +12,56 @@
 //! - `async-std-runtime`: use [async_std](https://docs.rs/async-std) runtime.
 //! - `tokio-runtime`: use [tokio](https://docs.rs/tokio) runtime.
 //! - `unprivileged`: allow mount filesystem without root permission by using `fusermount3`.
+//! - `tracing`: run every request handler in a [tracing](https://docs.rs/tracing) span, the
+//!   spans of the filesystem will nest under the request span.
 //!
 //! # Notes:
 //!
 //! You must enable `async-std-runtime` or `tokio-runtime` feature.
+//!
+//! # Upgrade from 0.1:
+//!
+//! - the lookup count of an inode is increased when its entry is replied to the kernel, the
+//!   session calls [`Filesystem::remember`] for it. A filesystem which counts the lookups in
+//!   [`Filesystem::lookup`], [`Filesystem::mknod`], [`Filesystem::mkdir`],
+//!   [`Filesystem::symlink`], [`Filesystem::link`], [`Filesystem::create`] or
+//!   [`Filesystem::readdirplus`] should move the counting to [`Filesystem::remember`], otherwise
+//!   the entries which are not replied, such as an interrupted lookup, are never forgotten. The
+//!   default `remember` does nothing, so the old counting still compiles.
+//! - [`Filesystem::batch_forget`] receives the `(inode, nlookup)` pairs instead of the inodes.
+//! - the `tokio-runtime` feature uses tokio 1 instead of tokio 0.2.
 
+use std::convert::TryFrom;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use nix::sys::stat::mode_t;
 
+/// the fuse request opcode.
+pub use abi::fuse_opcode as Opcode;
 /// re-export [`async_trait`].
 ///
 /// [`async_trait`]: async_trait::async_trait
 pub use async_trait::async_trait;
+/// re-export [`Bytes`].
+///
+/// [`Bytes`]: bytes::Bytes
+pub use bytes::Bytes;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub use decode::fuzz_decode;
 pub use errno::Errno;
 pub use filesystem::Filesystem;
+pub use handle_table::HandleTable;
 pub use helper::perm_from_mode_and_kind;
-pub use mount_options::MountOptions;
+pub use inode_table::InodeTable;
+#[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
+pub use mount_handle::MountHandle;
+pub use mount_options::{MountOptions, PanicPolicy, RequestOrdering};
 pub use request::Request;
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
 /// fuse filesystem session.
 pub use session::Session;
+pub use splice::WriteData;
 
 use crate::abi::{
     fuse_attr, fuse_setattr_in, FATTR_ATIME, FATTR_ATIME_NOW, FATTR_CTIME, FATTR_GID,
@@ -41,16 +70,33 @@ use crate::abi::{
 use crate::helper::mode_from_kind_and_perm;
 
 mod abi;
+mod buffer_pool;
+mod concurrency;
 mod connection;
+mod decode;
+mod dir_cursor;
+mod encode;
 mod errno;
 mod filesystem;
+mod handle_table;
 mod helper;
+mod inode_table;
+pub mod kernel_config;
+pub mod low_level;
+pub mod metrics;
+#[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
+mod mount_handle;
 mod mount_options;
 pub mod notify;
+pub mod path;
+pub mod raw;
 pub mod reply;
+mod reply_buf;
 mod request;
+mod request_order;
 mod session;
 mod spawn;
+mod splice;
 
 /// pre-defined Result, the Err type is [`Errno`].
 ///
@@ -204,8 +250,10 @@ pub struct SetAttr {
     pub flags: Option<u32>,
 }
 
-impl From<&fuse_setattr_in> for SetAttr {
-    fn from(setattr_in: &fuse_setattr_in) -> Self {
+impl TryFrom<&fuse_setattr_in> for SetAttr {
+    type Error = Errno;
+
+    fn try_from(setattr_in: &fuse_setattr_in) -> std::result::Result<Self, Self::Error> {
         let mut set_attr = Self::default();
 
         if setattr_in.valid & FATTR_MODE > 0 {
@@ -225,8 +273,7 @@ impl From<&fuse_setattr_in> for SetAttr {
         }
 
         if setattr_in.valid & FATTR_ATIME > 0 {
-            set_attr.atime =
-                Some(UNIX_EPOCH + Duration::new(setattr_in.atime, setattr_in.atimensec));
+            set_attr.atime = Some(system_time(setattr_in.atime, setattr_in.atimensec)?);
         }
 
         if setattr_in.valid & FATTR_ATIME_NOW > 0 {
@@ -234,8 +281,7 @@ impl From<&fuse_setattr_in> for SetAttr {
         }
 
         if setattr_in.valid & FATTR_MTIME > 0 {
-            set_attr.mtime =
-                Some(UNIX_EPOCH + Duration::new(setattr_in.mtime, setattr_in.mtimensec));
+            set_attr.mtime = Some(system_time(setattr_in.mtime, setattr_in.mtimensec)?);
         }
 
         if setattr_in.valid & FATTR_MTIME_NOW > 0 {
@@ -247,17 +293,25 @@ impl From<&fuse_setattr_in> for SetAttr {
         }
 
         if setattr_in.valid & FATTR_CTIME > 0 {
-            set_attr.ctime =
-                Some(UNIX_EPOCH + Duration::new(setattr_in.ctime, setattr_in.ctimensec));
+            set_attr.ctime = Some(system_time(setattr_in.ctime, setattr_in.ctimensec)?);
         }
 
-        set_attr
+        Ok(set_attr)
     }
 }
 
+/// build the [`SystemTime`] of a timestamp from the kernel, return `EINVAL` if it overflows.
+fn system_time(secs: u64, nsecs: u32) -> Result<SystemTime> {
+    Duration::from_secs(secs)
+        .checked_add(Duration::from_nanos(nsecs.into()))
+        .and_then(|duration| UNIX_EPOCH.checked_add(duration))
+        .ok_or_else(|| libc::EINVAL.into())
+}
+
 pub mod prelude {
     //! the fuse3 prelude.
 
+    pub use crate::kernel_config::KernelConfig;
     pub use crate::reply::*;
     pub use crate::Errno;
     pub use crate::FileAttr;
@@ -267,4 +321,5 @@ pub mod prelude {
     pub use crate::Request;
     pub use crate::Result;
     pub use crate::SetAttr;
+    pub use crate::WriteData;
 }
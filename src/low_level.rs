@@ -0,0 +1,1143 @@
+//! the low-level filesystem API with deferred replies.
+//!
+//! Every request of [`LowLevelFilesystem`] comes with a [`ReplyHandle`], like the `fuse_req_t` of
+//! libfuse. The handle can be moved into another task or stored, and replied later, the request
+//! doesn't hold a future of the filesystem while it is waiting. Use [`LowLevel`] to mount a
+//! [`LowLevelFilesystem`] by [`Session`].
+//!
+//! [`Session`]: crate::Session
+
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_channel::oneshot::{self, Sender};
+use futures_util::future::FutureExt;
+use log::warn;
+
+use crate::filesystem::Filesystem;
+use crate::helper::interruptible_by_default;
+use crate::kernel_config::KernelConfig;
+use crate::reply::*;
+use crate::request::Request;
+use crate::{Errno, Opcode, Result, SetAttr, WriteData};
+
+/// the handle to reply a request of [`LowLevelFilesystem`].
+///
+/// # Notes:
+///
+/// if the handle is dropped without reply, `EIO` will be replied.
+///
+/// the request holds its [`max_concurrency`] permit until the handle is replied, don't wait for
+/// a later request to reply the handle when the concurrency is limited, or the session may
+/// deadlock.
+///
+/// [`max_concurrency`]: crate::MountOptions::max_concurrency
+pub struct ReplyHandle<T> {
+    unique: u64,
+    sender: Option<Sender<Result<T>>>,
+}
+
+impl<T> ReplyHandle<T> {
+    /// the unique identifier of the request.
+    pub fn unique(&self) -> u64 {
+        self.unique
+    }
+
+    /// reply the request.
+    pub fn reply(self, reply: T) {
+        self.send(Ok(reply))
+    }
+
+    /// reply the request with an error.
+    pub fn error(self, err: Errno) {
+        self.send(Err(err))
+    }
+
+    /// reply the request with a result.
+    pub fn send(mut self, result: Result<T>) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(result);
+        }
+    }
+
+    /// return `true` if the request doesn't wait for the reply anymore, the request is interrupted
+    /// or the session is stopped.
+    pub fn is_canceled(&self) -> bool {
+        match &self.sender {
+            None => true,
+            Some(sender) => sender.is_canceled(),
+        }
+    }
+}
+
+impl ReplyHandle<()> {
+    /// reply the request without data.
+    pub fn ok(self) {
+        self.reply(())
+    }
+}
+
+impl<T> Debug for ReplyHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplyHandle")
+            .field("unique", &self.unique)
+            .finish()
+    }
+}
+
+impl<T> Drop for ReplyHandle<T> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            if !sender.is_canceled() {
+                warn!("request unique {} is not replied, reply EIO", self.unique);
+            }
+
+            let _ = sender.send(Err(libc::EIO.into()));
+        }
+    }
+}
+
+/// create a reply handle of the request and the future which waits for the reply.
+fn reply_channel<T>(req: Request) -> (ReplyHandle<T>, impl Future<Output = Result<T>>) {
+    let (sender, receiver) = oneshot::channel();
+
+    let handle = ReplyHandle {
+        unique: req.unique,
+        sender: Some(sender),
+    };
+
+    let reply = receiver.map(|result| result.unwrap_or_else(|_| Err(libc::EIO.into())));
+
+    (handle, reply)
+}
+
+#[async_trait]
+/// low-level filesystem trait, every request is replied by its [`ReplyHandle`].
+///
+/// # Notes:
+///
+/// except [`init`] and [`destroy`], the methods are called by the session directly, the names
+/// only live during the call, so the methods should return quickly, spawn a task or store the
+/// handle for the slow work. The methods have the same meanings as [`Filesystem`].
+///
+/// [`init`]: LowLevelFilesystem::init
+/// [`destroy`]: LowLevelFilesystem::destroy
+pub trait LowLevelFilesystem {
+    /// initialize filesystem, see [`Filesystem::init`].
+    async fn init(&self, req: Request, config: &mut KernelConfig) -> Result<()>;
+
+    /// clean up filesystem, see [`Filesystem::destroy`].
+    async fn destroy(&self, req: Request);
+
+    /// look up a directory entry by name and get its attributes.
+    fn lookup(&self, _req: Request, _parent: u64, _name: &OsStr, reply: ReplyHandle<ReplyEntry>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// forget an inode, see [`Filesystem::forget`].
+    fn forget(&self, _req: Request, _inode: u64, _nlookup: u64) {}
+
+    /// the kernel looks up the inode once, see [`Filesystem::remember`].
+    fn remember(&self, _inode: u64) {}
+
+    /// get file attributes. If `fh` is None, means `fh` is not set.
+    fn getattr(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: Option<u64>,
+        _flags: u32,
+        reply: ReplyHandle<ReplyAttr>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// set file attributes. If `fh` is None, means `fh` is not set.
+    fn setattr(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: Option<u64>,
+        _set_attr: SetAttr,
+        reply: ReplyHandle<ReplyAttr>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// read symbolic link.
+    fn readlink(&self, _req: Request, _inode: u64, reply: ReplyHandle<ReplyData>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// create a symbolic link.
+    fn symlink(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _link: &OsStr,
+        reply: ReplyHandle<ReplyEntry>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// create file node, see [`Filesystem::mknod`].
+    fn mknod(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _rdev: u32,
+        reply: ReplyHandle<ReplyEntry>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// create a directory.
+    fn mkdir(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyHandle<ReplyEntry>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// remove a file.
+    fn unlink(&self, _req: Request, _parent: u64, _name: &OsStr, reply: ReplyHandle<()>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// remove a directory.
+    fn rmdir(&self, _req: Request, _parent: u64, _name: &OsStr, reply: ReplyHandle<()>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// rename a file or directory.
+    fn rename(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _new_parent: u64,
+        _new_name: &OsStr,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// create a hard link.
+    fn link(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _new_parent: u64,
+        _new_name: &OsStr,
+        reply: ReplyHandle<ReplyEntry>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// open a file, see [`Filesystem::open`].
+    fn open(&self, _req: Request, _inode: u64, _flags: u32, reply: ReplyHandle<ReplyOpen>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// read data, see [`Filesystem::read`].
+    fn read(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _offset: u64,
+        _size: u32,
+        reply: ReplyHandle<ReplyData>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// write data, see [`Filesystem::write`].
+    // the arguments of the write request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn write(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _offset: u64,
+        _data: WriteData,
+        _flags: u32,
+        reply: ReplyHandle<ReplyWrite>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// get filesystem statistics.
+    fn statsfs(&self, _req: Request, _inode: u64, reply: ReplyHandle<ReplyStatFs>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// release an open file, see [`Filesystem::release`].
+    // the arguments of the release request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn release(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// synchronize file contents. If the `datasync` is true, then only the user data should be
+    /// flushed, not the metadata.
+    fn fsync(&self, _req: Request, _inode: u64, _fh: u64, _datasync: bool, reply: ReplyHandle<()>) {
+        reply.ok()
+    }
+
+    /// set an extended attribute.
+    // the arguments of the setxattr request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn setxattr(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _name: &OsStr,
+        _value: &OsStr,
+        _flags: u32,
+        _position: u32,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// get an extended attribute, see [`Filesystem::getxattr`].
+    fn getxattr(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _name: &OsStr,
+        _size: u32,
+        reply: ReplyHandle<ReplyXAttr>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// list extended attribute names, see [`Filesystem::listxattr`].
+    fn listxattr(&self, _req: Request, _inode: u64, _size: u32, reply: ReplyHandle<ReplyXAttr>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// remove an extended attribute.
+    fn removexattr(&self, _req: Request, _inode: u64, _name: &OsStr, reply: ReplyHandle<()>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// flush method, see [`Filesystem::flush`].
+    fn flush(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// open a directory, see [`Filesystem::opendir`].
+    fn opendir(&self, _req: Request, _inode: u64, _flags: u32, reply: ReplyHandle<ReplyOpen>) {
+        reply.reply(ReplyOpen { fh: 0, flags: 0 })
+    }
+
+    /// read directory, see [`Filesystem::readdir`].
+    fn readdir(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _fh: u64,
+        _offset: i64,
+        reply: ReplyHandle<ReplyDirectory>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// release an open directory, see [`Filesystem::releasedir`].
+    fn releasedir(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _flags: u32,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.ok()
+    }
+
+    /// synchronize directory contents, see [`Filesystem::fsyncdir`].
+    fn fsyncdir(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    #[cfg(feature = "file-lock")]
+    /// test for a POSIX file lock.
+    ///
+    /// # Notes:
+    ///
+    /// this is supported on enable **`file-lock`** feature.
+    // the arguments of the getlk request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn getlk(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        _start: u64,
+        _end: u64,
+        _type: u32,
+        _pid: u32,
+        reply: ReplyHandle<ReplyLock>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    #[cfg(feature = "file-lock")]
+    /// acquire, modify or release a POSIX file lock. If `block` is true, the handle can be kept
+    /// until the lock is acquired.
+    ///
+    /// # Notes:
+    ///
+    /// this is supported on enable **`file-lock`** feature.
+    // the arguments of the setlk request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn setlk(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        _start: u64,
+        _end: u64,
+        _type: u32,
+        _pid: u32,
+        _block: bool,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// check file access permissions, see [`Filesystem::access`].
+    fn access(&self, _req: Request, _inode: u64, _mask: u32, reply: ReplyHandle<()>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// create and open a file, see [`Filesystem::create`].
+    fn create(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _flags: u32,
+        reply: ReplyHandle<ReplyCreated>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// handle interrupt, see [`Filesystem::interrupt`].
+    fn interrupt(&self, _req: Request, _unique: u64, reply: ReplyHandle<()>) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// whether the operation can be cancelled by the session when it is interrupted, see
+    /// [`Filesystem::interruptible`]. If the operation is cancelled, [`ReplyHandle::is_canceled`]
+    /// of its handle returns `true`.
+    fn interruptible(&self, opcode: Opcode) -> bool {
+        interruptible_by_default(opcode)
+    }
+
+    /// map block index within file to block index within device.
+    fn bmap(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _blocksize: u32,
+        _idx: u64,
+        reply: ReplyHandle<ReplyBmap>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// poll for IO readiness events.
+    // the arguments of the poll request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn poll(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _kh: Option<u64>,
+        _flags: u32,
+        _events: u32,
+        reply: ReplyHandle<ReplyPoll>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// receive notify reply from kernel.
+    fn notify_reply(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _offset: u64,
+        _data: Bytes,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// forget more than one inode. This is a batch version [`forget`], every item of `forgets` is
+    /// an inode and its nlookup.
+    ///
+    /// [`forget`]: LowLevelFilesystem::forget
+    fn batch_forget(&self, _req: Request, _forgets: &[(u64, u64)]) {}
+
+    /// allocate space for an open file, see [`Filesystem::fallocate`].
+    // the arguments of the fallocate request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn fallocate(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _offset: u64,
+        _length: u64,
+        _mode: u32,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// read directory entries with their attribute, see [`Filesystem::readdirplus`].
+    fn readdirplus(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _fh: u64,
+        _offset: u64,
+        _lock_owner: u64,
+        reply: ReplyHandle<ReplyDirectoryPlus>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// rename a file or directory with flags.
+    // the arguments of the rename2 request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn rename2(
+        &self,
+        _req: Request,
+        _parent: u64,
+        _name: &OsStr,
+        _new_parent: u64,
+        _new_name: &OsStr,
+        _flags: u32,
+        reply: ReplyHandle<()>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// find next data or hole after the specified offset.
+    fn lseek(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh: u64,
+        _offset: u64,
+        _whence: u32,
+        reply: ReplyHandle<ReplyLSeek>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+
+    /// copy a range of data from one file to another, see [`Filesystem::copy_file_range`].
+    // the arguments of the copy_file_range request and its reply handle
+    #[allow(clippy::too_many_arguments)]
+    fn copy_file_range(
+        &self,
+        _req: Request,
+        _inode: u64,
+        _fh_in: u64,
+        _off_in: u64,
+        _inode_out: u64,
+        _fh_out: u64,
+        _off_out: u64,
+        _length: u64,
+        _flags: u64,
+        reply: ReplyHandle<ReplyCopyFileRange>,
+    ) {
+        reply.error(libc::ENOSYS.into())
+    }
+}
+
+/// the adapter to mount a [`LowLevelFilesystem`] by [`Session`], it implements [`Filesystem`]
+/// and waits for the [`ReplyHandle`] of every request.
+///
+/// [`Session`]: crate::Session
+#[derive(Debug)]
+pub struct LowLevel<FS> {
+    fs: FS,
+}
+
+impl<FS> LowLevel<FS> {
+    /// create the adapter of `fs`.
+    pub fn new(fs: FS) -> Self {
+        Self { fs }
+    }
+
+    /// get the reference of the filesystem.
+    pub fn get_ref(&self) -> &FS {
+        &self.fs
+    }
+
+    /// get the filesystem.
+    pub fn into_inner(self) -> FS {
+        self.fs
+    }
+}
+
+#[async_trait]
+impl<FS: LowLevelFilesystem + Send + Sync> Filesystem for LowLevel<FS> {
+    async fn init(&self, req: Request, config: &mut KernelConfig) -> Result<()> {
+        self.fs.init(req, config).await
+    }
+
+    async fn destroy(&self, req: Request) {
+        self.fs.destroy(req).await
+    }
+
+    async fn lookup(&self, req: Request, parent: u64, name: &OsStr) -> Result<ReplyEntry> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.lookup(req, parent, name, handle);
+
+        reply.await
+    }
+
+    async fn forget(&self, req: Request, inode: u64, nlookup: u64) {
+        self.fs.forget(req, inode, nlookup)
+    }
+
+    fn remember(&self, inode: u64) {
+        self.fs.remember(inode)
+    }
+
+    async fn getattr(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: Option<u64>,
+        flags: u32,
+    ) -> Result<ReplyAttr> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.getattr(req, inode, fh, flags, handle);
+
+        reply.await
+    }
+
+    async fn setattr(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: Option<u64>,
+        set_attr: SetAttr,
+    ) -> Result<ReplyAttr> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.setattr(req, inode, fh, set_attr, handle);
+
+        reply.await
+    }
+
+    async fn readlink(&self, req: Request, inode: u64) -> Result<ReplyData> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.readlink(req, inode, handle);
+
+        reply.await
+    }
+
+    async fn symlink(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        link: &OsStr,
+    ) -> Result<ReplyEntry> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.symlink(req, parent, name, link, handle);
+
+        reply.await
+    }
+
+    async fn mknod(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        rdev: u32,
+    ) -> Result<ReplyEntry> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.mknod(req, parent, name, mode, rdev, handle);
+
+        reply.await
+    }
+
+    async fn mkdir(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+    ) -> Result<ReplyEntry> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.mkdir(req, parent, name, mode, umask, handle);
+
+        reply.await
+    }
+
+    async fn unlink(&self, req: Request, parent: u64, name: &OsStr) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.unlink(req, parent, name, handle);
+
+        reply.await
+    }
+
+    async fn rmdir(&self, req: Request, parent: u64, name: &OsStr) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.rmdir(req, parent, name, handle);
+
+        reply.await
+    }
+
+    async fn rename(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .rename(req, parent, name, new_parent, new_name, handle);
+
+        reply.await
+    }
+
+    async fn link(
+        &self,
+        req: Request,
+        inode: u64,
+        new_parent: u64,
+        new_name: &OsStr,
+    ) -> Result<ReplyEntry> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.link(req, inode, new_parent, new_name, handle);
+
+        reply.await
+    }
+
+    async fn open(&self, req: Request, inode: u64, flags: u32) -> Result<ReplyOpen> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.open(req, inode, flags, handle);
+
+        reply.await
+    }
+
+    async fn read(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+    ) -> Result<ReplyData> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.read(req, inode, fh, offset, size, handle);
+
+        reply.await
+    }
+
+    async fn write(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        data: WriteData,
+        flags: u32,
+    ) -> Result<ReplyWrite> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.write(req, inode, fh, offset, data, flags, handle);
+
+        reply.await
+    }
+
+    async fn statsfs(&self, req: Request, inode: u64) -> Result<ReplyStatFs> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.statsfs(req, inode, handle);
+
+        reply.await
+    }
+
+    async fn release(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        flags: u32,
+        lock_owner: u64,
+        flush: bool,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .release(req, inode, fh, flags, lock_owner, flush, handle);
+
+        reply.await
+    }
+
+    async fn fsync(&self, req: Request, inode: u64, fh: u64, datasync: bool) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.fsync(req, inode, fh, datasync, handle);
+
+        reply.await
+    }
+
+    async fn setxattr(
+        &self,
+        req: Request,
+        inode: u64,
+        name: &OsStr,
+        value: &OsStr,
+        flags: u32,
+        position: u32,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .setxattr(req, inode, name, value, flags, position, handle);
+
+        reply.await
+    }
+
+    async fn getxattr(
+        &self,
+        req: Request,
+        inode: u64,
+        name: &OsStr,
+        size: u32,
+    ) -> Result<ReplyXAttr> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.getxattr(req, inode, name, size, handle);
+
+        reply.await
+    }
+
+    async fn listxattr(&self, req: Request, inode: u64, size: u32) -> Result<ReplyXAttr> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.listxattr(req, inode, size, handle);
+
+        reply.await
+    }
+
+    async fn removexattr(&self, req: Request, inode: u64, name: &OsStr) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.removexattr(req, inode, name, handle);
+
+        reply.await
+    }
+
+    async fn flush(&self, req: Request, inode: u64, fh: u64, lock_owner: u64) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.flush(req, inode, fh, lock_owner, handle);
+
+        reply.await
+    }
+
+    async fn opendir(&self, req: Request, inode: u64, flags: u32) -> Result<ReplyOpen> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.opendir(req, inode, flags, handle);
+
+        reply.await
+    }
+
+    async fn readdir(
+        &self,
+        req: Request,
+        parent: u64,
+        fh: u64,
+        offset: i64,
+    ) -> Result<ReplyDirectory> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.readdir(req, parent, fh, offset, handle);
+
+        reply.await
+    }
+
+    async fn releasedir(&self, req: Request, inode: u64, fh: u64, flags: u32) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.releasedir(req, inode, fh, flags, handle);
+
+        reply.await
+    }
+
+    async fn fsyncdir(&self, req: Request, inode: u64, fh: u64, datasync: bool) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.fsyncdir(req, inode, fh, datasync, handle);
+
+        reply.await
+    }
+
+    #[cfg(feature = "file-lock")]
+    async fn getlk(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+    ) -> Result<ReplyLock> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .getlk(req, inode, fh, lock_owner, start, end, r#type, pid, handle);
+
+        reply.await
+    }
+
+    #[cfg(feature = "file-lock")]
+    async fn setlk(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+        block: bool,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.setlk(
+            req, inode, fh, lock_owner, start, end, r#type, pid, block, handle,
+        );
+
+        reply.await
+    }
+
+    async fn access(&self, req: Request, inode: u64, mask: u32) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.access(req, inode, mask, handle);
+
+        reply.await
+    }
+
+    async fn create(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        flags: u32,
+    ) -> Result<ReplyCreated> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.create(req, parent, name, mode, flags, handle);
+
+        reply.await
+    }
+
+    async fn interrupt(&self, req: Request, unique: u64) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.interrupt(req, unique, handle);
+
+        reply.await
+    }
+
+    fn interruptible(&self, opcode: Opcode) -> bool {
+        self.fs.interruptible(opcode)
+    }
+
+    async fn bmap(&self, req: Request, inode: u64, blocksize: u32, idx: u64) -> Result<ReplyBmap> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.bmap(req, inode, blocksize, idx, handle);
+
+        reply.await
+    }
+
+    async fn poll(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        kh: Option<u64>,
+        flags: u32,
+        events: u32,
+    ) -> Result<ReplyPoll> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.poll(req, inode, fh, kh, flags, events, handle);
+
+        reply.await
+    }
+
+    async fn notify_reply(&self, req: Request, inode: u64, offset: u64, data: Bytes) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.notify_reply(req, inode, offset, data, handle);
+
+        reply.await
+    }
+
+    async fn batch_forget(&self, req: Request, forgets: &[(u64, u64)]) {
+        self.fs.batch_forget(req, forgets)
+    }
+
+    async fn fallocate(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        length: u64,
+        mode: u32,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .fallocate(req, inode, fh, offset, length, mode, handle);
+
+        reply.await
+    }
+
+    async fn readdirplus(
+        &self,
+        req: Request,
+        parent: u64,
+        fh: u64,
+        offset: u64,
+        lock_owner: u64,
+    ) -> Result<ReplyDirectoryPlus> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .readdirplus(req, parent, fh, offset, lock_owner, handle);
+
+        reply.await
+    }
+
+    async fn rename2(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+        flags: u32,
+    ) -> Result<()> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs
+            .rename2(req, parent, name, new_parent, new_name, flags, handle);
+
+        reply.await
+    }
+
+    async fn lseek(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        whence: u32,
+    ) -> Result<ReplyLSeek> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.lseek(req, inode, fh, offset, whence, handle);
+
+        reply.await
+    }
+
+    async fn copy_file_range(
+        &self,
+        req: Request,
+        inode: u64,
+        fh_in: u64,
+        off_in: u64,
+        inode_out: u64,
+        fh_out: u64,
+        off_out: u64,
+        length: u64,
+        flags: u64,
+    ) -> Result<ReplyCopyFileRange> {
+        let (handle, reply) = reply_channel(req);
+
+        self.fs.copy_file_range(
+            req, inode, fh_in, off_in, inode_out, fh_out, off_out, length, flags, handle,
+        );
+
+        reply.await
+    }
+}
@@ -0,0 +1,444 @@
+//! the metrics of the fuse session.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bincode::Options;
+
+use crate::abi::{fuse_opcode, fuse_out_header, fuse_write_out, FUSE_OUT_HEADER_SIZE};
+use crate::decode::binary;
+use crate::reply_buf::ReplyBuf;
+
+/// the upper bounds of the latency histogram buckets, the last bucket is `+Inf`.
+const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_micros(50),
+    Duration::from_micros(100),
+    Duration::from_micros(250),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+/// the counters are indexed by the opcode, the opcodes not less than it are not counted.
+const OPCODE_SLOTS: usize = 64;
+
+/// the kernel only accepts the errno less than it.
+const ERRNO_SLOTS: usize = 512;
+
+struct OpcodeCounters {
+    requests: AtomicU64,
+    in_flight: AtomicU64,
+    /// the count of every errno, indexed by the errno.
+    errors: Box<[AtomicU64]>,
+    /// the count of every bucket, the last one is `+Inf`.
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_sum_nanos: AtomicU64,
+    latency_count: AtomicU64,
+}
+
+impl Default for OpcodeCounters {
+    fn default() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            errors: (0..ERRNO_SLOTS).map(|_| AtomicU64::new(0)).collect(),
+            buckets: Default::default(),
+            latency_sum_nanos: AtomicU64::new(0),
+            latency_count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl OpcodeCounters {
+    fn snapshot(&self) -> OpcodeMetrics {
+        let mut count = 0;
+
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(&bound, bucket)| {
+                count += bucket.load(Ordering::Relaxed);
+
+                (bound, count)
+            })
+            .collect();
+
+        let errors = self
+            .errors
+            .iter()
+            .enumerate()
+            .filter_map(|(errno, count)| {
+                let count = count.load(Ordering::Relaxed);
+
+                (count > 0).then_some((errno as i32, count))
+            })
+            .collect();
+
+        OpcodeMetrics {
+            requests: self.requests.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            errors,
+            latency: LatencyHistogram {
+                buckets,
+                count: self.latency_count.load(Ordering::Relaxed),
+                sum: Duration::from_nanos(self.latency_sum_nanos.load(Ordering::Relaxed)),
+            },
+        }
+    }
+}
+
+struct MetricsInner {
+    opcodes: Box<[OpcodeCounters]>,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    reply_queue_depth: Arc<AtomicU64>,
+}
+
+impl Default for MetricsInner {
+    fn default() -> Self {
+        Self {
+            opcodes: (0..OPCODE_SLOTS)
+                .map(|_| OpcodeCounters::default())
+                .collect(),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            reply_queue_depth: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// the opcode of a request and when it is received, it goes with the reply of the request, the
+/// latency is counted when the reply is written.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RequestTimer {
+    opcode: fuse_opcode,
+    received: Instant,
+}
+
+/// the metrics of a session, get it by [`Session::metrics`], it can be cloned and read at any
+/// time, include after the session is mounted.
+///
+/// The session counts the requests, the request latency, from the request is read to it is
+/// replied, and the errors of every opcode. It also counts the bytes read and written, the
+/// requests waiting for reply and the replies waiting to be written to the kernel.
+///
+/// [`Session::metrics`]: crate::Session::metrics
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<MetricsInner>,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// take a snapshot of the metrics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let opcodes = self
+            .inner
+            .opcodes
+            .iter()
+            .enumerate()
+            .filter(|(_, counters)| counters.requests.load(Ordering::Relaxed) > 0)
+            .filter_map(|(opcode, counters)| {
+                let opcode = fuse_opcode::try_from(opcode as u32).ok()?;
+
+                Some((opcode, counters.snapshot()))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        MetricsSnapshot {
+            in_flight: opcodes.values().map(|metrics| metrics.in_flight).sum(),
+            opcodes,
+            bytes_read: self.inner.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.inner.bytes_written.load(Ordering::Relaxed),
+            reply_queue_depth: self.inner.reply_queue_depth.load(Ordering::Relaxed),
+        }
+    }
+
+    fn counters(&self, opcode: fuse_opcode) -> Option<&OpcodeCounters> {
+        self.inner.opcodes.get(opcode as usize)
+    }
+
+    /// count a request read from the kernel, the returned timer should go with its reply, so the
+    /// latency is counted when it is replied.
+    pub(crate) fn request_received(&self, opcode: fuse_opcode) -> Option<RequestTimer> {
+        let counters = self.counters(opcode)?;
+
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+
+        // these requests are not replied by the reply queue
+        if let fuse_opcode::FUSE_INIT
+        | fuse_opcode::FUSE_DESTROY
+        | fuse_opcode::FUSE_FORGET
+        | fuse_opcode::FUSE_BATCH_FORGET
+        | fuse_opcode::FUSE_NOTIFY_REPLY
+        | fuse_opcode::FUSE_INTERRUPT = opcode
+        {
+            return None;
+        }
+
+        counters.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        Some(RequestTimer {
+            opcode,
+            received: Instant::now(),
+        })
+    }
+
+    /// count a reply written to the kernel.
+    pub(crate) fn replied(&self, reply: &ReplyBuf) {
+        // notifications are not counted
+        let timer = match reply.request_timer() {
+            None => return,
+            Some(timer) => timer,
+        };
+
+        let header = reply.header();
+
+        let out_header = match binary().deserialize::<fuse_out_header>(header) {
+            Err(_) => return,
+            Ok(out_header) => out_header,
+        };
+
+        self.finish(timer, out_header.error);
+
+        if out_header.error != 0 {
+            return;
+        }
+
+        match timer.opcode {
+            fuse_opcode::FUSE_READ => {
+                let len = out_header.len as usize - FUSE_OUT_HEADER_SIZE;
+
+                self.inner
+                    .bytes_read
+                    .fetch_add(len as u64, Ordering::Relaxed);
+            }
+
+            fuse_opcode::FUSE_WRITE => {
+                if let Ok(write_out) =
+                    binary().deserialize::<fuse_write_out>(&header[FUSE_OUT_HEADER_SIZE..])
+                {
+                    self.inner
+                        .bytes_written
+                        .fetch_add(write_out.size as u64, Ordering::Relaxed);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    /// count a read reply which is spliced to the kernel, `len` is the size of the data.
+    pub(crate) fn spliced(&self, timer: Option<RequestTimer>, len: usize) {
+        if let Some(timer) = timer {
+            self.finish(timer, 0);
+
+            self.inner
+                .bytes_read
+                .fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// count the latency and the error of the replied request.
+    fn finish(&self, timer: RequestTimer, error: i32) {
+        // request_received only returns the timer of the counted opcode
+        let counters = match self.counters(timer.opcode) {
+            None => return,
+            Some(counters) => counters,
+        };
+
+        let latency = timer.received.elapsed();
+
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| latency <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+        counters.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        counters
+            .latency_sum_nanos
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        counters.latency_count.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(errors) = counters
+            .errors
+            .get((-error) as usize)
+            .filter(|_| error != 0)
+        {
+            errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// the reply queue depth counter, it is shared with the reply senders.
+    pub(crate) fn reply_queue_depth(&self) -> Arc<AtomicU64> {
+        self.inner.reply_queue_depth.clone()
+    }
+
+    /// a reply is taken from the reply queue.
+    pub(crate) fn reply_dequeued(&self) {
+        self.inner.reply_queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// a snapshot of the session [`Metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSnapshot {
+    /// the metrics of every opcode which is received.
+    pub opcodes: BTreeMap<fuse_opcode, OpcodeMetrics>,
+    /// the requests waiting for reply.
+    pub in_flight: u64,
+    /// the bytes replied to the read requests.
+    pub bytes_read: u64,
+    /// the bytes the write requests written.
+    pub bytes_written: u64,
+    /// the replies waiting to be written to the kernel.
+    pub reply_queue_depth: u64,
+}
+
+/// the metrics of an opcode.
+#[derive(Debug, Clone, Default)]
+pub struct OpcodeMetrics {
+    /// the requests received.
+    pub requests: u64,
+    /// the requests waiting for reply.
+    pub in_flight: u64,
+    /// the error replies, the key is the errno.
+    pub errors: BTreeMap<i32, u64>,
+    /// the latency of the replied requests.
+    pub latency: LatencyHistogram,
+}
+
+/// the latency histogram of an opcode.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// the upper bound of every bucket and the cumulative count of the latency not larger than
+    /// it. The `+Inf` bucket is not included, its count is the [`count`].
+    ///
+    /// [`count`]: LatencyHistogram::count
+    pub buckets: Vec<(Duration, u64)>,
+    /// the count of all latency.
+    pub count: u64,
+    /// the sum of all latency.
+    pub sum: Duration,
+}
+
+impl MetricsSnapshot {
+    /// render the snapshot in the Prometheus text format, the metric names are prefixed with
+    /// `fuse_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+
+        // write into a String never fails
+        let _ = self.write_prometheus(&mut text);
+
+        text
+    }
+
+    fn write_prometheus(&self, text: &mut String) -> std::fmt::Result {
+        writeln!(text, "# HELP fuse_requests_total The requests received.")?;
+        writeln!(text, "# TYPE fuse_requests_total counter")?;
+        for (opcode, metrics) in &self.opcodes {
+            writeln!(
+                text,
+                "fuse_requests_total{{opcode=\"{}\"}} {}",
+                opcode, metrics.requests
+            )?;
+        }
+
+        writeln!(
+            text,
+            "# HELP fuse_request_errors_total The error replies by errno."
+        )?;
+        writeln!(text, "# TYPE fuse_request_errors_total counter")?;
+        for (opcode, metrics) in &self.opcodes {
+            for (errno, count) in &metrics.errors {
+                writeln!(
+                    text,
+                    "fuse_request_errors_total{{opcode=\"{}\",errno=\"{}\"}} {}",
+                    opcode, errno, count
+                )?;
+            }
+        }
+
+        writeln!(
+            text,
+            "# HELP fuse_request_duration_seconds The latency of the replied requests."
+        )?;
+        writeln!(text, "# TYPE fuse_request_duration_seconds histogram")?;
+        for (opcode, metrics) in &self.opcodes {
+            let latency = &metrics.latency;
+
+            for (bound, count) in &latency.buckets {
+                writeln!(
+                    text,
+                    "fuse_request_duration_seconds_bucket{{opcode=\"{}\",le=\"{}\"}} {}",
+                    opcode,
+                    bound.as_secs_f64(),
+                    count
+                )?;
+            }
+
+            writeln!(
+                text,
+                "fuse_request_duration_seconds_bucket{{opcode=\"{}\",le=\"+Inf\"}} {}",
+                opcode, latency.count
+            )?;
+            writeln!(
+                text,
+                "fuse_request_duration_seconds_sum{{opcode=\"{}\"}} {}",
+                opcode,
+                latency.sum.as_secs_f64()
+            )?;
+            writeln!(
+                text,
+                "fuse_request_duration_seconds_count{{opcode=\"{}\"}} {}",
+                opcode, latency.count
+            )?;
+        }
+
+        writeln!(
+            text,
+            "# HELP fuse_requests_in_flight The requests waiting for reply."
+        )?;
+        writeln!(text, "# TYPE fuse_requests_in_flight gauge")?;
+        for (opcode, metrics) in &self.opcodes {
+            writeln!(
+                text,
+                "fuse_requests_in_flight{{opcode=\"{}\"}} {}",
+                opcode, metrics.in_flight
+            )?;
+        }
+
+        writeln!(
+            text,
+            "# HELP fuse_read_bytes_total The bytes replied to reads."
+        )?;
+        writeln!(text, "# TYPE fuse_read_bytes_total counter")?;
+        writeln!(text, "fuse_read_bytes_total {}", self.bytes_read)?;
+
+        writeln!(text, "# HELP fuse_written_bytes_total The bytes written.")?;
+        writeln!(text, "# TYPE fuse_written_bytes_total counter")?;
+        writeln!(text, "fuse_written_bytes_total {}", self.bytes_written)?;
+
+        writeln!(
+            text,
+            "# HELP fuse_reply_queue_depth The replies waiting to be written."
+        )?;
+        writeln!(text, "# TYPE fuse_reply_queue_depth gauge")?;
+        writeln!(text, "fuse_reply_queue_depth {}", self.reply_queue_depth)
+    }
+}
@@ -0,0 +1,237 @@
+use std::ffi::OsString;
+use std::fs;
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+#[cfg(feature = "unprivileged")]
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use log::debug;
+use nix::mount::{self, MntFlags};
+
+use crate::helper::io_error_from_nix_error;
+use crate::kernel_config::KernelConfig;
+use crate::spawn::spawn_blocking;
+
+/// a handle of the mounted fuse filesystem.
+///
+/// await it will wait until the filesystem is unmounted and return the filesystem after
+/// [`destroy`] is called.
+///
+/// # Notes:
+///
+/// drop the handle won't unmount the filesystem, the filesystem will keep running in background
+/// until it is unmounted.
+///
+/// [`destroy`]: crate::Filesystem::destroy
+pub struct MountHandle<FS> {
+    mount_path: PathBuf,
+    kernel_config: Arc<Mutex<Option<KernelConfig>>>,
+    #[cfg(feature = "unprivileged")]
+    unprivileged: bool,
+    task: Pin<Box<dyn Future<Output = IoResult<FS>> + Send>>,
+}
+
+impl<FS> MountHandle<FS> {
+    pub(crate) fn new(
+        mount_path: PathBuf,
+        kernel_config: Arc<Mutex<Option<KernelConfig>>>,
+        #[cfg(feature = "unprivileged")] unprivileged: bool,
+        task: impl Future<Output = IoResult<FS>> + Send + 'static,
+    ) -> Self {
+        Self {
+            mount_path,
+            kernel_config,
+            #[cfg(feature = "unprivileged")]
+            unprivileged,
+            task: Box::pin(task),
+        }
+    }
+
+    /// the mount point of the filesystem.
+    pub fn mount_path(&self) -> &Path {
+        &self.mount_path
+    }
+
+    /// the kernel config negotiated in `FUSE_INIT`, return `None` if the init is not finished.
+    pub fn kernel_config(&self) -> Option<KernelConfig> {
+        *self.kernel_config.lock().unwrap()
+    }
+
+    /// unmount the filesystem, after unmount success, await the handle to get the filesystem.
+    ///
+    /// # Notes:
+    ///
+    /// if the filesystem is busy, unmount will fail with `EBUSY`, use [`unmount_lazy`] if you
+    /// want to detach the filesystem anyway.
+    ///
+    /// [`unmount_lazy`]: MountHandle::unmount_lazy
+    pub async fn unmount(&self) -> IoResult<()> {
+        self.inner_unmount(false).await
+    }
+
+    /// lazy unmount the filesystem, the filesystem is detached from the mount point immediately,
+    /// and it will be cleaned up when it is not busy anymore.
+    pub async fn unmount_lazy(&self) -> IoResult<()> {
+        self.inner_unmount(true).await
+    }
+
+    async fn inner_unmount(&self, lazy: bool) -> IoResult<()> {
+        unmount(
+            self.mount_path.clone(),
+            lazy,
+            #[cfg(feature = "unprivileged")]
+            self.unprivileged,
+        )
+        .await
+    }
+}
+
+/// unmount the filesystem mounted at `mount_path`.
+pub(crate) async fn unmount(
+    mount_path: PathBuf,
+    lazy: bool,
+    #[cfg(feature = "unprivileged")] unprivileged: bool,
+) -> IoResult<()> {
+    debug!("unmount {:?}, lazy {}", mount_path, lazy);
+
+    #[cfg(feature = "unprivileged")]
+    if unprivileged {
+        return unmount_with_unprivileged(mount_path, lazy).await;
+    }
+
+    let flags = if lazy {
+        MntFlags::MNT_DETACH
+    } else {
+        MntFlags::empty()
+    };
+
+    spawn_blocking(move || mount::umount2(&mount_path, flags))
+        .await
+        .map_err(io_error_from_nix_error)
+}
+
+/// abort the fuse connection of the filesystem mounted at `mount_path`, the waiting and the later
+/// requests fail with `ENOTCONN`, so the processes using the filesystem won't hang.
+///
+/// # Notes:
+///
+/// `mount_path` must be an absolute path, and the fusectl filesystem must be mounted at
+/// `/sys/fs/fuse/connections`.
+pub(crate) async fn abort_connection(mount_path: PathBuf) -> IoResult<()> {
+    spawn_blocking(move || {
+        let device = fuse_device(&mount_path)?;
+
+        let abort_path = format!("/sys/fs/fuse/connections/{}/abort", device);
+
+        debug!("abort fuse connection {}", abort_path);
+
+        fs::write(abort_path, "1")
+    })
+    .await
+}
+
+/// find the device number of the filesystem mounted at `mount_path` in the mountinfo, it
+/// doesn't access the mount point, which may hang when the filesystem is not responding.
+fn fuse_device(mount_path: &Path) -> IoResult<u64> {
+    let mountinfo = fs::read("/proc/self/mountinfo")?;
+
+    mountinfo
+        .split(|&byte| byte == b'\n')
+        // the last mount on the path is the visible one
+        .rev()
+        .find_map(|line| {
+            // mount_id parent_id major:minor root mount_point ...
+            let mut fields = line.split(|&byte| byte == b' ');
+            let device = fields.nth(2)?;
+            let mount_point = fields.nth(1)?;
+
+            if Path::new(&unescape_mount_point(mount_point)) != mount_path {
+                return None;
+            }
+
+            let device = std::str::from_utf8(device).ok()?;
+            let (major, minor) = device.split_once(':')?;
+
+            // the device number is encoded as the kernel dev_t
+            Some((major.parse::<u64>().ok()? << 20) | minor.parse::<u64>().ok()?)
+        })
+        .ok_or_else(|| {
+            IoError::new(
+                ErrorKind::NotFound,
+                format!("{:?} is not in mountinfo", mount_path),
+            )
+        })
+}
+
+/// the space, tab, newline and backslash in mountinfo are escaped as `\ooo`.
+fn unescape_mount_point(mount_point: &[u8]) -> OsString {
+    let mut unescaped = Vec::with_capacity(mount_point.len());
+    let mut index = 0;
+
+    while index < mount_point.len() {
+        let octal = mount_point
+            .get(index + 1..index + 4)
+            .filter(|_| mount_point[index] == b'\\')
+            .and_then(|octal| std::str::from_utf8(octal).ok())
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+
+        match octal {
+            None => {
+                unescaped.push(mount_point[index]);
+                index += 1;
+            }
+
+            Some(byte) => {
+                unescaped.push(byte);
+                index += 4;
+            }
+        }
+    }
+
+    OsString::from_vec(unescaped)
+}
+
+#[cfg(feature = "unprivileged")]
+async fn unmount_with_unprivileged(mount_path: PathBuf, lazy: bool) -> IoResult<()> {
+    let binary_path = match which::which("fusermount3") {
+        Err(err) => {
+            return Err(IoError::other(format!(
+                "find fusermount binary failed {}",
+                err
+            )));
+        }
+        Ok(path) => path,
+    };
+
+    let status = spawn_blocking(move || {
+        let mut command = Command::new(binary_path);
+
+        command.arg("-u");
+
+        if lazy {
+            command.arg("-z");
+        }
+
+        command.arg(mount_path).status()
+    })
+    .await?;
+
+    if !status.success() {
+        return Err(IoError::other("fusermount unmount failed"));
+    }
+
+    Ok(())
+}
+
+impl<FS> Future for MountHandle<FS> {
+    type Output = IoResult<FS>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.task.as_mut().poll(cx)
+    }
+}
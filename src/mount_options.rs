@@ -1,8 +1,44 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::os::unix::io::RawFd;
 
 use nix::unistd;
 
+use crate::abi::{fuse_opcode, MIN_WRITE_SIZE};
+
+/// what the session does when a request handler panics.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PanicPolicy {
+    /// log the panic and reply `EIO` to the panicked request, the session keeps running.
+    #[default]
+    ReplyEio,
+    /// log the panic and abort the session, the fuse connection is aborted by the fusectl
+    /// filesystem and the filesystem is lazy unmounted, then the mount task will return an error.
+    Abort,
+}
+
+/// how the session orders the mutating requests, such as write, setattr and unlink.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum RequestOrdering {
+    /// all requests run concurrently.
+    #[default]
+    Unordered,
+    /// the mutating requests of the same inode run one by one, by the order they are read from
+    /// the kernel. The directory operations are ordered by the parent inode.
+    Inode,
+    /// like [`Inode`], but the file operations with a fh, such as fsync and flush, are ordered
+    /// by the fh instead of the inode.
+    ///
+    /// # Notes:
+    ///
+    /// the requests changing the data or the size by fh, such as write, fallocate and ftruncate,
+    /// of different fhs run concurrently. A truncate without fh runs after the queued data
+    /// requests of every fh of the inode, and the later data requests run after it.
+    ///
+    /// [`Inode`]: RequestOrdering::Inode
+    FileHandle,
+}
+
 /// mount options.
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub struct MountOptions {
@@ -37,7 +73,26 @@ pub struct MountOptions {
 
     pub(crate) force_readdir_plus: bool,
 
+    pub(crate) splice_read: bool,
+    pub(crate) splice_write: bool,
+
     pub(crate) custom_options: Option<OsString>,
+
+    // lib self option
+    pub(crate) worker_channels: Option<usize>,
+    pub(crate) panic_policy: PanicPolicy,
+    pub(crate) max_concurrency: Option<usize>,
+    pub(crate) opcode_max_concurrency: HashMap<fuse_opcode, usize>,
+    pub(crate) reply_queue_size: Option<usize>,
+    pub(crate) request_ordering: RequestOrdering,
+
+    // init negotiation option
+    pub(crate) max_write: Option<u32>,
+    pub(crate) max_readahead: Option<u32>,
+    pub(crate) max_background: Option<u16>,
+    pub(crate) congestion_threshold: Option<u16>,
+    pub(crate) time_gran: Option<u32>,
+    pub(crate) max_pages: Option<u16>,
 }
 
 impl MountOptions {
@@ -158,6 +213,35 @@ impl MountOptions {
         self
     }
 
+    /// read requests from the kernel by splice, default is disable. The data of write requests
+    /// is kept in a pipe and passed to [`Filesystem::write`] as a [`WriteData`].
+    ///
+    /// # Notes:
+    ///
+    /// every worker channel allocates a pipe which can hold a max size request, it is about
+    /// [`max_write`] plus two pages. If the pipe can't be created, for example the size is larger
+    /// than `/proc/sys/fs/pipe-max-size`, the channel will fall back to the normal read.
+    ///
+    /// [`max_write`]: MountOptions::max_write
+    ///
+    /// [`Filesystem::write`]: crate::Filesystem::write
+    /// [`WriteData`]: crate::WriteData
+    pub fn splice_read(mut self, splice_read: bool) -> Self {
+        self.splice_read = splice_read;
+
+        self
+    }
+
+    /// reply read requests by splice, default is disable. When enabled, a [`ReplyData::Fd`]
+    /// will be spliced to the kernel without copying through userspace.
+    ///
+    /// [`ReplyData::Fd`]: crate::reply::ReplyData::Fd
+    pub fn splice_write(mut self, splice_write: bool) -> Self {
+        self.splice_write = splice_write;
+
+        self
+    }
+
     /// set custom options for fuse filesystem, the custom options will be used in mount
     pub fn custom_options(mut self, custom_options: impl Into<OsString>) -> Self {
         self.custom_options = Some(custom_options.into());
@@ -165,6 +249,159 @@ impl MountOptions {
         self
     }
 
+    /// set the number of worker channels which read requests from the kernel, default is 1.
+    ///
+    /// Each extra worker channel is a `/dev/fuse` file descriptor cloned by the
+    /// `FUSE_DEV_IOC_CLONE` ioctl, and has its own read loop and buffer.
+    ///
+    /// # Notes:
+    ///
+    /// every worker channel allocates its own read buffer, so increase it will use more memory.
+    pub fn worker_channels(mut self, worker_channels: usize) -> Self {
+        self.worker_channels.replace(worker_channels.max(1));
+
+        self
+    }
+
+    /// set what to do when a request handler panics, default is [`PanicPolicy::ReplyEio`].
+    ///
+    /// # Notes:
+    ///
+    /// only the panic in the request handlers is caught, the panic in `init` and `destroy` is not.
+    pub fn panic_policy(mut self, panic_policy: PanicPolicy) -> Self {
+        self.panic_policy = panic_policy;
+
+        self
+    }
+
+    /// set the max number of request handlers running at the same time, default is unlimited.
+    /// When the limit is reached, the session stops reading requests from the kernel until a
+    /// handler finishes, the kernel will throttle the requests by its `max_background`.
+    ///
+    /// # Notes:
+    ///
+    /// `FUSE_INIT`, `FUSE_DESTROY`, `FUSE_INTERRUPT` and `FUSE_SETLKW` are not limited. The
+    /// requests waiting for the previous requests by the [`RequestOrdering`] don't hold the
+    /// permits until the wait finishes, they are limited by another `max_concurrency` permits, so
+    /// at most `max_concurrency` requests run and `max_concurrency` requests wait.
+    ///
+    /// A handler holds its permit until it returns, if it waits for another request, such as a
+    /// [`LowLevelFilesystem`] handler waiting for a [`ReplyHandle`] which is replied by a later
+    /// request, the session may deadlock when all permits are held by the waiting handlers.
+    ///
+    /// [`LowLevelFilesystem`]: crate::low_level::LowLevelFilesystem
+    /// [`ReplyHandle`]: crate::low_level::ReplyHandle
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency.replace(max_concurrency.max(1));
+
+        self
+    }
+
+    /// set the max number of request handlers of the `opcode` running at the same time, default
+    /// is unlimited. It works with the [`max_concurrency`], a request handler needs to satisfy
+    /// both limits.
+    ///
+    /// [`max_concurrency`]: MountOptions::max_concurrency
+    pub fn opcode_max_concurrency(mut self, opcode: fuse_opcode, max_concurrency: usize) -> Self {
+        self.opcode_max_concurrency
+            .insert(opcode, max_concurrency.max(1));
+
+        self
+    }
+
+    /// set the size of the reply queue of each worker channel, default is 1024. A request takes a
+    /// slot of the queue when it is read and releases it after its reply is written to the
+    /// kernel. When all slots are taken, the channel stops reading requests, so the kernel
+    /// throttles the requests.
+    ///
+    /// # Notes:
+    ///
+    /// the slots bound the requests which are handled or replied at the same time in each
+    /// channel. `FUSE_SETLKW` doesn't take a slot because it waits for the unlock request. A
+    /// handler waiting for another request, such as a [`LowLevelFilesystem`] handler waiting for
+    /// a [`ReplyHandle`] which is replied by a later request, may deadlock the channel when all
+    /// slots are taken.
+    ///
+    /// [`LowLevelFilesystem`]: crate::low_level::LowLevelFilesystem
+    /// [`ReplyHandle`]: crate::low_level::ReplyHandle
+    pub fn reply_queue_size(mut self, reply_queue_size: usize) -> Self {
+        self.reply_queue_size.replace(reply_queue_size.max(1));
+
+        self
+    }
+
+    /// set how to order the mutating requests, default is [`RequestOrdering::Unordered`]. It is
+    /// useful when `write_back` is enabled and the filesystem isn't serialized internally.
+    ///
+    /// # Notes:
+    ///
+    /// the read requests, such as read, getattr and lookup, are never ordered. With
+    /// [`RequestOrdering::FileHandle`], a setattr without fh, such as `truncate(2)`, is ordered by
+    /// the inode, so it isn't ordered with the writes of the same file.
+    pub fn request_ordering(mut self, request_ordering: RequestOrdering) -> Self {
+        self.request_ordering = request_ordering;
+
+        self
+    }
+
+    /// set the max size of write requests, default is 16 MiB, the min value is 4 KiB.
+    ///
+    /// # Notes:
+    ///
+    /// every worker channel allocates a read buffer of max_write plus 4 KiB, and the kernel also
+    /// limits the size of write requests by [`max_pages`].
+    ///
+    /// [`max_pages`]: MountOptions::max_pages
+    pub fn max_write(mut self, max_write: u32) -> Self {
+        self.max_write.replace(max_write.max(MIN_WRITE_SIZE as u32));
+
+        self
+    }
+
+    /// set the max readahead size, default is the value provided by the kernel. The kernel will
+    /// use the smaller one if the value is larger than the kernel's.
+    pub fn max_readahead(mut self, max_readahead: u32) -> Self {
+        self.max_readahead.replace(max_readahead);
+
+        self
+    }
+
+    /// set the max number of pending background requests, default is 12.
+    pub fn max_background(mut self, max_background: u16) -> Self {
+        self.max_background.replace(max_background);
+
+        self
+    }
+
+    /// set the number of pending background requests when the kernel marks the filesystem as
+    /// congested, default is 3/4 of [`max_background`].
+    ///
+    /// [`max_background`]: MountOptions::max_background
+    pub fn congestion_threshold(mut self, congestion_threshold: u16) -> Self {
+        self.congestion_threshold.replace(congestion_threshold);
+
+        self
+    }
+
+    /// set the timestamp granularity in nanoseconds, default is 1.
+    ///
+    /// # Notes:
+    ///
+    /// the value should be a power of 10 between 1 and 1000000000.
+    pub fn time_gran(mut self, time_gran: u32) -> Self {
+        self.time_gran.replace(time_gran);
+
+        self
+    }
+
+    /// set the max pages of a request, default is 65535, the kernel will limit it by its own max
+    /// pages limit.
+    pub fn max_pages(mut self, max_pages: u16) -> Self {
+        self.max_pages.replace(max_pages);
+
+        self
+    }
+
     pub(crate) fn build(&mut self, fd: RawFd) -> OsString {
         let mut opts = vec![
             format!("fd={}", fd),
@@ -3,7 +3,6 @@
 use std::ffi::OsString;
 use std::os::unix::ffi::OsStrExt;
 
-use futures_channel::mpsc::UnboundedSender;
 use futures_util::sink::SinkExt;
 
 use lazy_static::lazy_static;
@@ -16,6 +15,7 @@ use crate::abi::{
     FUSE_NOTIFY_POLL_WAKEUP_OUT_SIZE, FUSE_NOTIFY_RETRIEVE_OUT_SIZE, FUSE_NOTIFY_STORE_OUT_SIZE,
     FUSE_OUT_HEADER_SIZE,
 };
+use crate::reply_buf::{ReplyBuf, ReplySender};
 
 lazy_static! {
     static ref BINARY: bincode::Config = {
@@ -29,11 +29,11 @@ lazy_static! {
 #[derive(Debug, Clone)]
 /// notify kernel there are something need to handle.
 pub struct Notify {
-    sender: UnboundedSender<Vec<u8>>,
+    sender: ReplySender,
 }
 
 impl Notify {
-    pub(crate) fn new(sender: UnboundedSender<Vec<u8>>) -> Self {
+    pub(crate) fn new(sender: ReplySender) -> Self {
         Self { sender }
     }
 
@@ -60,7 +60,7 @@ impl Notify {
                     .serialize_into(&mut data, &wakeup_out)
                     .expect("vec size is not enough");
 
-                data
+                data.into()
             }
 
             NotifyKind::InvalidInode { inode, offset, len } => {
@@ -86,7 +86,7 @@ impl Notify {
                     .serialize_into(&mut data, &invalid_inode_out)
                     .expect("vec size is not enough");
 
-                data
+                data.into()
             }
 
             NotifyKind::InvalidEntry { parent, name } => {
@@ -117,7 +117,7 @@ impl Notify {
 
                 // TODO should I add null at the end?
 
-                data
+                data.into()
             }
 
             NotifyKind::Delete {
@@ -153,7 +153,7 @@ impl Notify {
 
                 // TODO should I add null at the end?
 
-                data
+                data.into()
             }
 
             NotifyKind::Store {
@@ -162,7 +162,7 @@ impl Notify {
                 data,
             } => {
                 let out_header = fuse_out_header {
-                    len: (FUSE_OUT_HEADER_SIZE + FUSE_NOTIFY_STORE_OUT_SIZE) as u32,
+                    len: (FUSE_OUT_HEADER_SIZE + FUSE_NOTIFY_STORE_OUT_SIZE + data.len()) as u32,
                     error: fuse_notify_code::FUSE_NOTIFY_STORE as i32,
                     unique: 0,
                 };
@@ -174,20 +174,18 @@ impl Notify {
                     padding: 0,
                 };
 
-                let mut data_buf = Vec::with_capacity(
-                    FUSE_OUT_HEADER_SIZE + FUSE_NOTIFY_STORE_OUT_SIZE + data.len(),
-                );
+                let mut header =
+                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_NOTIFY_STORE_OUT_SIZE);
 
                 BINARY
-                    .serialize_into(&mut data_buf, &out_header)
+                    .serialize_into(&mut header, &out_header)
                     .expect("vec size is not enough");
                 BINARY
-                    .serialize_into(&mut data_buf, &store_out)
+                    .serialize_into(&mut header, &store_out)
                     .expect("vec size is not enough");
 
-                data_buf.extend_from_slice(data);
-
-                data_buf
+                // the data will be given back if notify failed, so it is cloned
+                ReplyBuf::with_payload(header, Box::new(data.clone()), data.len())
             }
 
             NotifyKind::Retrieve {
@@ -220,7 +218,7 @@ impl Notify {
                     .serialize_into(&mut data, &retrieve_out)
                     .expect("vec size is not enough");
 
-                data
+                data.into()
             }
         };
 
@@ -0,0 +1,1352 @@
+//! the path based filesystem API.
+//!
+//! [`PathFilesystem`] is like the high-level API of libfuse, its methods take the paths of the
+//! files instead of inodes. [`PathAdapter`] implements [`Filesystem`] for it, the adapter
+//! allocates the inodes, tracks the lookup counts and keeps the paths correct across rename and
+//! unlink.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::StreamExt;
+use log::debug;
+
+use crate::filesystem::Filesystem;
+use crate::helper::interruptible_by_default;
+use crate::inode_table::LookupCount;
+use crate::kernel_config::KernelConfig;
+use crate::reply::*;
+use crate::request::Request;
+use crate::{Opcode, Result, SetAttr, WriteData};
+
+/// the inode of the root directory.
+const ROOT_INODE: u64 = 1;
+
+/// the inode of a readdir entry which is not looked up yet, the same as libfuse.
+const UNKNOWN_INODE: u64 = 0xffff_ffff;
+
+#[async_trait]
+/// path based filesystem trait.
+///
+/// # Notes:
+///
+/// the methods have the same meanings as [`Filesystem`], but a file is named by its absolute
+/// path, the root directory is `/`. The operations with a file handle get `None` as the path if
+/// the file is unlinked or renamed away. The inodes in the replies are ignored, the
+/// [`PathAdapter`] fills them.
+pub trait PathFilesystem {
+    /// initialize filesystem, see [`Filesystem::init`].
+    async fn init(&self, req: Request, config: &mut KernelConfig) -> Result<()>;
+
+    /// clean up filesystem, see [`Filesystem::destroy`].
+    async fn destroy(&self, req: Request);
+
+    /// get file attributes. If `fh` is None, means `fh` is not set. It is also used to look up a
+    /// file, the `ttl` of the reply is used as the entry ttl.
+    async fn getattr(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: Option<u64>,
+        _flags: u32,
+    ) -> Result<ReplyAttr> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// set file attributes. If `fh` is None, means `fh` is not set.
+    async fn setattr(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: Option<u64>,
+        _set_attr: SetAttr,
+    ) -> Result<ReplyAttr> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// read symbolic link.
+    async fn readlink(&self, _req: Request, _path: &Path) -> Result<ReplyData> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// create a symbolic link at `path`.
+    async fn symlink(&self, _req: Request, _path: &Path, _link: &OsStr) -> Result<ReplyEntry> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// create file node, see [`Filesystem::mknod`].
+    async fn mknod(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _mode: u32,
+        _rdev: u32,
+    ) -> Result<ReplyEntry> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// create a directory.
+    async fn mkdir(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _mode: u32,
+        _umask: u32,
+    ) -> Result<ReplyEntry> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// remove a file.
+    async fn unlink(&self, _req: Request, _path: &Path) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// remove a directory.
+    async fn rmdir(&self, _req: Request, _path: &Path) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// rename a file or directory.
+    async fn rename(&self, _req: Request, _from: &Path, _to: &Path) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// create a hard link `new_path` of `path`.
+    async fn link(&self, _req: Request, _path: &Path, _new_path: &Path) -> Result<ReplyEntry> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// open a file, see [`Filesystem::open`].
+    async fn open(&self, _req: Request, _path: &Path, _flags: u32) -> Result<ReplyOpen> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// read data, see [`Filesystem::read`].
+    async fn read(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _offset: u64,
+        _size: u32,
+    ) -> Result<ReplyData> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// write data, see [`Filesystem::write`].
+    async fn write(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _offset: u64,
+        _data: WriteData,
+        _flags: u32,
+    ) -> Result<ReplyWrite> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// get filesystem statistics.
+    async fn statsfs(&self, _req: Request, _path: &Path) -> Result<ReplyStatFs> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// release an open file, see [`Filesystem::release`].
+    async fn release(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// synchronize file contents. If the `datasync` is true, then only the user data should be
+    /// flushed, not the metadata.
+    async fn fsync(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _datasync: bool,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// set an extended attribute.
+    async fn setxattr(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _name: &OsStr,
+        _value: &OsStr,
+        _flags: u32,
+        _position: u32,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// get an extended attribute, see [`Filesystem::getxattr`].
+    async fn getxattr(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _name: &OsStr,
+        _size: u32,
+    ) -> Result<ReplyXAttr> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// list extended attribute names, see [`Filesystem::listxattr`].
+    async fn listxattr(&self, _req: Request, _path: &Path, _size: u32) -> Result<ReplyXAttr> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// remove an extended attribute.
+    async fn removexattr(&self, _req: Request, _path: &Path, _name: &OsStr) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// flush method, see [`Filesystem::flush`].
+    async fn flush(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _lock_owner: u64,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// open a directory, see [`Filesystem::opendir`].
+    async fn opendir(&self, _req: Request, _path: &Path, _flags: u32) -> Result<ReplyOpen> {
+        Ok(ReplyOpen { fh: 0, flags: 0 })
+    }
+
+    /// read directory, see [`Filesystem::readdir`].
+    async fn readdir(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _fh: u64,
+        _offset: i64,
+    ) -> Result<ReplyDirectory> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// release an open directory, see [`Filesystem::releasedir`].
+    async fn releasedir(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _flags: u32,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// synchronize directory contents, see [`Filesystem::fsyncdir`].
+    async fn fsyncdir(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _datasync: bool,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    #[cfg(feature = "file-lock")]
+    /// test for a POSIX file lock.
+    ///
+    /// # Notes:
+    ///
+    /// this is supported on enable **`file-lock`** feature.
+    // the fields of the getlk request with the path in place of the inode
+    #[allow(clippy::too_many_arguments)]
+    async fn getlk(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _lock_owner: u64,
+        _start: u64,
+        _end: u64,
+        _type: u32,
+        _pid: u32,
+    ) -> Result<ReplyLock> {
+        Err(libc::ENOSYS.into())
+    }
+
+    #[cfg(feature = "file-lock")]
+    /// acquire, modify or release a POSIX file lock.
+    ///
+    /// # Notes:
+    ///
+    /// this is supported on enable **`file-lock`** feature.
+    // the fields of the setlk request with the path in place of the inode
+    #[allow(clippy::too_many_arguments)]
+    async fn setlk(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _lock_owner: u64,
+        _start: u64,
+        _end: u64,
+        _type: u32,
+        _pid: u32,
+        _block: bool,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// check file access permissions, see [`Filesystem::access`].
+    async fn access(&self, _req: Request, _path: &Path, _mask: u32) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// create and open a file, see [`Filesystem::create`].
+    async fn create(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _mode: u32,
+        _flags: u32,
+    ) -> Result<ReplyCreated> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// handle interrupt, see [`Filesystem::interrupt`].
+    async fn interrupt(&self, _req: Request, _unique: u64) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// whether the operation can be cancelled by the session when it is interrupted, see
+    /// [`Filesystem::interruptible`].
+    fn interruptible(&self, opcode: Opcode) -> bool {
+        interruptible_by_default(opcode)
+    }
+
+    /// map block index within file to block index within device.
+    async fn bmap(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _blocksize: u32,
+        _idx: u64,
+    ) -> Result<ReplyBmap> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// poll for IO readiness events.
+    async fn poll(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _kh: Option<u64>,
+        _flags: u32,
+        _events: u32,
+    ) -> Result<ReplyPoll> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// receive notify reply from kernel.
+    async fn notify_reply(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _offset: u64,
+        _data: Bytes,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// allocate space for an open file, see [`Filesystem::fallocate`].
+    async fn fallocate(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _offset: u64,
+        _length: u64,
+        _mode: u32,
+    ) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// read directory entries with their attribute, see [`Filesystem::readdirplus`].
+    async fn readdirplus(
+        &self,
+        _req: Request,
+        _path: &Path,
+        _fh: u64,
+        _offset: u64,
+        _lock_owner: u64,
+    ) -> Result<ReplyDirectoryPlus> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// rename a file or directory with flags.
+    async fn rename2(&self, _req: Request, _from: &Path, _to: &Path, _flags: u32) -> Result<()> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// find next data or hole after the specified offset.
+    async fn lseek(
+        &self,
+        _req: Request,
+        _path: Option<&Path>,
+        _fh: u64,
+        _offset: u64,
+        _whence: u32,
+    ) -> Result<ReplyLSeek> {
+        Err(libc::ENOSYS.into())
+    }
+
+    /// copy a range of data from one file to another, see [`Filesystem::copy_file_range`].
+    // both files of the copy are passed by path and fh
+    #[allow(clippy::too_many_arguments)]
+    async fn copy_file_range(
+        &self,
+        _req: Request,
+        _from_path: Option<&Path>,
+        _fh_in: u64,
+        _off_in: u64,
+        _to_path: Option<&Path>,
+        _fh_out: u64,
+        _off_out: u64,
+        _length: u64,
+        _flags: u64,
+    ) -> Result<ReplyCopyFileRange> {
+        Err(libc::ENOSYS.into())
+    }
+}
+
+/// a looked up file.
+#[derive(Debug)]
+struct Node {
+    parent: u64,
+    name: OsString,
+    nlookup: LookupCount,
+    /// the number of the nodes whose parent is this node.
+    children: u64,
+    /// the file is unlinked or replaced by rename, it has no path anymore.
+    removed: bool,
+}
+
+/// the looked up files, the path of a file is built from its parent and name.
+#[derive(Debug)]
+struct NodeTable {
+    nodes: HashMap<u64, Node>,
+    names: HashMap<(u64, OsString), u64>,
+    /// the inodes of the entries which are not replied to the kernel yet, with their parent and
+    /// name. They become nodes when they are remembered.
+    unreplied: HashMap<u64, (u64, OsString)>,
+    /// the unreplied inodes of each parent, they are dropped with the parent.
+    unreplied_names: HashMap<u64, HashMap<OsString, u64>>,
+    next_inode: u64,
+}
+
+impl NodeTable {
+    fn new() -> Self {
+        let root = Node {
+            parent: ROOT_INODE,
+            name: OsString::new(),
+            nlookup: LookupCount::new(1),
+            children: 0,
+            removed: false,
+        };
+
+        let mut nodes = HashMap::new();
+        nodes.insert(ROOT_INODE, root);
+
+        Self {
+            nodes,
+            names: HashMap::new(),
+            unreplied: HashMap::new(),
+            unreplied_names: HashMap::new(),
+            next_inode: ROOT_INODE + 1,
+        }
+    }
+
+    fn path(&self, mut inode: u64) -> Option<PathBuf> {
+        let mut names = vec![];
+
+        while inode != ROOT_INODE {
+            let node = self.nodes.get(&inode)?;
+
+            if node.removed {
+                return None;
+            }
+
+            names.push(node.name.as_os_str());
+
+            inode = node.parent;
+        }
+
+        let mut path = PathBuf::from("/");
+
+        path.extend(names.into_iter().rev());
+
+        Some(path)
+    }
+
+    fn child_path(&self, parent: u64, name: &OsStr) -> Option<PathBuf> {
+        self.path(parent).map(|path| path.join(name))
+    }
+
+    /// the inode of a readdir entry, `.` and `..` are not looked up by the kernel.
+    fn dirent_inode(&self, parent: u64, name: &OsStr) -> Option<u64> {
+        if name == "." {
+            Some(parent)
+        } else if name == ".." {
+            Some(
+                self.nodes
+                    .get(&parent)
+                    .map_or(ROOT_INODE, |node| node.parent),
+            )
+        } else {
+            None
+        }
+    }
+
+    /// the inode of the child, allocate one if it is not known yet. The allocated inode becomes
+    /// a node when the entry is replied to the kernel, so the entries which are never replied
+    /// don't stay in the table.
+    fn child(&mut self, parent: u64, name: &OsStr) -> u64 {
+        if let Some(&inode) = self.names.get(&(parent, name.to_os_string())) {
+            return inode;
+        }
+
+        let unreplied_names = self.unreplied_names.entry(parent).or_default();
+
+        if let Some(&inode) = unreplied_names.get(name) {
+            return inode;
+        }
+
+        let inode = self.next_inode;
+        self.next_inode += 1;
+
+        unreplied_names.insert(name.to_os_string(), inode);
+        self.unreplied.insert(inode, (parent, name.to_os_string()));
+
+        inode
+    }
+
+    fn remember(&mut self, inode: u64) {
+        if let Some(node) = self.nodes.get_mut(&inode) {
+            node.nlookup.remember();
+
+            return;
+        }
+
+        // the file is unlinked or renamed before its entry is replied, it has no path
+        let (parent, name) = match self.unreplied.remove(&inode) {
+            None => {
+                debug!("remember unknown inode {}", inode);
+
+                return;
+            }
+
+            Some(entry) => entry,
+        };
+
+        self.remove_unreplied_name(parent, &name);
+
+        self.names.insert((parent, name.clone()), inode);
+        self.nodes.insert(
+            inode,
+            Node {
+                parent,
+                name,
+                nlookup: LookupCount::new(1),
+                children: 0,
+                removed: false,
+            },
+        );
+
+        if let Some(parent) = self.nodes.get_mut(&parent) {
+            parent.children += 1;
+        }
+    }
+
+    /// drop the unreplied inode of the name, the name is changed by unlink or rename.
+    fn drop_unreplied(&mut self, parent: u64, name: &OsStr) {
+        if let Some(inode) = self.remove_unreplied_name(parent, name) {
+            self.unreplied.remove(&inode);
+        }
+    }
+
+    fn remove_unreplied_name(&mut self, parent: u64, name: &OsStr) -> Option<u64> {
+        let unreplied_names = self.unreplied_names.get_mut(&parent)?;
+
+        let inode = unreplied_names.remove(name);
+
+        if unreplied_names.is_empty() {
+            self.unreplied_names.remove(&parent);
+        }
+
+        inode
+    }
+
+    fn forget(&mut self, inode: u64, nlookup: u64) {
+        if inode == ROOT_INODE {
+            return;
+        }
+
+        if let Some(node) = self.nodes.get_mut(&inode) {
+            node.nlookup.forget(inode, nlookup, cfg!(debug_assertions));
+        }
+
+        self.try_remove(inode);
+    }
+
+    /// drop the node which is forgotten by the kernel and has no child, then its parent.
+    fn try_remove(&mut self, mut inode: u64) {
+        while inode != ROOT_INODE {
+            match self.nodes.get(&inode) {
+                Some(node) if node.nlookup.is_zero() && node.children == 0 => {}
+                _ => return,
+            }
+
+            let node = self.nodes.remove(&inode).expect("checked above");
+
+            if let Some(unreplied_names) = self.unreplied_names.remove(&inode) {
+                for unreplied_inode in unreplied_names.values() {
+                    self.unreplied.remove(unreplied_inode);
+                }
+            }
+
+            if !node.removed {
+                self.names.remove(&(node.parent, node.name));
+            }
+
+            match self.nodes.get_mut(&node.parent) {
+                None => return,
+                Some(parent) => parent.children -= 1,
+            }
+
+            inode = node.parent;
+        }
+    }
+
+    /// the file is unlinked, it has no path anymore.
+    fn unlink(&mut self, parent: u64, name: &OsStr) {
+        self.drop_unreplied(parent, name);
+
+        if let Some(inode) = self.names.remove(&(parent, name.to_os_string())) {
+            if let Some(node) = self.nodes.get_mut(&inode) {
+                node.removed = true;
+            }
+        }
+    }
+
+    fn rename(&mut self, parent: u64, name: &OsStr, new_parent: u64, new_name: &OsStr) {
+        if parent == new_parent && name == new_name {
+            return;
+        }
+
+        self.unlink(new_parent, new_name);
+        self.drop_unreplied(parent, name);
+
+        let inode = match self.names.remove(&(parent, name.to_os_string())) {
+            None => return,
+            Some(inode) => inode,
+        };
+
+        self.move_node(inode, new_parent, new_name);
+    }
+
+    fn exchange(&mut self, parent: u64, name: &OsStr, new_parent: u64, new_name: &OsStr) {
+        self.drop_unreplied(parent, name);
+        self.drop_unreplied(new_parent, new_name);
+
+        let inode = self.names.remove(&(parent, name.to_os_string()));
+        let new_inode = self.names.remove(&(new_parent, new_name.to_os_string()));
+
+        if let Some(inode) = inode {
+            self.move_node(inode, new_parent, new_name);
+        }
+
+        if let Some(new_inode) = new_inode {
+            self.move_node(new_inode, parent, name);
+        }
+    }
+
+    fn move_node(&mut self, inode: u64, new_parent: u64, new_name: &OsStr) {
+        let old_parent = match self.nodes.get_mut(&inode) {
+            None => return,
+            Some(node) => {
+                let old_parent = node.parent;
+
+                node.parent = new_parent;
+                node.name = new_name.to_os_string();
+
+                old_parent
+            }
+        };
+
+        self.names
+            .insert((new_parent, new_name.to_os_string()), inode);
+
+        if old_parent != new_parent {
+            if let Some(parent) = self.nodes.get_mut(&new_parent) {
+                parent.children += 1;
+            }
+
+            if let Some(parent) = self.nodes.get_mut(&old_parent) {
+                parent.children -= 1;
+            }
+
+            self.try_remove(old_parent);
+        }
+    }
+}
+
+/// the adapter to mount a [`PathFilesystem`] by [`Session`], it implements [`Filesystem`].
+///
+/// # Notes:
+///
+/// every file path is a different inode, even if they are hard links of the same file, like
+/// libfuse.
+///
+/// [`Session`]: crate::Session
+#[derive(Debug)]
+pub struct PathAdapter<FS> {
+    fs: FS,
+    nodes: Arc<Mutex<NodeTable>>,
+}
+
+impl<FS> PathAdapter<FS> {
+    /// create the adapter of `fs`.
+    pub fn new(fs: FS) -> Self {
+        Self {
+            fs,
+            nodes: Arc::new(Mutex::new(NodeTable::new())),
+        }
+    }
+
+    /// get the reference of the filesystem.
+    pub fn get_ref(&self) -> &FS {
+        &self.fs
+    }
+
+    /// get the filesystem.
+    pub fn into_inner(self) -> FS {
+        self.fs
+    }
+
+    fn nodes(&self) -> MutexGuard<'_, NodeTable> {
+        self.nodes.lock().expect("node table lock poisoned")
+    }
+
+    fn path(&self, inode: u64) -> Result<PathBuf> {
+        self.nodes().path(inode).ok_or_else(|| libc::ENOENT.into())
+    }
+
+    fn child_path(&self, parent: u64, name: &OsStr) -> Result<PathBuf> {
+        self.nodes()
+            .child_path(parent, name)
+            .ok_or_else(|| libc::ENOENT.into())
+    }
+
+    /// set the inode of a created or looked up entry.
+    fn entry(&self, parent: u64, name: &OsStr, mut entry: ReplyEntry) -> ReplyEntry {
+        entry.attr.ino = self.nodes().child(parent, name);
+
+        entry
+    }
+}
+
+#[async_trait]
+impl<FS: PathFilesystem + Send + Sync> Filesystem for PathAdapter<FS> {
+    async fn init(&self, req: Request, config: &mut KernelConfig) -> Result<()> {
+        self.fs.init(req, config).await
+    }
+
+    async fn destroy(&self, req: Request) {
+        self.fs.destroy(req).await
+    }
+
+    async fn lookup(&self, req: Request, parent: u64, name: &OsStr) -> Result<ReplyEntry> {
+        let path = self.child_path(parent, name)?;
+
+        let attr = self.fs.getattr(req, Some(&path), None, 0).await?;
+
+        Ok(self.entry(
+            parent,
+            name,
+            ReplyEntry {
+                ttl: attr.ttl,
+                attr: attr.attr,
+                generation: 0,
+            },
+        ))
+    }
+
+    async fn forget(&self, _req: Request, inode: u64, nlookup: u64) {
+        self.nodes().forget(inode, nlookup)
+    }
+
+    fn remember(&self, inode: u64) {
+        self.nodes().remember(inode)
+    }
+
+    async fn getattr(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: Option<u64>,
+        flags: u32,
+    ) -> Result<ReplyAttr> {
+        let path = match fh {
+            None => Some(self.path(inode)?),
+            Some(_) => self.nodes().path(inode),
+        };
+
+        let mut attr = self.fs.getattr(req, path.as_deref(), fh, flags).await?;
+        attr.attr.ino = inode;
+
+        Ok(attr)
+    }
+
+    async fn setattr(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: Option<u64>,
+        set_attr: SetAttr,
+    ) -> Result<ReplyAttr> {
+        let path = match fh {
+            None => Some(self.path(inode)?),
+            Some(_) => self.nodes().path(inode),
+        };
+
+        let mut attr = self.fs.setattr(req, path.as_deref(), fh, set_attr).await?;
+        attr.attr.ino = inode;
+
+        Ok(attr)
+    }
+
+    async fn readlink(&self, req: Request, inode: u64) -> Result<ReplyData> {
+        let path = self.path(inode)?;
+
+        self.fs.readlink(req, &path).await
+    }
+
+    async fn symlink(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        link: &OsStr,
+    ) -> Result<ReplyEntry> {
+        let path = self.child_path(parent, name)?;
+
+        let entry = self.fs.symlink(req, &path, link).await?;
+
+        Ok(self.entry(parent, name, entry))
+    }
+
+    async fn mknod(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        rdev: u32,
+    ) -> Result<ReplyEntry> {
+        let path = self.child_path(parent, name)?;
+
+        let entry = self.fs.mknod(req, &path, mode, rdev).await?;
+
+        Ok(self.entry(parent, name, entry))
+    }
+
+    async fn mkdir(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+    ) -> Result<ReplyEntry> {
+        let path = self.child_path(parent, name)?;
+
+        let entry = self.fs.mkdir(req, &path, mode, umask).await?;
+
+        Ok(self.entry(parent, name, entry))
+    }
+
+    async fn unlink(&self, req: Request, parent: u64, name: &OsStr) -> Result<()> {
+        let path = self.child_path(parent, name)?;
+
+        self.fs.unlink(req, &path).await?;
+
+        self.nodes().unlink(parent, name);
+
+        Ok(())
+    }
+
+    async fn rmdir(&self, req: Request, parent: u64, name: &OsStr) -> Result<()> {
+        let path = self.child_path(parent, name)?;
+
+        self.fs.rmdir(req, &path).await?;
+
+        self.nodes().unlink(parent, name);
+
+        Ok(())
+    }
+
+    async fn rename(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+    ) -> Result<()> {
+        let from = self.child_path(parent, name)?;
+        let to = self.child_path(new_parent, new_name)?;
+
+        self.fs.rename(req, &from, &to).await?;
+
+        self.nodes().rename(parent, name, new_parent, new_name);
+
+        Ok(())
+    }
+
+    async fn link(
+        &self,
+        req: Request,
+        inode: u64,
+        new_parent: u64,
+        new_name: &OsStr,
+    ) -> Result<ReplyEntry> {
+        let path = self.path(inode)?;
+        let new_path = self.child_path(new_parent, new_name)?;
+
+        let entry = self.fs.link(req, &path, &new_path).await?;
+
+        Ok(self.entry(new_parent, new_name, entry))
+    }
+
+    async fn open(&self, req: Request, inode: u64, flags: u32) -> Result<ReplyOpen> {
+        let path = self.path(inode)?;
+
+        self.fs.open(req, &path, flags).await
+    }
+
+    async fn read(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        size: u32,
+    ) -> Result<ReplyData> {
+        let path = self.nodes().path(inode);
+
+        self.fs.read(req, path.as_deref(), fh, offset, size).await
+    }
+
+    async fn write(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        data: WriteData,
+        flags: u32,
+    ) -> Result<ReplyWrite> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .write(req, path.as_deref(), fh, offset, data, flags)
+            .await
+    }
+
+    async fn statsfs(&self, req: Request, inode: u64) -> Result<ReplyStatFs> {
+        let path = self.path(inode)?;
+
+        self.fs.statsfs(req, &path).await
+    }
+
+    async fn release(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        flags: u32,
+        lock_owner: u64,
+        flush: bool,
+    ) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .release(req, path.as_deref(), fh, flags, lock_owner, flush)
+            .await
+    }
+
+    async fn fsync(&self, req: Request, inode: u64, fh: u64, datasync: bool) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs.fsync(req, path.as_deref(), fh, datasync).await
+    }
+
+    async fn setxattr(
+        &self,
+        req: Request,
+        inode: u64,
+        name: &OsStr,
+        value: &OsStr,
+        flags: u32,
+        position: u32,
+    ) -> Result<()> {
+        let path = self.path(inode)?;
+
+        self.fs
+            .setxattr(req, &path, name, value, flags, position)
+            .await
+    }
+
+    async fn getxattr(
+        &self,
+        req: Request,
+        inode: u64,
+        name: &OsStr,
+        size: u32,
+    ) -> Result<ReplyXAttr> {
+        let path = self.path(inode)?;
+
+        self.fs.getxattr(req, &path, name, size).await
+    }
+
+    async fn listxattr(&self, req: Request, inode: u64, size: u32) -> Result<ReplyXAttr> {
+        let path = self.path(inode)?;
+
+        self.fs.listxattr(req, &path, size).await
+    }
+
+    async fn removexattr(&self, req: Request, inode: u64, name: &OsStr) -> Result<()> {
+        let path = self.path(inode)?;
+
+        self.fs.removexattr(req, &path, name).await
+    }
+
+    async fn flush(&self, req: Request, inode: u64, fh: u64, lock_owner: u64) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs.flush(req, path.as_deref(), fh, lock_owner).await
+    }
+
+    async fn opendir(&self, req: Request, inode: u64, flags: u32) -> Result<ReplyOpen> {
+        let path = self.path(inode)?;
+
+        self.fs.opendir(req, &path, flags).await
+    }
+
+    async fn readdir(
+        &self,
+        req: Request,
+        parent: u64,
+        fh: u64,
+        offset: i64,
+    ) -> Result<ReplyDirectory> {
+        let path = self.path(parent)?;
+
+        let reply = self.fs.readdir(req, &path, fh, offset).await?;
+
+        let nodes = self.nodes.clone();
+
+        let entries = reply.entries.map(move |entry| {
+            let mut entry = entry?;
+
+            let nodes = nodes.lock().expect("node table lock poisoned");
+
+            entry.inode = nodes
+                .dirent_inode(parent, &entry.name)
+                .or_else(|| nodes.names.get(&(parent, entry.name.clone())).copied())
+                .unwrap_or(UNKNOWN_INODE);
+
+            Ok(entry)
+        });
+
+        Ok(ReplyDirectory {
+            entries: Box::pin(entries),
+        })
+    }
+
+    async fn releasedir(&self, req: Request, inode: u64, fh: u64, flags: u32) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs.releasedir(req, path.as_deref(), fh, flags).await
+    }
+
+    async fn fsyncdir(&self, req: Request, inode: u64, fh: u64, datasync: bool) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs.fsyncdir(req, path.as_deref(), fh, datasync).await
+    }
+
+    #[cfg(feature = "file-lock")]
+    async fn getlk(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+    ) -> Result<ReplyLock> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .getlk(
+                req,
+                path.as_deref(),
+                fh,
+                lock_owner,
+                start,
+                end,
+                r#type,
+                pid,
+            )
+            .await
+    }
+
+    #[cfg(feature = "file-lock")]
+    async fn setlk(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        r#type: u32,
+        pid: u32,
+        block: bool,
+    ) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .setlk(
+                req,
+                path.as_deref(),
+                fh,
+                lock_owner,
+                start,
+                end,
+                r#type,
+                pid,
+                block,
+            )
+            .await
+    }
+
+    async fn access(&self, req: Request, inode: u64, mask: u32) -> Result<()> {
+        let path = self.path(inode)?;
+
+        self.fs.access(req, &path, mask).await
+    }
+
+    async fn create(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        flags: u32,
+    ) -> Result<ReplyCreated> {
+        let path = self.child_path(parent, name)?;
+
+        let mut created = self.fs.create(req, &path, mode, flags).await?;
+        created.attr.ino = self.nodes().child(parent, name);
+
+        Ok(created)
+    }
+
+    async fn interrupt(&self, req: Request, unique: u64) -> Result<()> {
+        self.fs.interrupt(req, unique).await
+    }
+
+    fn interruptible(&self, opcode: Opcode) -> bool {
+        self.fs.interruptible(opcode)
+    }
+
+    async fn bmap(&self, req: Request, inode: u64, blocksize: u32, idx: u64) -> Result<ReplyBmap> {
+        let path = self.path(inode)?;
+
+        self.fs.bmap(req, &path, blocksize, idx).await
+    }
+
+    async fn poll(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        kh: Option<u64>,
+        flags: u32,
+        events: u32,
+    ) -> Result<ReplyPoll> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .poll(req, path.as_deref(), fh, kh, flags, events)
+            .await
+    }
+
+    async fn notify_reply(&self, req: Request, inode: u64, offset: u64, data: Bytes) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .notify_reply(req, path.as_deref(), offset, data)
+            .await
+    }
+
+    async fn batch_forget(&self, _req: Request, forgets: &[(u64, u64)]) {
+        let mut nodes = self.nodes();
+
+        for &(inode, nlookup) in forgets {
+            nodes.forget(inode, nlookup);
+        }
+    }
+
+    async fn fallocate(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        length: u64,
+        mode: u32,
+    ) -> Result<()> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .fallocate(req, path.as_deref(), fh, offset, length, mode)
+            .await
+    }
+
+    async fn readdirplus(
+        &self,
+        req: Request,
+        parent: u64,
+        fh: u64,
+        offset: u64,
+        lock_owner: u64,
+    ) -> Result<ReplyDirectoryPlus> {
+        let path = self.path(parent)?;
+
+        let reply = self
+            .fs
+            .readdirplus(req, &path, fh, offset, lock_owner)
+            .await?;
+
+        let nodes = self.nodes.clone();
+
+        // the entries are remembered when they are replied
+        let entries = reply.entries.map(move |entry| {
+            let mut entry = entry?;
+
+            let mut nodes = nodes.lock().expect("node table lock poisoned");
+
+            entry.inode = match nodes.dirent_inode(parent, &entry.name) {
+                Some(inode) => inode,
+                None => nodes.child(parent, &entry.name),
+            };
+            entry.attr.ino = entry.inode;
+
+            Ok(entry)
+        });
+
+        Ok(ReplyDirectoryPlus {
+            entries: Box::pin(entries),
+        })
+    }
+
+    async fn rename2(
+        &self,
+        req: Request,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+        flags: u32,
+    ) -> Result<()> {
+        let from = self.child_path(parent, name)?;
+        let to = self.child_path(new_parent, new_name)?;
+
+        self.fs.rename2(req, &from, &to, flags).await?;
+
+        let mut nodes = self.nodes();
+
+        if flags & libc::RENAME_EXCHANGE > 0 {
+            nodes.exchange(parent, name, new_parent, new_name);
+        } else {
+            nodes.rename(parent, name, new_parent, new_name);
+        }
+
+        Ok(())
+    }
+
+    async fn lseek(
+        &self,
+        req: Request,
+        inode: u64,
+        fh: u64,
+        offset: u64,
+        whence: u32,
+    ) -> Result<ReplyLSeek> {
+        let path = self.nodes().path(inode);
+
+        self.fs
+            .lseek(req, path.as_deref(), fh, offset, whence)
+            .await
+    }
+
+    async fn copy_file_range(
+        &self,
+        req: Request,
+        inode: u64,
+        fh_in: u64,
+        off_in: u64,
+        inode_out: u64,
+        fh_out: u64,
+        off_out: u64,
+        length: u64,
+        flags: u64,
+    ) -> Result<ReplyCopyFileRange> {
+        let (from_path, to_path) = {
+            let nodes = self.nodes();
+
+            (nodes.path(inode), nodes.path(inode_out))
+        };
+
+        self.fs
+            .copy_file_range(
+                req,
+                from_path.as_deref(),
+                fh_in,
+                off_in,
+                to_path.as_deref(),
+                fh_out,
+                off_out,
+                length,
+                flags,
+            )
+            .await
+    }
+}
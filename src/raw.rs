@@ -0,0 +1,26 @@
+//! the low-level request and reply types.
+//!
+//! [`Session`] decodes the requests into [`Operation`]s and encodes the replies from
+//! [`Response`]s, they can also be used to write a custom dispatcher, proxy or analyzer which
+//! reads the requests from `/dev/fuse` by itself.
+//!
+//! [`Session`]: crate::Session
+
+use crate::decode::{decode_header, decode_operation, CompatSizes};
+pub use crate::decode::{DecodeError, Operation};
+pub use crate::encode::Response;
+use crate::Request;
+
+/// decode a request read from `/dev/fuse`, `proto_minor` is the negotiated fuse protocol minor
+/// version, some request structs are smaller for old kernels. Names and data of the
+/// [`Operation`] borrow `data`.
+pub fn decode_request(
+    data: &[u8],
+    proto_minor: u32,
+) -> Result<(Request, Operation<'_>), DecodeError> {
+    let in_header = decode_header(data)?;
+
+    let operation = decode_operation(&in_header, data, 0, CompatSizes::new(proto_minor))?;
+
+    Ok((Request::from(&in_header), operation))
+}
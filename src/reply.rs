@@ -1,6 +1,8 @@
 //! reply structures.
 use std::ffi::OsString;
+use std::os::unix::io::AsRawFd;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::stream::Stream;
@@ -11,7 +13,7 @@ use crate::abi::{
 };
 #[cfg(feature = "file-lock")]
 use crate::abi::{fuse_file_lock, fuse_lk_out};
-use crate::{FileAttr, FileType};
+use crate::{FileAttr, FileType, Result};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 /// entry reply.
@@ -61,9 +63,19 @@ impl Into<fuse_attr_out> for ReplyAttr {
 }
 
 /// data reply.
-pub struct ReplyData {
+pub enum ReplyData {
     /// the data.
-    pub data: Box<dyn AsRef<[u8]> + Send>,
+    Data(Box<dyn AsRef<[u8]> + Send>),
+    /// read `len` bytes of `fd` at `offset`, if the read reply is in splice write mode, the data
+    /// will be spliced to the kernel without copying through userspace.
+    Fd {
+        /// the file.
+        fd: Arc<dyn AsRawFd + Send + Sync>,
+        /// the offset of the file.
+        offset: u64,
+        /// the length of the data.
+        len: u32,
+    },
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -166,8 +178,13 @@ pub struct DirectoryEntry {
 }
 
 /// readdir reply.
+///
+/// # Notes:
+///
+/// if the stream returns an error at the first entry of a reply, the error is replied. If some
+/// entries are replied already, the error is replied by the next readdir.
 pub struct ReplyDirectory {
-    pub entries: Pin<Box<dyn Stream<Item = DirectoryEntry> + Send>>,
+    pub entries: Pin<Box<dyn Stream<Item = Result<DirectoryEntry>> + Send>>,
 }
 
 #[cfg(feature = "file-lock")]
@@ -300,8 +317,12 @@ pub struct DirectoryEntryPlus {
 }
 
 /// the readdirplus reply.
+///
+/// # Notes:
+///
+/// the errors of the stream are replied like [`ReplyDirectory`].
 pub struct ReplyDirectoryPlus {
-    pub entries: Pin<Box<dyn Stream<Item = DirectoryEntryPlus> + Send>>,
+    pub entries: Pin<Box<dyn Stream<Item = Result<DirectoryEntryPlus>> + Send>>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -0,0 +1,300 @@
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_lock::SemaphoreGuardArc;
+#[cfg(feature = "tracing")]
+use bincode::Options;
+use futures_channel::mpsc::{SendError, Sender};
+use futures_util::sink::Sink;
+
+#[cfg(feature = "tracing")]
+use crate::abi::{fuse_out_header, FUSE_OUT_HEADER_SIZE};
+#[cfg(feature = "tracing")]
+use crate::decode::binary;
+use crate::metrics::RequestTimer;
+
+/// a slot of the reply queue taken by a request, it is released when the request handler
+/// finishes and its replies are written to the kernel.
+pub(crate) type ReplySlot = Arc<SemaphoreGuardArc>;
+
+/// a reply to the kernel. The header and the payload are kept in their own buffers and sent by
+/// one `writev`, so the payload owned by the filesystem is not copied.
+pub(crate) struct ReplyBuf {
+    header: Vec<u8>,
+    payload: Option<Payload>,
+    /// the timer of the replied request, it is set by the [`ReplySender`] of the request.
+    request_timer: Option<RequestTimer>,
+    /// the inodes the kernel looks up when it takes the reply.
+    lookups: Vec<u64>,
+    /// the reply queue slot of the replied request, it is released after the reply is written.
+    reply_slot: Option<ReplySlot>,
+}
+
+struct Payload {
+    data: Box<dyn AsRef<[u8]> + Send>,
+    len: usize,
+}
+
+impl ReplyBuf {
+    /// create a reply which only has the header, the `fuse_out_header` and the fixed size out
+    /// struct.
+    pub(crate) fn new(header: Vec<u8>) -> Self {
+        Self {
+            header,
+            payload: None,
+            request_timer: None,
+            lookups: vec![],
+            reply_slot: None,
+        }
+    }
+
+    /// create a reply with a payload, only the first `len` bytes of the payload will be sent.
+    pub(crate) fn with_payload(
+        header: Vec<u8>,
+        payload: Box<dyn AsRef<[u8]> + Send>,
+        len: usize,
+    ) -> Self {
+        let len = len.min(payload.as_ref().as_ref().len());
+
+        Self {
+            header,
+            payload: Some(Payload { data: payload, len }),
+            request_timer: None,
+            lookups: vec![],
+            reply_slot: None,
+        }
+    }
+
+    /// the `fuse_out_header` and the fixed size out struct.
+    pub(crate) fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// set the inodes the kernel looks up when it takes the reply, they are remembered by the
+    /// filesystem when the reply is written.
+    pub(crate) fn with_lookups(mut self, lookups: Vec<u64>) -> Self {
+        self.lookups = lookups;
+
+        self
+    }
+
+    /// the inodes the kernel looks up when it takes the reply.
+    pub(crate) fn lookups(&self) -> &[u64] {
+        &self.lookups
+    }
+
+    /// the timer of the replied request, notifications don't have it.
+    pub(crate) fn request_timer(&self) -> Option<RequestTimer> {
+        self.request_timer
+    }
+
+    /// the buffers of the reply which are passed to `writev`.
+    pub(crate) fn io_slices(&self) -> [IoSlice<'_>; 2] {
+        let payload = match &self.payload {
+            None => &[][..],
+            Some(payload) => &payload.data.as_ref().as_ref()[..payload.len],
+        };
+
+        [IoSlice::new(&self.header), IoSlice::new(payload)]
+    }
+
+    #[cfg(feature = "tracing")]
+    /// record the errno and the size of the reply in the current request span.
+    fn record_in_span(&self) {
+        let out_header = match binary().deserialize::<fuse_out_header>(&self.header) {
+            Err(_) => return,
+            Ok(out_header) => out_header,
+        };
+
+        // the notifications are not replies of the requests
+        if out_header.unique == 0 {
+            return;
+        }
+
+        let span = tracing::Span::current();
+
+        span.record("errno", -out_header.error);
+        span.record(
+            "reply_size",
+            (out_header.len as usize - FUSE_OUT_HEADER_SIZE) as u64,
+        );
+    }
+
+    /// copy the header and the payload into one buffer.
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        let [header, payload] = self.io_slices();
+
+        let mut data = Vec::with_capacity(header.len() + payload.len());
+
+        data.extend_from_slice(&header);
+        data.extend_from_slice(&payload);
+
+        data
+    }
+}
+
+impl From<Vec<u8>> for ReplyBuf {
+    fn from(header: Vec<u8>) -> Self {
+        Self::new(header)
+    }
+}
+
+const UNCLAIMED: u8 = 0;
+const SPLICING: u8 = 1;
+const REPLIED: u8 = 2;
+const ABANDONED: u8 = 3;
+
+/// who replies a request, the spliced read reply is written outside the reply queue, it races
+/// with the error reply of the interrupt or the deadline. Only the first one replies the request.
+#[derive(Debug, Default)]
+pub(crate) struct ReplyClaim {
+    state: AtomicU8,
+    /// the errno of the error reply, the splicer replies it if the request is abandoned while
+    /// splicing and nothing is spliced.
+    errno: AtomicI32,
+}
+
+impl ReplyClaim {
+    /// start splicing the reply, return `false` if the request is already replied.
+    pub(crate) fn claim_splice(&self) -> bool {
+        self.state
+            .compare_exchange(UNCLAIMED, SPLICING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// the reply is spliced.
+    pub(crate) fn splice_finished(&self) {
+        self.state.store(REPLIED, Ordering::SeqCst);
+    }
+
+    /// nothing is spliced, the request can be replied in the normal way. Return the errno to
+    /// reply if the request is abandoned by the interrupt or the deadline while splicing.
+    pub(crate) fn splice_failed(&self) -> Option<i32> {
+        match self
+            .state
+            .compare_exchange(SPLICING, UNCLAIMED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => None,
+            Err(_) => {
+                self.state.store(REPLIED, Ordering::SeqCst);
+
+                Some(self.errno.load(Ordering::SeqCst))
+            }
+        }
+    }
+
+    /// the request is replied with the `errno` by the interrupt or the deadline, return `false` if
+    /// it is replied by the splicer.
+    pub(crate) fn claim_error(&self, errno: i32) -> bool {
+        self.errno.store(errno, Ordering::SeqCst);
+
+        match self
+            .state
+            .compare_exchange(UNCLAIMED, REPLIED, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => true,
+
+            Err(SPLICING) => {
+                // the splicer replies the errno if nothing is spliced
+                self.state
+                    .compare_exchange(SPLICING, ABANDONED, Ordering::SeqCst, Ordering::SeqCst)
+                    .map_or_else(|_| self.claim_error(errno), |_| false)
+            }
+
+            Err(_) => false,
+        }
+    }
+}
+
+/// the sender of a reply queue, it counts the replies put in the queue, the reply task decreases
+/// the count when it takes a reply.
+#[derive(Debug, Clone)]
+pub(crate) struct ReplySender {
+    sender: Sender<ReplyBuf>,
+    queue_depth: Arc<AtomicU64>,
+    request_timer: Option<RequestTimer>,
+    reply_slot: Option<ReplySlot>,
+    reply_claim: Arc<ReplyClaim>,
+}
+
+impl ReplySender {
+    pub(crate) fn new(sender: Sender<ReplyBuf>, queue_depth: Arc<AtomicU64>) -> Self {
+        Self {
+            sender,
+            queue_depth,
+            request_timer: None,
+            reply_slot: None,
+            reply_claim: Arc::default(),
+        }
+    }
+
+    /// a sender of the replies of a request, the replies carry the request timer and the reply
+    /// queue slot of the request.
+    pub(crate) fn for_request(
+        &self,
+        request_timer: Option<RequestTimer>,
+        reply_slot: Option<ReplySlot>,
+    ) -> Self {
+        Self {
+            request_timer,
+            reply_slot,
+            reply_claim: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// the claim of the request which the sender replies.
+    pub(crate) fn reply_claim(&self) -> Arc<ReplyClaim> {
+        self.reply_claim.clone()
+    }
+
+    /// the timer of the request which the sender replies.
+    pub(crate) fn request_timer(&self) -> Option<RequestTimer> {
+        self.request_timer
+    }
+}
+
+impl Sink<ReplyBuf> for ReplySender {
+    type Error = SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, mut reply: ReplyBuf) -> Result<(), Self::Error> {
+        // the reply is sent by the request handler, so the current span is the request span
+        #[cfg(feature = "tracing")]
+        reply.record_in_span();
+
+        if reply.request_timer.is_none() {
+            reply.request_timer = self.request_timer;
+        }
+
+        if reply.reply_slot.is_none() {
+            reply.reply_slot = self.reply_slot.clone();
+        }
+
+        // count the reply before it is sent, the reply task may take it and decrease the depth
+        // before start_send returns
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+
+        let result = Pin::new(&mut self.sender).start_send(reply);
+
+        if result.is_err() {
+            self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_close(cx)
+    }
+}
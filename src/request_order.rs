@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_channel::oneshot::{self, Receiver, Sender};
+use futures_util::future::{FutureExt, Shared};
+
+use crate::decode::Operation;
+use crate::spawn::spawn_without_return;
+use crate::RequestOrdering;
+
+/// the key which the mutating requests are serialized by.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+enum OrderKey {
+    Inode(u64),
+    Fh(u64),
+    /// the data of the inode. The data requests by fh share it and the truncate without fh holds
+    /// it, so the truncate runs after the data requests of every fh.
+    Data(u64),
+}
+
+/// it is ready when the request finishes.
+type Finished = Shared<Receiver<()>>;
+
+#[derive(Default)]
+struct KeyTail {
+    /// the last request which holds the key.
+    holder: Option<(u64, Finished)>,
+    /// the requests which share the key after the last holder, they run concurrently.
+    sharers: HashMap<u64, Finished>,
+}
+
+#[derive(Default)]
+struct Tails {
+    tails: HashMap<OrderKey, KeyTail>,
+    next_seq: u64,
+}
+
+/// serialize the mutating requests per inode or per fh, by the order they are read from the
+/// kernel.
+pub(crate) struct RequestOrder {
+    ordering: RequestOrdering,
+    tails: Arc<Mutex<Tails>>,
+}
+
+impl RequestOrder {
+    pub(crate) fn new(ordering: RequestOrdering) -> Self {
+        Self {
+            ordering,
+            tails: Arc::new(Mutex::new(Tails::default())),
+        }
+    }
+
+    /// queue the request after the previous requests with the same keys, it must be called in
+    /// the order the requests are read.
+    pub(crate) fn enter(&self, operation: &Operation) -> OrderTicket {
+        let keys = order_keys(operation, self.ordering);
+        let shared_keys = shared_order_keys(operation, self.ordering);
+
+        if keys.is_empty() && shared_keys.is_empty() {
+            return OrderTicket {
+                previous: vec![],
+                done: vec![],
+                seq: 0,
+                tails: None,
+            };
+        }
+
+        let mut tails = self.tails.lock().unwrap();
+
+        let seq = tails.next_seq;
+        tails.next_seq += 1;
+
+        let mut previous = vec![];
+        let mut done = Vec::with_capacity(keys.len() + shared_keys.len());
+
+        // the holder runs after the previous holder and sharers of the key
+        for key in keys {
+            let (sender, receiver) = oneshot::channel();
+
+            let tail = tails.tails.entry(key).or_default();
+
+            if let Some((_, holder)) = tail.holder.replace((seq, receiver.shared())) {
+                previous.push(holder);
+            }
+
+            previous.extend(tail.sharers.drain().map(|(_, sharer)| sharer));
+
+            done.push((key, sender));
+        }
+
+        // the sharer only runs after the previous holder of the key
+        for key in shared_keys {
+            let (sender, receiver) = oneshot::channel();
+
+            let tail = tails.tails.entry(key).or_default();
+
+            if let Some((_, holder)) = &tail.holder {
+                previous.push(holder.clone());
+            }
+
+            tail.sharers.insert(seq, receiver.shared());
+
+            done.push((key, sender));
+        }
+
+        OrderTicket {
+            previous,
+            done,
+            seq,
+            tails: Some(self.tails.clone()),
+        }
+    }
+}
+
+/// the place of a request in the order, the next requests with the same keys can run after it is
+/// dropped.
+pub(crate) struct OrderTicket {
+    previous: Vec<Finished>,
+    done: Vec<(OrderKey, Sender<()>)>,
+    seq: u64,
+    tails: Option<Arc<Mutex<Tails>>>,
+}
+
+impl OrderTicket {
+    /// the request is queued after the previous requests with the same keys.
+    pub(crate) fn is_waiting(&self) -> bool {
+        !self.previous.is_empty()
+    }
+
+    /// wait until the previous requests with the same keys finish.
+    pub(crate) async fn wait(&mut self) {
+        // keep the receiver until it is ready, so it is still chained when the wait is cancelled
+        while let Some(previous) = self.previous.last_mut() {
+            // the sender is dropped when the previous request finishes
+            let _ = previous.await;
+
+            self.previous.pop();
+        }
+    }
+}
+
+/// release the keys of the finished request, the next requests can run after the senders are
+/// dropped.
+fn release(tails: &Mutex<Tails>, seq: u64, done: Vec<(OrderKey, Sender<()>)>) {
+    let mut tails = tails.lock().unwrap();
+
+    // remove the key if no request is queued after this one
+    for (key, _) in &done {
+        let tail = match tails.tails.get_mut(key) {
+            None => continue,
+            Some(tail) => tail,
+        };
+
+        if matches!(tail.holder, Some((holder_seq, _)) if holder_seq == seq) {
+            tail.holder = None;
+        }
+
+        tail.sharers.remove(&seq);
+
+        if tail.holder.is_none() && tail.sharers.is_empty() {
+            tails.tails.remove(key);
+        }
+    }
+}
+
+impl Drop for OrderTicket {
+    fn drop(&mut self) {
+        let tails = match self.tails.take() {
+            None => return,
+            Some(tails) => tails,
+        };
+
+        let seq = self.seq;
+        let done = std::mem::take(&mut self.done);
+
+        if self.previous.is_empty() {
+            release(&tails, seq, done);
+
+            return;
+        }
+
+        // the request is dropped before the previous requests finish, such as it is interrupted
+        // or its deadline expires, the next requests must still wait for the previous requests
+        let previous = std::mem::take(&mut self.previous);
+
+        spawn_without_return(async move {
+            for previous in previous {
+                let _ = previous.await;
+            }
+
+            release(&tails, seq, done);
+        });
+    }
+}
+
+/// the keys held by the mutating requests, reads and the other requests are not ordered.
+fn order_keys(operation: &Operation, ordering: RequestOrdering) -> Vec<OrderKey> {
+    let by_fh = match ordering {
+        RequestOrdering::Unordered => return vec![],
+        RequestOrdering::Inode => false,
+        RequestOrdering::FileHandle => true,
+    };
+
+    let file_key = |inode, fh| {
+        if by_fh {
+            OrderKey::Fh(fh)
+        } else {
+            OrderKey::Inode(inode)
+        }
+    };
+
+    match *operation {
+        Operation::Setattr {
+            inode,
+            fh,
+            ref set_attr,
+        } => match fh {
+            Some(fh) => vec![file_key(inode, fh)],
+            // a truncate without fh can't overtake the data requests queued by fh
+            None if by_fh && set_attr.size.is_some() => {
+                vec![OrderKey::Inode(inode), OrderKey::Data(inode)]
+            }
+            None => vec![OrderKey::Inode(inode)],
+        },
+
+        Operation::Write { inode, fh, .. } | Operation::Fallocate { inode, fh, .. } => {
+            vec![file_key(inode, fh)]
+        }
+
+        Operation::Fsync { inode, fh, .. }
+        | Operation::Flush { inode, fh, .. }
+        | Operation::Release { inode, fh, .. } => vec![file_key(inode, fh)],
+
+        Operation::CopyFileRange {
+            inode_out, fh_out, ..
+        } => vec![file_key(inode_out, fh_out)],
+
+        Operation::Setxattr { inode, .. } | Operation::Removexattr { inode, .. } => {
+            vec![OrderKey::Inode(inode)]
+        }
+
+        Operation::Symlink { parent, .. }
+        | Operation::Mknod { parent, .. }
+        | Operation::Mkdir { parent, .. }
+        | Operation::Unlink { parent, .. }
+        | Operation::Rmdir { parent, .. }
+        | Operation::Create { parent, .. } => vec![OrderKey::Inode(parent)],
+
+        Operation::Rename {
+            parent, new_parent, ..
+        }
+        | Operation::Rename2 {
+            parent, new_parent, ..
+        } => {
+            if parent == new_parent {
+                vec![OrderKey::Inode(parent)]
+            } else {
+                vec![OrderKey::Inode(parent), OrderKey::Inode(new_parent)]
+            }
+        }
+
+        Operation::Link {
+            inode, new_parent, ..
+        } => {
+            if inode == new_parent {
+                vec![OrderKey::Inode(inode)]
+            } else {
+                vec![OrderKey::Inode(inode), OrderKey::Inode(new_parent)]
+            }
+        }
+
+        _ => vec![],
+    }
+}
+
+/// the keys shared by the requests changing the data or the size by fh, they run after the
+/// previous truncate without fh, but not after each other.
+fn shared_order_keys(operation: &Operation, ordering: RequestOrdering) -> Vec<OrderKey> {
+    if ordering != RequestOrdering::FileHandle {
+        return vec![];
+    }
+
+    match *operation {
+        Operation::Setattr {
+            inode,
+            fh: Some(_),
+            ref set_attr,
+        } if set_attr.size.is_some() => vec![OrderKey::Data(inode)],
+
+        Operation::Write { inode, .. } | Operation::Fallocate { inode, .. } => {
+            vec![OrderKey::Data(inode)]
+        }
+
+        Operation::CopyFileRange { inode_out, .. } => vec![OrderKey::Data(inode_out)],
+
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SetAttr;
+
+    fn write(inode: u64, fh: u64) -> Operation<'static> {
+        Operation::Write {
+            inode,
+            fh,
+            offset: 0,
+            size: 0,
+            write_flags: 0,
+            flags: 0,
+            lock_owner: None,
+            data: &[],
+        }
+    }
+
+    fn truncate(inode: u64, fh: Option<u64>) -> Operation<'static> {
+        Operation::Setattr {
+            inode,
+            fh,
+            set_attr: SetAttr {
+                size: Some(0),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn fsync(inode: u64, fh: u64) -> Operation<'static> {
+        Operation::Fsync {
+            inode,
+            fh,
+            datasync: false,
+        }
+    }
+
+    /// return `true` if the previous requests of the ticket are finished.
+    fn ready(ticket: &mut OrderTicket) -> bool {
+        ticket.wait().now_or_never().is_some()
+    }
+
+    #[test]
+    fn unordered() {
+        let order = RequestOrder::new(RequestOrdering::Unordered);
+
+        let _first = order.enter(&write(2, 1));
+        let mut second = order.enter(&write(2, 1));
+
+        assert!(!second.is_waiting());
+        assert!(ready(&mut second));
+        assert!(order.tails.lock().unwrap().tails.is_empty());
+    }
+
+    #[test]
+    fn inode_ordering() {
+        let order = RequestOrder::new(RequestOrdering::Inode);
+
+        let mut first = order.enter(&write(2, 1));
+        let mut second = order.enter(&write(2, 2));
+        let mut other_inode = order.enter(&write(3, 1));
+
+        assert!(ready(&mut first));
+        assert!(second.is_waiting());
+        assert!(!ready(&mut second));
+        assert!(ready(&mut other_inode));
+
+        drop(first);
+
+        assert!(ready(&mut second));
+
+        drop(second);
+        drop(other_inode);
+
+        assert!(order.tails.lock().unwrap().tails.is_empty());
+    }
+
+    #[test]
+    fn fh_ordering() {
+        let order = RequestOrder::new(RequestOrdering::FileHandle);
+
+        let first = order.enter(&write(2, 1));
+        let mut other_fh = order.enter(&write(2, 2));
+        let mut same_fh = order.enter(&fsync(2, 1));
+
+        assert!(ready(&mut other_fh));
+        assert!(!ready(&mut same_fh));
+
+        drop(first);
+
+        assert!(ready(&mut same_fh));
+    }
+
+    #[test]
+    fn truncate_without_fh_waits_for_every_fh() {
+        let order = RequestOrder::new(RequestOrdering::FileHandle);
+
+        let first = order.enter(&write(2, 1));
+        let second = order.enter(&write(2, 2));
+        let mut truncate = order.enter(&truncate(2, None));
+        let mut after = order.enter(&write(2, 3));
+        let mut truncate_by_fh = order.enter(&self::truncate(2, Some(4)));
+
+        assert!(!ready(&mut truncate));
+
+        drop(first);
+
+        assert!(!ready(&mut truncate));
+
+        drop(second);
+
+        assert!(ready(&mut truncate));
+        assert!(!ready(&mut after));
+        assert!(!ready(&mut truncate_by_fh));
+
+        drop(truncate);
+
+        assert!(ready(&mut after));
+        assert!(ready(&mut truncate_by_fh));
+
+        drop(after);
+        drop(truncate_by_fh);
+
+        assert!(order.tails.lock().unwrap().tails.is_empty());
+    }
+
+    #[cfg(feature = "async-std-runtime")]
+    #[test]
+    fn dropped_ticket_keeps_the_chain() {
+        let order = RequestOrder::new(RequestOrdering::Inode);
+
+        let first = order.enter(&write(2, 1));
+        let second = order.enter(&write(2, 1));
+        let mut third = order.enter(&write(2, 1));
+
+        // the second request is cancelled before the first one finishes
+        drop(second);
+
+        assert!(!ready(&mut third));
+
+        drop(first);
+
+        async_std::task::block_on(third.wait());
+
+        drop(third);
+
+        assert!(order.tails.lock().unwrap().tails.is_empty());
+    }
+}
@@ -1,57 +1,88 @@
-use std::convert::TryFrom;
-use std::ffi::OsString;
+use std::collections::HashMap;
+use std::convert::{Infallible, TryFrom};
+use std::ffi::OsStr;
+use std::future::Future;
 use std::io::Error as IoError;
 use std::io::ErrorKind;
 use std::io::Result as IoResult;
-use std::os::unix::ffi::OsStrExt;
-use std::os::unix::ffi::OsStringExt;
 use std::os::unix::io::AsRawFd;
-use std::path::Path;
-use std::sync::Arc;
-
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_lock::Semaphore;
 #[cfg(feature = "async-std-runtime")]
 use async_std::fs::read_dir;
-use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
-use futures_util::future::FutureExt;
+use bincode::Options;
+use futures_channel::mpsc::{channel, unbounded, Receiver, UnboundedReceiver, UnboundedSender};
+use futures_util::future::{self, AbortHandle, Abortable, FutureExt};
 use futures_util::sink::{Sink, SinkExt};
-use futures_util::stream::StreamExt;
+use futures_util::stream::{Stream, StreamExt};
 use futures_util::{pin_mut, select};
 use log::{debug, error, warn};
-use nix::mount;
-use nix::mount::MsFlags;
+use nix::unistd::{self, SysconfVar};
 #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
 use tokio::fs::read_dir;
-
-use lazy_static::lazy_static;
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
 
 use crate::abi::*;
+use crate::buffer_pool::BufferPool;
+use crate::concurrency::{waits_for_later_request, ConcurrencyLimits, Permits};
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
 use crate::connection::FuseConnection;
+use crate::decode::{binary, decode_header, decode_operation, CompatSizes, DecodeError, Operation};
+use crate::dir_cursor::{DirCursor, DirCursors};
+use crate::encode::{
+    dirent_size, direntplus_lookup, direntplus_size, encode_dirent, encode_direntplus, out_entries,
+    out_header, Response,
+};
 use crate::filesystem::Filesystem;
-use crate::helper::*;
+use crate::handle_table::OpenHandles;
+use crate::kernel_config::KernelConfig;
+use crate::metrics::{Metrics, RequestTimer};
+use crate::mount_handle::{abort_connection, unmount};
 use crate::notify::Notify;
-use crate::reply::ReplyXAttr;
+use crate::reply::{DirectoryEntry, DirectoryEntryPlus, ReplyData};
+use crate::reply_buf::{ReplyBuf, ReplyClaim, ReplySender, ReplySlot};
 use crate::request::Request;
-use crate::spawn::spawn_without_return;
-use crate::MountOptions;
-use crate::{Errno, SetAttr};
-
-lazy_static! {
-    static ref BINARY: bincode::Config = {
-        let mut cfg = bincode::config();
-        cfg.little_endian();
-
-        cfg
-    };
-}
+use crate::request_order::{OrderTicket, RequestOrder};
+use crate::spawn::{sleep, spawn, spawn_blocking, spawn_without_return};
+use crate::splice::{self, Pipe, PipePool};
+use crate::{Errno, FileAttr, Result, WriteData};
+use crate::{MountHandle, MountOptions, Opcode, PanicPolicy};
 
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
 /// fuse filesystem session.
 pub struct Session<FS> {
     fuse_connection: Option<Arc<FuseConnection>>,
+    /// the running requests, the interruptible ones have their abort handles.
+    in_flight_requests: Arc<Mutex<HashMap<u64, Option<AbortHandle>>>>,
+    open_handles: Arc<OpenHandles>,
+    readdir_cursors: Arc<DirCursors<Result<DirectoryEntry>>>,
+    readdirplus_cursors: Arc<DirCursors<Result<ReaddirplusEntry>>>,
+    readdir_unsupported: Arc<AtomicBool>,
+    readdirplus_unsupported: Arc<AtomicBool>,
+    kernel_config: Arc<Mutex<Option<KernelConfig>>>,
+    proto_minor: AtomicU32,
+    splice_read: AtomicBool,
+    splice_write: AtomicBool,
     filesystem: Option<Arc<FS>>,
-    response_sender: UnboundedSender<Vec<u8>>,
-    response_receiver: Option<UnboundedReceiver<Vec<u8>>>,
+    response_sender: ReplySender,
+    response_receiver: Option<Receiver<ReplyBuf>>,
+    metrics: Metrics,
+    panic_sender: UnboundedSender<HandlerPanic>,
+    panic_receiver: Option<UnboundedReceiver<HandlerPanic>>,
+    /// the spawned request tasks hold the clones of the sender, nothing is sent.
+    running_task_sender: Option<UnboundedSender<Infallible>>,
+    running_task_receiver: Option<UnboundedReceiver<Infallible>>,
+    concurrency_limits: Arc<ConcurrencyLimits>,
+    request_order: RequestOrder,
+    deadlines: HashMap<fuse_opcode, Duration>,
+    deadline_errno: Errno,
     mount_options: MountOptions,
 }
 
@@ -59,13 +90,40 @@ pub struct Session<FS> {
 impl<FS> Session<FS> {
     /// new a fuse filesystem session.
     pub fn new(mount_options: MountOptions) -> Self {
-        let (sender, receiver) = unbounded();
+        let metrics = Metrics::new();
+
+        let (sender, receiver) = channel(
+            mount_options
+                .reply_queue_size
+                .unwrap_or(DEFAULT_REPLY_QUEUE_SIZE),
+        );
+        let (panic_sender, panic_receiver) = unbounded();
+        let (running_task_sender, running_task_receiver) = unbounded();
 
         Self {
             fuse_connection: None,
+            in_flight_requests: Arc::new(Mutex::new(HashMap::new())),
+            open_handles: Arc::new(OpenHandles::default()),
+            readdir_cursors: Arc::new(DirCursors::new()),
+            readdirplus_cursors: Arc::new(DirCursors::new()),
+            readdir_unsupported: Arc::new(AtomicBool::new(false)),
+            readdirplus_unsupported: Arc::new(AtomicBool::new(false)),
+            kernel_config: Arc::new(Mutex::new(None)),
+            proto_minor: AtomicU32::new(FUSE_KERNEL_MINOR_VERSION),
+            splice_read: AtomicBool::new(false),
+            splice_write: AtomicBool::new(false),
             filesystem: None,
-            response_sender: sender,
+            response_sender: ReplySender::new(sender, metrics.reply_queue_depth()),
             response_receiver: Some(receiver),
+            metrics,
+            panic_sender,
+            panic_receiver: Some(panic_receiver),
+            running_task_sender: Some(running_task_sender),
+            running_task_receiver: Some(running_task_receiver),
+            concurrency_limits: Arc::new(ConcurrencyLimits::new(&mount_options)),
+            request_order: RequestOrder::new(mount_options.request_ordering),
+            deadlines: HashMap::new(),
+            deadline_errno: libc::ETIMEDOUT.into(),
             mount_options,
         }
     }
@@ -76,27 +134,65 @@ impl<FS> Session<FS> {
     pub fn get_notify(&self) -> Notify {
         Notify::new(self.response_sender.clone())
     }
+
+    /// get the [`metrics`] of the session, it keeps updating after the session is mounted.
+    ///
+    /// [`metrics`]: Metrics
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// set the deadline of the requests with the `opcode`, default is no deadline. When the
+    /// deadline expires, the handler future is canceled and the request is replied with the
+    /// [`deadline_errno`].
+    ///
+    /// # Notes:
+    ///
+    /// the requests which don't need a reply, such as `FUSE_FORGET`, never time out. The deadline
+    /// starts when the handler runs, the time waiting for the previous requests by the
+    /// [`RequestOrdering`] and the [`max_concurrency`] permits is not counted.
+    ///
+    /// [`deadline_errno`]: Session::deadline_errno
+    /// [`RequestOrdering`]: crate::RequestOrdering
+    /// [`max_concurrency`]: crate::MountOptions::max_concurrency
+    pub fn deadline(mut self, opcode: Opcode, deadline: Duration) -> Self {
+        self.deadlines.insert(opcode, deadline);
+
+        self
+    }
+
+    /// set the error replied to the timed out requests, default is `ETIMEDOUT`, `EIO` is another
+    /// common choice.
+    pub fn deadline_errno(mut self, errno: Errno) -> Self {
+        self.deadline_errno = errno;
+
+        self
+    }
 }
 
 #[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
 impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
     #[cfg(feature = "unprivileged")]
-    /// mount the filesystem without root permission. This function will block until the filesystem
-    /// is unmounted.
+    /// mount the filesystem without root permission. This function will return a [`MountHandle`]
+    /// after the filesystem is mounted, the filesystem is running in background.
+    ///
+    /// [`MountHandle`]: MountHandle
     pub async fn mount_with_unprivileged<P: AsRef<Path>>(
         mut self,
         fs: FS,
         mount_path: P,
-    ) -> IoResult<()> {
-        if !self.mount_options.nonempty
-            && read_dir(mount_path.as_ref()).await?.next().await.is_some()
-        {
+    ) -> IoResult<MountHandle<FS>> {
+        if !self.mount_options.nonempty && !is_empty_dir(mount_path.as_ref()).await? {
             return Err(IoError::new(
                 ErrorKind::AlreadyExists,
                 "mount point is not empty",
             ));
         }
 
+        // the absolute path is used to abort the connection, resolve it before mount because the
+        // mount point can't be accessed after the filesystem stops responding
+        let absolute_mount_path = canonicalize(mount_path.as_ref()).await?;
+
         let fuse_connection =
             FuseConnection::new_with_unprivileged(self.mount_options.clone(), mount_path.as_ref())
                 .await?;
@@ -107,68 +203,189 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
 
         debug!("mount {:?} success", mount_path.as_ref());
 
-        self.inner_mount().await
+        Ok(MountHandle::new(
+            mount_path.as_ref().to_path_buf(),
+            self.kernel_config.clone(),
+            true,
+            spawn(self.inner_mount(absolute_mount_path, true)),
+        ))
     }
 
-    /// mount the filesystem. This function will block until the filesystem is unmounted.
-    pub async fn mount<P: AsRef<Path>>(mut self, fs: FS, mount_path: P) -> IoResult<()> {
-        let mut mount_options = self.mount_options.clone();
-
-        if !mount_options.nonempty && read_dir(mount_path.as_ref()).await?.next().await.is_some() {
+    /// mount the filesystem. This function will return a [`MountHandle`] after the filesystem is
+    /// mounted, the filesystem is running in background.
+    ///
+    /// [`MountHandle`]: MountHandle
+    pub async fn mount<P: AsRef<Path>>(
+        mut self,
+        fs: FS,
+        mount_path: P,
+    ) -> IoResult<MountHandle<FS>> {
+        if !self.mount_options.nonempty && !is_empty_dir(mount_path.as_ref()).await? {
             return Err(IoError::new(
                 ErrorKind::AlreadyExists,
                 "mount point is not empty",
             ));
         }
 
-        let fuse_connection = FuseConnection::new().await?;
+        // the absolute path is used to abort the connection, resolve it before mount because the
+        // mount point can't be accessed after the filesystem stops responding
+        let absolute_mount_path = canonicalize(mount_path.as_ref()).await?;
 
-        let fd = fuse_connection.as_raw_fd();
+        let fuse_connection =
+            FuseConnection::new(self.mount_options.clone(), mount_path.as_ref()).await?;
 
-        let options = mount_options.build(fd);
+        self.fuse_connection.replace(Arc::new(fuse_connection));
 
-        let fs_name = if let Some(fs_name) = mount_options.fs_name.as_ref() {
-            Some(fs_name.as_str())
-        } else {
-            Some("fuse")
-        };
+        self.filesystem.replace(Arc::new(fs));
+
+        debug!("mount {:?} success", mount_path.as_ref());
+
+        Ok(MountHandle::new(
+            mount_path.as_ref().to_path_buf(),
+            self.kernel_config.clone(),
+            #[cfg(feature = "unprivileged")]
+            false,
+            spawn(self.inner_mount(
+                absolute_mount_path,
+                #[cfg(feature = "unprivileged")]
+                false,
+            )),
+        ))
+    }
+
+    async fn inner_mount(
+        mut self,
+        mount_path: PathBuf,
+        #[cfg(feature = "unprivileged")] unprivileged: bool,
+    ) -> IoResult<FS> {
+        let fuse_connection = self.fuse_connection.take().unwrap();
+
+        let fs = self.filesystem.take().expect("filesystem not init");
+
+        let receiver = self.response_receiver.take().unwrap();
+
+        let mut panic_receiver = self.panic_receiver.take().unwrap();
+
+        let mut running_task_receiver = self.running_task_receiver.take().unwrap();
+
+        let worker_channels = self.mount_options.worker_channels.unwrap_or(1);
+
+        let mut channels = Vec::with_capacity(worker_channels);
 
-        debug!("mount options {:?}", options);
+        channels.push((
+            fuse_connection.clone(),
+            self.response_sender.clone(),
+            receiver,
+        ));
 
-        if let Err(err) = mount::mount(
-            fs_name,
-            mount_path.as_ref(),
-            Some("fuse"),
-            MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
-            Some(options.as_os_str()),
-        ) {
-            error!("mount {:?} failed", mount_path.as_ref());
+        for _ in 1..worker_channels {
+            let cloned_connection = fuse_connection.try_clone().await?;
 
-            return Err(io_error_from_nix_error(err));
+            let (sender, receiver) = channel(
+                self.mount_options
+                    .reply_queue_size
+                    .unwrap_or(DEFAULT_REPLY_QUEUE_SIZE),
+            );
+            let sender = ReplySender::new(sender, self.metrics.reply_queue_depth());
+
+            channels.push((Arc::new(cloned_connection), sender, receiver));
         }
 
-        self.fuse_connection.replace(Arc::new(fuse_connection));
+        debug!("start {} worker channels", channels.len());
 
-        self.filesystem.replace(Arc::new(fs));
+        let destroyed = AtomicBool::new(false);
 
-        debug!("mount {:?} success", mount_path.as_ref());
+        // the channel tasks borrow the session, stop them before the session is changed
+        {
+            let channel_tasks = channels.into_iter().map(
+                |(fuse_connection, response_sender, response_receiver)| {
+                    self.run_channel(
+                        fuse_connection,
+                        response_sender,
+                        response_receiver,
+                        fs.clone(),
+                        &destroyed,
+                    )
+                },
+            );
+
+            let channel_tasks = future::try_join_all(channel_tasks).fuse();
+
+            pin_mut!(channel_tasks);
 
-        self.inner_mount().await
-    }
+            select! {
+                result = channel_tasks => {
+                    result?;
+                }
 
-    async fn inner_mount(&mut self) -> IoResult<()> {
-        let fuse_write_connection = self.fuse_connection.as_ref().unwrap().clone();
+                handler_panic = panic_receiver.next() => {
+                    // the session holds a sender, so the receiver never ends
+                    let HandlerPanic { opcode, unique } = handler_panic.unwrap();
 
-        let receiver = self.response_receiver.take().unwrap();
+                    error!(
+                        "abort the session because request handler panicked, opcode {} unique {}",
+                        opcode, unique
+                    );
+
+                    // abort the connection first, so the processes waiting for the replies won't
+                    // hang, then detach the filesystem from the mount point
+                    if let Err(err) = abort_connection(mount_path.clone()).await {
+                        error!("abort fuse connection of {:?} failed {}", mount_path, err);
+                    }
+
+                    if let Err(err) = unmount(
+                        mount_path.clone(),
+                        true,
+                        #[cfg(feature = "unprivileged")]
+                        unprivileged,
+                    )
+                    .await
+                    {
+                        error!("unmount {:?} failed {}", mount_path, err);
+                    }
+
+                    return Err(IoError::other(format!(
+                        "request handler panicked, opcode {} unique {}",
+                        opcode, unique
+                    )));
+                }
+            }
+        }
+
+        debug!("all worker channels stopped, wait for running requests");
+
+        // every spawned request task holds a running task sender, the receiver ends when all of
+        // them finish and release the filesystem
+        self.running_task_sender.take();
+
+        while running_task_receiver.next().await.is_some() {}
+
+        Arc::try_unwrap(fs).map_err(|_| IoError::other("filesystem is still in use"))
+    }
 
-        let dispatch_task = self.dispatch().fuse();
+    async fn run_channel(
+        &self,
+        fuse_connection: Arc<FuseConnection>,
+        response_sender: ReplySender,
+        response_receiver: Receiver<ReplyBuf>,
+        fs: Arc<FS>,
+        destroyed: &AtomicBool,
+    ) -> IoResult<()> {
+        let fuse_write_connection = fuse_connection.clone();
+        let metrics = self.metrics.clone();
+        // the reply task may outlive the session, it can't keep the filesystem
+        let weak_fs = Arc::downgrade(&fs);
+
+        let dispatch_task = self
+            .dispatch(fuse_connection, response_sender, fs, destroyed)
+            .fuse();
 
         pin_mut!(dispatch_task);
 
         #[cfg(feature = "async-std-runtime")]
         {
             let reply_task = async_std::task::spawn(async move {
-                Self::reply_fuse(fuse_write_connection, receiver).await
+                Self::reply_fuse(fuse_write_connection, response_receiver, metrics, weak_fs).await
             })
             .fuse();
 
@@ -191,11 +408,10 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
 
         #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
         {
-            let reply_task =
-                tokio::spawn(
-                    async move { Self::reply_fuse(fuse_write_connection, receiver).await },
-                )
-                .fuse();
+            let reply_task = tokio::spawn(async move {
+                Self::reply_fuse(fuse_write_connection, response_receiver, metrics, weak_fs).await
+            })
+            .fuse();
 
             pin_mut!(reply_task);
 
@@ -219,12 +435,46 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
 
     async fn reply_fuse(
         fuse_connection: Arc<FuseConnection>,
-        mut response_receiver: UnboundedReceiver<Vec<u8>>,
+        mut response_receiver: Receiver<ReplyBuf>,
+        metrics: Metrics,
+        fs: Weak<FS>,
     ) -> IoResult<()> {
         while let Some(response) = response_receiver.next().await {
-            let n = response.len();
+            metrics.reply_dequeued();
+
+            // remember the lookups before the kernel takes the reply, so the forget of them
+            // can't be handled before
+            let fs = if response.lookups().is_empty() {
+                None
+            } else {
+                fs.upgrade()
+            };
 
-            if let Err((_, err)) = fuse_connection.write(response, n).await {
+            if let Some(fs) = &fs {
+                for &inode in response.lookups() {
+                    fs.remember(inode);
+                }
+            }
+
+            let result = fuse_connection.writev(&response.io_slices()).await;
+
+            metrics.replied(&response);
+
+            if let (Err(_), Some(fs)) = (&result, fs) {
+                // the session forgets the lookups which the kernel doesn't take
+                let request = Request {
+                    unique: 0,
+                    uid: 0,
+                    gid: 0,
+                    pid: 0,
+                };
+
+                for &inode in response.lookups() {
+                    fs.forget(request, inode, 1).await;
+                }
+            }
+
+            if let Err(err) = result {
                 if err.kind() == ErrorKind::NotFound {
                     warn!(
                         "may reply interrupted fuse request, ignore this error {}",
@@ -243,27 +493,92 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
         Ok(())
     }
 
-    async fn dispatch(&mut self) -> IoResult<()> {
-        let mut buffer = vec![0; BUFFER_SIZE];
+    async fn dispatch(
+        &self,
+        fuse_connection: Arc<FuseConnection>,
+        response_sender: ReplySender,
+        fs: Arc<FS>,
+        destroyed: &AtomicBool,
+    ) -> IoResult<()> {
+        let max_write = self
+            .mount_options
+            .max_write
+            .unwrap_or(MAX_WRITE_SIZE as u32);
+
+        let buffer_pool = BufferPool::new(max_write as usize + BUFFER_EXTRA_SIZE);
+
+        let reply_slots = Arc::new(Semaphore::new(
+            self.mount_options
+                .reply_queue_size
+                .unwrap_or(DEFAULT_REPLY_QUEUE_SIZE),
+        ));
+
+        let mut splice_pipe = None;
+        let reply_pipes = Arc::new(PipePool::default());
+        let mut splice_pipe_failed = false;
+
+        loop {
+            if splice_pipe.is_none()
+                && !splice_pipe_failed
+                && self.splice_read.load(Ordering::Relaxed)
+            {
+                let kernel_config = self
+                    .kernel_config
+                    .lock()
+                    .unwrap()
+                    .expect("kernel config is set in init");
+
+                match Pipe::new(splice_pipe_size(&kernel_config)) {
+                    Err(err) => {
+                        warn!("create splice pipe failed {}, disable splice read", err);
+
+                        splice_pipe_failed = true;
+                    }
 
-        let fuse_connection = self.fuse_connection.take().unwrap();
+                    Ok(pipe) => {
+                        splice_pipe.replace(pipe);
+                    }
+                }
+            }
 
-        let fs = self.filesystem.take().expect("filesystem not init");
+            // stop reading requests until the reply queue has a slot, so the kernel throttles the
+            // requests when the replies can't be written in time
+            let reply_slot: ReplySlot = Arc::new(reply_slots.acquire_arc().await);
+
+            let mut buffer = buffer_pool.get();
+
+            let mut write_payload = None;
+
+            let result = if splice_pipe.is_some() {
+                splice_read_request(
+                    &fuse_connection,
+                    &mut splice_pipe,
+                    &mut buffer,
+                    CompatSizes::new(self.proto_minor.load(Ordering::Relaxed)).write_in,
+                    &mut write_payload,
+                )
+                .await
+            } else {
+                fuse_connection.read(&mut buffer).await
+            };
 
-        'dispatch_loop: loop {
-            let mut data = match fuse_connection.read(buffer).await {
-                Err((_, err)) => {
+            let request_data = match result {
+                Err(err) => {
                     if let Some(errno) = err.raw_os_error() {
                         if errno == libc::ENODEV {
                             debug!("read from /dev/fuse failed with ENODEV, call destroy now");
 
-                            fs.destroy(Request {
-                                unique: 0,
-                                uid: 0,
-                                gid: 0,
-                                pid: 0,
-                            })
-                            .await;
+                            if !destroyed.swap(true, Ordering::SeqCst) {
+                                self.open_handles.report_leaks();
+
+                                fs.destroy(Request {
+                                    unique: 0,
+                                    uid: 0,
+                                    gid: 0,
+                                    pid: 0,
+                                })
+                                .await;
+                            }
 
                             return Ok(());
                         }
@@ -274,16 +589,12 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
                     return Err(err);
                 }
 
-                Ok((buf, n)) => {
-                    buffer = buf;
-
-                    &buffer[..n]
-                }
+                Ok(n) => buffer_pool.freeze(buffer, n),
             };
 
-            let in_header = match BINARY.deserialize::<fuse_in_header>(data) {
+            let in_header = match decode_header(&request_data) {
                 Err(err) => {
-                    error!("deserialize fuse_in_header failed {}", err);
+                    error!("decode fuse_in_header failed {}", err);
 
                     continue;
                 }
@@ -293,145 +604,268 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
 
             let request = Request::from(&in_header);
 
-            let opcode = match fuse_opcode::try_from(in_header.opcode) {
-                Err(err) => {
-                    debug!("receive unknown opcode {}", err.0);
+            let compat_sizes = CompatSizes::new(self.proto_minor.load(Ordering::Relaxed));
 
-                    reply_error(libc::ENOSYS.into(), request, self.response_sender.clone());
+            // the payload of a spliced write request is kept in the pipe
+            let payload_len = write_payload.as_ref().map_or(0, WriteData::len);
 
-                    continue;
-                }
-                Ok(opcode) => opcode,
-            };
+            let operation =
+                match decode_operation(&in_header, &request_data, payload_len, compat_sizes) {
+                    Err(DecodeError::UnknownOpcode(opcode)) => {
+                        debug!("receive unknown opcode {}", opcode);
 
-            debug!("receive opcode {}", opcode);
+                        reply_error(
+                            libc::ENOSYS.into(),
+                            request,
+                            response_sender.for_request(None, Some(reply_slot)),
+                        );
 
-            // data = &data[FUSE_IN_HEADER_SIZE..in_header.len as usize - FUSE_IN_HEADER_SIZE];
-            data = &data[FUSE_IN_HEADER_SIZE..];
-            data = &data[..in_header.len as usize - FUSE_IN_HEADER_SIZE];
+                        continue;
+                    }
 
-            match opcode {
-                fuse_opcode::FUSE_INIT => {
-                    let init_in = match BINARY.deserialize::<fuse_init_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_init_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                    Err(err) => {
+                        error!(
+                            "decode request failed {}, request unique {}",
+                            err, request.unique
+                        );
 
-                            let init_out_header = fuse_out_header {
-                                len: FUSE_OUT_HEADER_SIZE as u32,
-                                error: libc::EINVAL,
-                                unique: request.unique,
-                            };
+                        match fuse_opcode::try_from(in_header.opcode) {
+                            Ok(fuse_opcode::FUSE_INIT) => {
+                                let init_out_header_data =
+                                    out_header(request.unique, -err.errno(), 0);
 
-                            let init_out_header_data =
-                                BINARY.serialize(&init_out_header).expect("won't happened");
+                                if let Err(err) = fuse_connection.write(&init_out_header_data).await
+                                {
+                                    error!("write error init out data to /dev/fuse failed {}", err);
+                                }
 
-                            if let Err((_, err)) = fuse_connection
-                                .write(init_out_header_data, FUSE_OUT_HEADER_SIZE)
-                                .await
-                            {
-                                error!("write error init out data to /dev/fuse failed {}", err);
+                                return Err(IoError::from_raw_os_error(err.errno()));
                             }
 
-                            return Err(IoError::from_raw_os_error(libc::EINVAL));
+                            // these requests don't need a reply
+                            Ok(fuse_opcode::FUSE_FORGET)
+                            | Ok(fuse_opcode::FUSE_BATCH_FORGET)
+                            | Ok(fuse_opcode::FUSE_NOTIFY_REPLY) => {}
+
+                            _ => reply_error(
+                                err.errno().into(),
+                                request,
+                                response_sender.for_request(None, Some(reply_slot)),
+                            ),
                         }
 
-                        Ok(init_in) => init_in,
-                    };
+                        continue;
+                    }
+
+                    Ok(operation) => operation,
+                };
+
+            let opcode = operation.opcode();
+
+            debug!("receive opcode {}", opcode);
+
+            // the request which may wait for a later request doesn't hold the slot, otherwise the
+            // later request may be never read
+            let reply_slot = Some(reply_slot).filter(|_| !waits_for_later_request(opcode));
+
+            // the replies of the request carry its timer and its reply queue slot
+            let response_sender =
+                response_sender.for_request(self.metrics.request_received(opcode), reply_slot);
+
+            let order_ticket = self.request_order.enter(&operation);
+
+            // stop reading requests until the handler can run, so the kernel throttles the
+            // requests. The request waiting for the previous ordered requests takes the queued
+            // permits and acquires the running permits after the wait, otherwise the waiters may
+            // hold all running permits and the requests they wait for can't run
+            let queued = order_ticket.is_waiting();
+
+            let permits = if queued {
+                self.concurrency_limits.acquire_queued(opcode).await
+            } else {
+                self.concurrency_limits.acquire(opcode).await
+            };
+
+            let in_flight = InFlight {
+                permits,
+                queued,
+                concurrency_limits: self.concurrency_limits.clone(),
+                order_ticket,
+                requests: self.in_flight_requests.clone(),
+                request,
+                opcode,
+                inode: in_header.nodeid,
+                interruptible: fs.interruptible(opcode),
+                deadline: self.deadlines.get(&opcode).copied(),
+                deadline_errno: self.deadline_errno,
+                response_sender: response_sender.clone(),
+                panic_policy: self.mount_options.panic_policy,
+                panic_sender: self.panic_sender.clone(),
+                running_task: self.running_task_sender.clone(),
+                #[cfg(feature = "tracing")]
+                span: tracing::debug_span!(
+                    "fuse_request",
+                    opcode = %opcode,
+                    unique = request.unique,
+                    nodeid = in_header.nodeid,
+                    uid = request.uid,
+                    gid = request.gid,
+                    pid = request.pid,
+                    errno = tracing::field::Empty,
+                    reply_size = tracing::field::Empty,
+                ),
+            };
+
+            match operation {
+                Operation::Init {
+                    major,
+                    minor,
+                    max_readahead: init_max_readahead,
+                    flags: init_flags,
+                } => {
+                    debug!(
+                        "fuse_init major {} minor {} max_readahead {} flags {}",
+                        major, minor, init_max_readahead, init_flags
+                    );
+
+                    if major < FUSE_KERNEL_VERSION {
+                        error!("unsupported fuse protocol version {}.{}", major, minor);
+
+                        let init_out_header_data = out_header(request.unique, -libc::EPROTO, 0);
+
+                        if let Err(err) = fuse_connection.write(&init_out_header_data).await {
+                            error!("write error init out data to /dev/fuse failed {}", err);
+                        }
+
+                        return Err(IoError::from_raw_os_error(libc::EPROTO));
+                    }
+
+                    // the kernel will send a new init request with our major version
+                    if major > FUSE_KERNEL_VERSION {
+                        debug!(
+                            "kernel fuse protocol major {} is newer, reply our version",
+                            major
+                        );
+
+                        let init_out = fuse_init_out {
+                            major: FUSE_KERNEL_VERSION,
+                            minor: FUSE_KERNEL_MINOR_VERSION,
+                            max_readahead: 0,
+                            flags: 0,
+                            max_background: 0,
+                            congestion_threshold: 0,
+                            max_write: 0,
+                            time_gran: 0,
+                            max_pages: 0,
+                            map_alignment: 0,
+                            unused: [0; 8],
+                        };
+
+                        let mut data = out_header(request.unique, 0, FUSE_INIT_OUT_SIZE);
+
+                        binary()
+                            .serialize_into(&mut data, &init_out)
+                            .expect("won't happened");
+
+                        if let Err(err) = fuse_connection.write(&data).await {
+                            error!("write init out data to /dev/fuse failed {}", err);
+
+                            return Err(err);
+                        }
 
-                    debug!("fuse_init {:?}", init_in);
+                        continue;
+                    }
 
                     let mut reply_flags = 0;
 
-                    if init_in.flags & FUSE_ASYNC_READ > 0 {
+                    if init_flags & FUSE_ASYNC_READ > 0 {
                         debug!("enable FUSE_ASYNC_READ");
 
                         reply_flags |= FUSE_ASYNC_READ;
                     }
 
                     #[cfg(feature = "file-lock")]
-                    if init_in.flags & FUSE_POSIX_LOCKS > 0 {
+                    if init_flags & FUSE_POSIX_LOCKS > 0 {
                         debug!("enable FUSE_POSIX_LOCKS");
 
                         reply_flags |= FUSE_POSIX_LOCKS;
                     }
 
-                    if init_in.flags & FUSE_FILE_OPS > 0 {
+                    if init_flags & FUSE_FILE_OPS > 0 {
                         debug!("enable FUSE_FILE_OPS");
 
                         reply_flags |= FUSE_FILE_OPS;
                     }
 
-                    if init_in.flags & FUSE_ATOMIC_O_TRUNC > 0 {
+                    if init_flags & FUSE_ATOMIC_O_TRUNC > 0 {
                         debug!("enable FUSE_ATOMIC_O_TRUNC");
 
                         reply_flags |= FUSE_ATOMIC_O_TRUNC;
                     }
 
-                    if init_in.flags & FUSE_EXPORT_SUPPORT > 0 {
+                    if init_flags & FUSE_EXPORT_SUPPORT > 0 {
                         debug!("enable FUSE_EXPORT_SUPPORT");
 
                         reply_flags |= FUSE_EXPORT_SUPPORT;
                     }
 
-                    if init_in.flags & FUSE_BIG_WRITES > 0 {
+                    if init_flags & FUSE_BIG_WRITES > 0 {
                         debug!("enable FUSE_BIG_WRITES");
 
                         reply_flags |= FUSE_BIG_WRITES;
                     }
 
-                    if init_in.flags & FUSE_DONT_MASK > 0 && self.mount_options.dont_mask {
+                    if init_flags & FUSE_DONT_MASK > 0 && self.mount_options.dont_mask {
                         debug!("enable FUSE_DONT_MASK");
 
                         reply_flags |= FUSE_DONT_MASK;
                     }
 
-                    if init_in.flags & FUSE_SPLICE_WRITE > 0 {
+                    if init_flags & FUSE_SPLICE_WRITE > 0 && self.mount_options.splice_write {
                         debug!("enable FUSE_SPLICE_WRITE");
 
                         reply_flags |= FUSE_SPLICE_WRITE;
                     }
 
-                    if init_in.flags & FUSE_SPLICE_MOVE > 0 {
+                    if init_flags & FUSE_SPLICE_MOVE > 0
+                        && (self.mount_options.splice_read || self.mount_options.splice_write)
+                    {
                         debug!("enable FUSE_SPLICE_MOVE");
 
                         reply_flags |= FUSE_SPLICE_MOVE;
                     }
 
-                    if init_in.flags & FUSE_SPLICE_READ > 0 {
+                    if init_flags & FUSE_SPLICE_READ > 0 && self.mount_options.splice_read {
                         debug!("enable FUSE_SPLICE_READ");
 
                         reply_flags |= FUSE_SPLICE_READ;
                     }
 
                     // posix lock used, maybe we don't need bsd lock
-                    /*if init_in.flags&FUSE_FLOCK_LOCKS>0 {
+                    /*if init_flags&FUSE_FLOCK_LOCKS>0 {
                         reply_flags |= FUSE_FLOCK_LOCKS;
                     }*/
 
-                    /*if init_in.flags & FUSE_HAS_IOCTL_DIR > 0 {
+                    /*if init_flags & FUSE_HAS_IOCTL_DIR > 0 {
                         debug!("enable FUSE_HAS_IOCTL_DIR");
 
                         reply_flags |= FUSE_HAS_IOCTL_DIR;
                     }*/
 
-                    if init_in.flags & FUSE_AUTO_INVAL_DATA > 0 {
+                    if init_flags & FUSE_AUTO_INVAL_DATA > 0 {
                         debug!("enable FUSE_AUTO_INVAL_DATA");
 
                         reply_flags |= FUSE_AUTO_INVAL_DATA;
                     }
 
-                    if init_in.flags & FUSE_DO_READDIRPLUS > 0
-                        || self.mount_options.force_readdir_plus
+                    if init_flags & FUSE_DO_READDIRPLUS > 0 || self.mount_options.force_readdir_plus
                     {
                         debug!("enable FUSE_DO_READDIRPLUS");
 
                         reply_flags |= FUSE_DO_READDIRPLUS;
                     }
 
-                    if init_in.flags & FUSE_READDIRPLUS_AUTO > 0
+                    if init_flags & FUSE_READDIRPLUS_AUTO > 0
                         && !self.mount_options.force_readdir_plus
                     {
                         debug!("enable FUSE_READDIRPLUS_AUTO");
@@ -439,60 +873,55 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
                         reply_flags |= FUSE_READDIRPLUS_AUTO;
                     }
 
-                    if init_in.flags & FUSE_ASYNC_DIO > 0 {
+                    if init_flags & FUSE_ASYNC_DIO > 0 {
                         debug!("enable FUSE_ASYNC_DIO");
 
                         reply_flags |= FUSE_ASYNC_DIO;
                     }
 
-                    if init_in.flags & FUSE_WRITEBACK_CACHE > 0 && self.mount_options.write_back {
+                    if init_flags & FUSE_WRITEBACK_CACHE > 0 && self.mount_options.write_back {
                         debug!("enable FUSE_WRITEBACK_CACHE");
 
                         reply_flags |= FUSE_WRITEBACK_CACHE;
                     }
 
-                    if init_in.flags & FUSE_NO_OPEN_SUPPORT > 0
-                        && self.mount_options.no_open_support
-                    {
+                    if init_flags & FUSE_NO_OPEN_SUPPORT > 0 && self.mount_options.no_open_support {
                         debug!("enable FUSE_NO_OPEN_SUPPORT");
 
                         reply_flags |= FUSE_NO_OPEN_SUPPORT;
                     }
 
-                    if init_in.flags & FUSE_PARALLEL_DIROPS > 0 {
+                    if init_flags & FUSE_PARALLEL_DIROPS > 0 {
                         debug!("enable FUSE_PARALLEL_DIROPS");
 
                         reply_flags |= FUSE_PARALLEL_DIROPS;
                     }
 
-                    if init_in.flags & FUSE_HANDLE_KILLPRIV > 0
-                        && self.mount_options.handle_killpriv
-                    {
+                    if init_flags & FUSE_HANDLE_KILLPRIV > 0 && self.mount_options.handle_killpriv {
                         debug!("enable FUSE_HANDLE_KILLPRIV");
 
                         reply_flags |= FUSE_HANDLE_KILLPRIV;
                     }
 
-                    if init_in.flags & FUSE_POSIX_ACL > 0 && self.mount_options.default_permissions
-                    {
+                    if init_flags & FUSE_POSIX_ACL > 0 && self.mount_options.default_permissions {
                         debug!("enable FUSE_POSIX_ACL");
 
                         reply_flags |= FUSE_POSIX_ACL;
                     }
 
-                    if init_in.flags & FUSE_MAX_PAGES > 0 {
+                    if init_flags & FUSE_MAX_PAGES > 0 {
                         debug!("enable FUSE_MAX_PAGES");
 
                         reply_flags |= FUSE_MAX_PAGES;
                     }
 
-                    if init_in.flags & FUSE_CACHE_SYMLINKS > 0 {
+                    if init_flags & FUSE_CACHE_SYMLINKS > 0 {
                         debug!("enable FUSE_CACHE_SYMLINKS");
 
                         reply_flags |= FUSE_CACHE_SYMLINKS;
                     }
 
-                    if init_in.flags & FUSE_NO_OPENDIR_SUPPORT > 0
+                    if init_flags & FUSE_NO_OPENDIR_SUPPORT > 0
                         && self.mount_options.no_open_dir_support
                     {
                         debug!("enable FUSE_NO_OPENDIR_SUPPORT");
@@ -500,61 +929,66 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
                         reply_flags |= FUSE_NO_OPENDIR_SUPPORT;
                     }
 
-                    if let Err(err) = fs.init(request).await {
-                        let init_out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: err.into(),
-                            unique: request.unique,
-                        };
+                    let max_readahead = match self.mount_options.max_readahead {
+                        None => init_max_readahead,
+                        Some(max_readahead) => max_readahead.min(init_max_readahead),
+                    };
 
-                        let init_out_header_data =
-                            BINARY.serialize(&init_out_header).expect("won't happened");
+                    let max_background = self
+                        .mount_options
+                        .max_background
+                        .unwrap_or(DEFAULT_MAX_BACKGROUND);
 
-                        if let Err((_, err)) = fuse_connection
-                            .write(init_out_header_data, FUSE_OUT_HEADER_SIZE)
-                            .await
-                        {
+                    let congestion_threshold = match self.mount_options.congestion_threshold {
+                        None if self.mount_options.max_background.is_none() => {
+                            DEFAULT_CONGESTION_THRESHOLD
+                        }
+                        None => (max_background as u32 * 3 / 4) as u16,
+                        Some(congestion_threshold) => congestion_threshold,
+                    };
+
+                    let mut kernel_config = KernelConfig {
+                        major,
+                        minor: minor.min(FUSE_KERNEL_MINOR_VERSION),
+                        capable: init_flags,
+                        want: reply_flags,
+                        max_readahead,
+                        max_background,
+                        congestion_threshold,
+                        max_write,
+                        max_write_limit: max_write,
+                        time_gran: self.mount_options.time_gran.unwrap_or(DEFAULT_TIME_GRAN),
+                        max_pages: self.mount_options.max_pages.unwrap_or(DEFAULT_MAX_PAGES),
+                    };
+
+                    if let Err(err) = fs.init(request, &mut kernel_config).await {
+                        let init_out_header_data = out_header(request.unique, err.into(), 0);
+
+                        if let Err(err) = fuse_connection.write(&init_out_header_data).await {
                             error!("write error init out data to /dev/fuse failed {}", err);
                         }
 
                         return Err(IoError::from_raw_os_error(err.0));
                     }
 
-                    let init_out = fuse_init_out {
-                        major: FUSE_KERNEL_VERSION,
-                        minor: FUSE_KERNEL_MINOR_VERSION,
-                        max_readahead: init_in.max_readahead,
-                        flags: reply_flags,
-                        max_background: DEFAULT_MAX_BACKGROUND,
-                        congestion_threshold: DEFAULT_CONGESTION_THRESHOLD,
-                        max_write: MAX_WRITE_SIZE as u32,
-                        time_gran: DEFAULT_TIME_GRAN,
-                        max_pages: DEFAULT_MAX_PAGES,
-                        map_alignment: DEFAULT_MAP_ALIGNMENT,
-                        unused: [0; 8],
-                    };
+                    debug!("fuse init kernel config {:?}", kernel_config);
 
-                    debug!("fuse init out {:?}", init_out);
+                    self.kernel_config.lock().unwrap().replace(kernel_config);
 
-                    let out_header = fuse_out_header {
-                        len: (FUSE_OUT_HEADER_SIZE + FUSE_INIT_OUT_SIZE) as u32,
-                        error: 0,
-                        unique: request.unique,
-                    };
+                    self.proto_minor
+                        .store(kernel_config.minor, Ordering::Relaxed);
 
-                    let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_INIT_OUT_SIZE);
+                    self.splice_read
+                        .store(kernel_config.want & FUSE_SPLICE_READ > 0, Ordering::Relaxed);
+                    self.splice_write.store(
+                        kernel_config.want & FUSE_SPLICE_WRITE > 0,
+                        Ordering::Relaxed,
+                    );
 
-                    BINARY
-                        .serialize_into(&mut data, &out_header)
-                        .expect("won't happened");
-                    BINARY
-                        .serialize_into(&mut data, &init_out)
-                        .expect("won't happened");
+                    let data =
+                        Response::Init(kernel_config).encode(request.unique, kernel_config.minor);
 
-                    if let Err((_, err)) = fuse_connection
-                        .write(data, FUSE_OUT_HEADER_SIZE + FUSE_INIT_OUT_SIZE)
-                        .await
-                    {
+                    if let Err(err) = fuse_connection.write(&data).await {
                         error!("write init out data to /dev/fuse failed {}", err);
 
                         return Err(err);
@@ -563,2795 +997,2078 @@ impl<FS: Filesystem + Send + Sync + 'static> Session<FS> {
                     debug!("fuse init done");
                 }
 
-                fuse_opcode::FUSE_DESTROY => {
+                Operation::Destroy => {
                     debug!("receive fuse destroy");
 
-                    fs.destroy(request).await;
+                    if !destroyed.swap(true, Ordering::SeqCst) {
+                        self.open_handles.report_leaks();
+
+                        fs.destroy(request).await;
+                    }
 
                     debug!("fuse destroyed");
 
                     return Ok(());
                 }
 
-                fuse_opcode::FUSE_LOOKUP => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!("lookup body has no null, request unique {}", request.unique);
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                Operation::Lookup { parent, name } => {
+                    let mut resp_sender = response_sender.clone();
 
+                    let name = name.to_owned();
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
                             "lookup unique {} name {:?} in parent {}",
-                            request.unique, name, in_header.nodeid
+                            request.unique, name, parent
                         );
 
-                        let data = match fs.lookup(request, in_header.nodeid, &name).await {
-                            Err(err) => {
-                                let out_header = fuse_out_header {
-                                    len: FUSE_OUT_HEADER_SIZE as u32,
-                                    error: err.into(),
-                                    unique: request.unique,
-                                };
-
-                                BINARY.serialize(&out_header).expect("won't happened")
-                            }
+                        let response = match fs.lookup(request, parent, &name).await {
+                            Err(err) => Response::Error(err),
 
                             Ok(entry) => {
-                                let entry_out: fuse_entry_out = entry.into();
-
-                                debug!("lookup response {:?}", entry_out);
+                                debug!("lookup response {:?}", entry);
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
-
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE);
-
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &entry_out)
-                                    .expect("won't happened");
-
-                                data
+                                Response::Entry(entry)
                             }
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_FORGET => {
-                    let forget_in = match BINARY.deserialize::<fuse_forget_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_forget_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            // no need to reply
-                            continue;
-                        }
-
-                        Ok(forget_in) => forget_in,
-                    };
-
+                Operation::Forget { inode, nlookup } => {
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn_without_reply(async move {
                         debug!(
                             "forget unique {} inode {} nlookup {}",
-                            request.unique, in_header.nodeid, forget_in.nlookup
+                            request.unique, inode, nlookup
                         );
 
-                        fs.forget(request, in_header.nodeid, forget_in.nlookup)
-                            .await
+                        fs.forget(request, inode, nlookup).await
                     });
                 }
 
-                fuse_opcode::FUSE_GETATTR => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let getattr_in = match BINARY.deserialize::<fuse_getattr_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_forget_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error_in_place(libc::EINVAL.into(), request, resp_sender).await;
-
-                            continue;
-                        }
-
-                        Ok(getattr_in) => getattr_in,
-                    };
+                Operation::Getattr { inode, fh, flags } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "getattr unique {} inode {}",
-                            request.unique, in_header.nodeid
-                        );
-
-                        let fh = if getattr_in.getattr_flags & FUSE_GETATTR_FH > 0 {
-                            Some(getattr_in.fh)
-                        } else {
-                            None
-                        };
-
-                        let data = match fs
-                            .getattr(request, in_header.nodeid, fh, getattr_in.getattr_flags)
-                            .await
-                        {
-                            Err(err) => {
-                                let out_header = fuse_out_header {
-                                    len: FUSE_OUT_HEADER_SIZE as u32,
-                                    error: err.into(),
-                                    unique: request.unique,
-                                };
-
-                                BINARY.serialize(&out_header).expect("won't happened")
-                            }
+                    in_flight.spawn(async move {
+                        debug!("getattr unique {} inode {}", request.unique, inode);
 
-                            Ok(attr) => {
-                                let attr_out = fuse_attr_out {
-                                    attr_valid: attr.ttl.as_secs(),
-                                    attr_valid_nsec: attr.ttl.subsec_nanos(),
-                                    dummy: getattr_in.dummy,
-                                    attr: attr.attr.into(),
-                                };
-
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ATTR_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
-
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ATTR_OUT_SIZE);
-
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &attr_out)
-                                    .expect("won't happened");
-
-                                data
-                            }
+                        let response = match fs.getattr(request, inode, fh, flags).await {
+                            Err(err) => Response::Error(err),
+                            Ok(attr) => Response::Attr(attr),
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_SETATTR => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let setattr_in = match BINARY.deserialize::<fuse_setattr_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_setattr_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(setattr_in) => setattr_in,
-                    };
+                Operation::Setattr {
+                    inode,
+                    fh,
+                    set_attr,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
-                        let set_attr = SetAttr::from(&setattr_in);
-
-                        let fh = if setattr_in.valid & FATTR_FH > 0 {
-                            Some(setattr_in.fh)
-                        } else {
-                            None
-                        };
-
+                    in_flight.spawn(async move {
                         debug!(
                             "setattr unique {} inode {} set_attr {:?}",
-                            request.unique, in_header.nodeid, set_attr
+                            request.unique, inode, set_attr
                         );
 
-                        let data = match fs.setattr(request, in_header.nodeid, fh, set_attr).await {
-                            Err(err) => {
-                                let out_header = fuse_out_header {
-                                    len: FUSE_OUT_HEADER_SIZE as u32,
-                                    error: err.into(),
-                                    unique: request.unique,
-                                };
-
-                                BINARY.serialize(&out_header).expect("won't happened")
-                            }
+                        let response = match fs.setattr(request, inode, fh, set_attr).await {
+                            Err(err) => Response::Error(err),
+                            Ok(attr) => Response::Attr(attr),
+                        };
 
-                            Ok(attr) => {
-                                let attr_out: fuse_attr_out = attr.into();
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ATTR_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                Operation::Readlink { inode } => {
+                    let mut resp_sender = response_sender.clone();
+                    let fs = fs.clone();
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ATTR_OUT_SIZE);
+                    in_flight.spawn(async move {
+                        debug!("readlink unique {} inode {}", request.unique, inode);
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &attr_out)
-                                    .expect("won't happened");
+                        let response = match fs.readlink(request, inode).await {
+                            Err(err) => Response::Error(err),
 
-                                data
-                            }
+                            Ok(data) => match read_reply_data(data, u32::MAX).await {
+                                Err(err) => Response::Error(err.into()),
+                                Ok(content) => Response::Data(content),
+                            },
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_READLINK => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Symlink { parent, name, link } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
+                    let link = link.to_owned();
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "readlink unique {} inode {}",
-                            request.unique, in_header.nodeid
+                            "symlink unique {} parent {} name {:?} link {:?}",
+                            request.unique, parent, name, link
                         );
 
-                        let data = match fs.readlink(request, in_header.nodeid).await {
-                            Err(err) => {
-                                let out_header = fuse_out_header {
-                                    len: FUSE_OUT_HEADER_SIZE as u32,
-                                    error: err.into(),
-                                    unique: request.unique,
-                                };
+                        let response = match fs.symlink(request, parent, &name, &link).await {
+                            Err(err) => Response::Error(err),
+                            Ok(entry) => Response::Entry(entry),
+                        };
 
-                                BINARY.serialize(&out_header).expect("won't happened")
-                            }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
+
+                Operation::Mknod {
+                    parent,
+                    name,
+                    mode,
+                    rdev,
+                    umask,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                            Ok(data) => {
-                                let content = data.data.as_ref().as_ref();
+                    in_flight.spawn(async move {
+                        debug!(
+                            "mknod unique {} parent {} name {:?} mode {} rdev {} umask {}",
+                            request.unique, parent, name, mode, rdev, umask
+                        );
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + content.len()) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                        let response = match fs.mknod(request, parent, &name, mode, rdev).await {
+                            Err(err) => Response::Error(err),
+                            Ok(entry) => Response::Entry(entry),
+                        };
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + content.len());
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
+                Operation::Mkdir {
+                    parent,
+                    name,
+                    mode,
+                    umask,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                                data.extend_from_slice(content);
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                                data
-                            }
+                    in_flight.spawn(async move {
+                        debug!(
+                            "mkdir unique {} parent {} name {:?} mode {} umask {}",
+                            request.unique, parent, name, mode, umask
+                        );
+
+                        let response = match fs.mkdir(request, parent, &name, mode, umask).await {
+                            Err(err) => Response::Error(err),
+                            Ok(entry) => Response::Entry(entry),
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_SYMLINK => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Unlink { parent, name } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let (name, first_null_index) = match get_first_null_position(data) {
-                        None => {
-                            error!("symlink has no null, request unique {}", request.unique);
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                            reply_error_in_place(libc::EINVAL.into(), request, resp_sender).await;
+                    in_flight.spawn(async move {
+                        debug!(
+                            "unlink unique {} parent {} name {:?}",
+                            request.unique, parent, name
+                        );
 
-                            continue;
-                        }
+                        let response = match fs.unlink(request, parent, &name).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        Some(index) => (OsString::from_vec((&data[..index]).to_vec()), index),
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                    data = &data[first_null_index + 1..];
+                Operation::Rmdir { parent, name } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let link_name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "symlink has no second null, request unique {}",
-                                request.unique
-                            );
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    in_flight.spawn(async move {
+                        debug!(
+                            "rmdir unique {} parent {} name {:?}",
+                            request.unique, parent, name
+                        );
 
-                            continue;
-                        }
+                        let response = match fs.rmdir(request, parent, &name).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
+                Operation::Rename {
+                    parent,
+                    name,
+                    new_parent,
+                    new_name,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
+                    let new_name = new_name.to_owned();
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "symlink unique {} parent {} name {:?} link {:?}",
-                            request.unique, in_header.nodeid, name, link_name
+                            "rename unique {} parent {} name {:?} new parent {} new name {:?}",
+                            request.unique, parent, name, new_parent, new_name
                         );
 
-                        let data = match fs
-                            .symlink(request, in_header.nodeid, &name, &link_name)
+                        let response = match fs
+                            .rename(request, parent, &name, new_parent, &new_name)
                             .await
                         {
-                            Err(err) => {
-                                let out_header = fuse_out_header {
-                                    len: FUSE_OUT_HEADER_SIZE as u32,
-                                    error: err.into(),
-                                    unique: request.unique,
-                                };
-
-                                BINARY.serialize(&out_header).expect("won't happened")
-                            }
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                            Ok(entry) => {
-                                let entry_out: fuse_entry_out = entry.into();
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                Operation::Link {
+                    inode,
+                    new_parent,
+                    new_name,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE);
+                    let new_name = new_name.to_owned();
+                    let fs = fs.clone();
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &entry_out)
-                                    .expect("won't happened");
+                    in_flight.spawn(async move {
+                        debug!(
+                            "link unique {} inode {} new parent {} new name {:?}",
+                            request.unique, inode, new_parent, new_name
+                        );
 
-                                data
-                            }
+                        let response = match fs.link(request, inode, new_parent, &new_name).await {
+                            Err(err) => Response::Error(err),
+                            Ok(entry) => Response::Entry(entry),
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_MKNOD => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let mknod_in = match BINARY.deserialize::<fuse_mknod_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_mknod_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                Operation::Open { inode, flags } => {
+                    let mut resp_sender = response_sender.clone();
 
-                            continue;
-                        }
-
-                        Ok(mknod_in) => mknod_in,
-                    };
+                    let fs = fs.clone();
+                    let open_handles = self.open_handles.clone();
 
-                    data = &data[FUSE_MKNOD_IN_SIZE..];
+                    in_flight.spawn(async move {
+                        debug!(
+                            "open unique {} inode {} flags {}",
+                            request.unique, inode, flags
+                        );
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_mknod_in body doesn't have null, request unique {}",
-                                request.unique
-                            );
+                        let response = match fs.open(request, inode, flags).await {
+                            Err(err) => Response::Error(err),
+                            Ok(opened) => {
+                                open_handles.opened(opened.fh, false);
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                                Response::Open(opened)
+                            }
+                        };
 
-                            continue;
-                        }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                Operation::Read {
+                    inode,
+                    fh,
+                    offset,
+                    size,
+                    flags,
+                    lock_owner,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
+                    let fuse_connection = fuse_connection.clone();
+                    let reply_pipes = reply_pipes.clone();
+                    let splice_write = self.splice_write.load(Ordering::Relaxed);
+                    let metrics = self.metrics.clone();
+                    let request_timer = resp_sender.request_timer();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "mknod unique {} parent {} name {:?} {:?}",
-                            request.unique, in_header.nodeid, name, mknod_in
+                            "read unique {} inode {} fh {} offset {} size {} flags {} lock_owner {:?}",
+                            request.unique, inode, fh, offset, size, flags, lock_owner
                         );
 
-                        match fs
-                            .mknod(
+                        let reply_data = match fs
+                            .read(
                                 request,
-                                in_header.nodeid,
-                                &name,
-                                mknod_in.mode,
-                                mknod_in.rdev,
+                                inode,
+                                fh,
+                                offset,
+                                size,
                             )
                             .await
                         {
                             Err(err) => {
                                 reply_error_in_place(err, request, resp_sender).await;
-                            }
-
-                            Ok(entry) => {
-                                let entry_out: fuse_entry_out = entry.into();
-
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE);
-
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &entry_out)
-                                    .expect("won't happened");
+                                return;
+                            }
 
-                                let _ = resp_sender.send(data).await;
+                            Ok(reply_data) => reply_data,
+                        };
+
+                        if let ReplyData::Fd { fd, offset, len } = &reply_data {
+                            if splice_write
+                                && splice_reply(
+                                    fuse_connection,
+                                    reply_pipes,
+                                    metrics,
+                                    request_timer,
+                                    resp_sender.reply_claim(),
+                                    request,
+                                    fd.clone(),
+                                    *offset,
+                                    (*len).min(size) as usize,
+                                )
+                                .await
+                            {
+                                return;
                             }
                         }
-                    });
-                }
-
-                fuse_opcode::FUSE_MKDIR => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let mkdir_in = match BINARY.deserialize::<fuse_mkdir_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_mknod_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
 
-                            continue;
-                        }
+                        let reply_data = match read_reply_data(reply_data, size).await {
+                            Err(err) => {
+                                reply_error_in_place(err.into(), request, resp_sender).await;
 
-                        Ok(mkdir_in) => mkdir_in,
-                    };
+                                return;
+                            }
 
-                    data = &data[FUSE_MKDIR_IN_SIZE..];
+                            Ok(reply_data) => reply_data,
+                        };
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "deserialize fuse_mknod_in doesn't have null unique {}",
-                                request.unique
-                            );
+                        let len = reply_data.as_ref().as_ref().len().min(size as _);
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                        let header = out_header(request.unique, 0, len);
 
-                            continue;
-                        }
+                        let _ = resp_sender
+                            .send(ReplyBuf::with_payload(header, reply_data, len))
+                            .await;
+                    });
+                }
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
+                Operation::Write {
+                    inode,
+                    fh,
+                    offset,
+                    size,
+                    write_flags,
+                    flags,
+                    lock_owner,
+                    data,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let data = match write_payload.take() {
+                        None => WriteData::from(request_data.slice_ref(data)),
+                        Some(payload) => payload,
                     };
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "mkdir unique {} parent {} name {:?} {:?}",
-                            request.unique, in_header.nodeid, name, mkdir_in
+                            "write unique {} inode {} fh {} offset {} size {} write_flags {} flags {} lock_owner {:?}",
+                            request.unique,
+                            inode,
+                            fh,
+                            offset,
+                            size,
+                            write_flags,
+                            flags,
+                            lock_owner
                         );
 
-                        match fs
-                            .mkdir(
+                        let response = match fs
+                            .write(
                                 request,
-                                in_header.nodeid,
-                                &name,
-                                mkdir_in.mode,
-                                mkdir_in.umask,
+                                inode,
+                                fh,
+                                offset,
+                                data,
+                                flags,
                             )
                             .await
                         {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-                            }
+                            Err(err) => Response::Error(err),
+                            Ok(reply_write) => Response::Write(reply_write),
+                        };
 
-                            Ok(entry) => {
-                                let entry_out: fuse_entry_out = entry.into();
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                Operation::Statfs { inode } => {
+                    let mut resp_sender = response_sender.clone();
+                    let fs = fs.clone();
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE);
+                    in_flight.spawn(async move {
+                        debug!("statfs unique {} inode {}", request.unique, inode);
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &entry_out)
-                                    .expect("won't happened");
+                        let response = match fs.statsfs(request, inode).await {
+                            Err(err) => Response::Error(err),
+                            Ok(fs_stat) => Response::StatFs(fs_stat),
+                        };
 
-                                let _ = resp_sender.send(data).await;
-                            }
-                        }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_UNLINK => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "unlink body doesn't have null, request unique {}",
-                                request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
+                Operation::Release {
+                    inode,
+                    fh,
+                    flags,
+                    lock_owner,
+                    flush,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                    self.open_handles.released(fh, false);
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "unlink unique {} parent {} name {:?}",
-                            request.unique, in_header.nodeid, name
+                            "release unique {} inode {} fh {} flags {} lock_owner {} flush {}",
+                            request.unique, inode, fh, flags, lock_owner, flush
                         );
 
-                        let resp_value =
-                            if let Err(err) = fs.unlink(request, in_header.nodeid, &name).await {
-                                err.into()
-                            } else {
-                                0
-                            };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
+                        let response = match fs
+                            .release(request, inode, fh, flags, lock_owner, flush)
+                            .await
+                        {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
                         };
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_RMDIR => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Fsync {
+                    inode,
+                    fh,
+                    datasync,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "rmdir body doesn't have null, request unique {}",
-                                request.unique
-                            );
+                    let fs = fs.clone();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    in_flight.spawn(async move {
+                        debug!(
+                            "fsync unique {} inode {} fh {} datasync {}",
+                            request.unique, inode, fh, datasync
+                        );
 
-                            continue;
-                        }
+                        let response = match fs.fsync(request, inode, fh, datasync).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
+                Operation::Setxattr {
+                    inode,
+                    name,
+                    value,
+                    flags,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
+                    let value = value.to_owned();
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "rmdir unique {} parent {} name {:?}",
-                            request.unique, in_header.nodeid, name
-                        );
+                    in_flight.spawn(async move {
+                        debug!("setxattr unique {} inode {}", request.unique, inode);
 
-                        let resp_value =
-                            if let Err(err) = fs.rmdir(request, in_header.nodeid, &name).await {
-                                err.into()
-                            } else {
-                                0
+                        // TODO handle os X argument
+                        let response =
+                            match fs.setxattr(request, inode, &name, &value, flags, 0).await {
+                                Err(err) => Response::Error(err),
+                                Ok(()) => Response::Empty,
                             };
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
-
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_RENAME => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Getxattr { inode, name, size } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let rename_in = match BINARY.deserialize::<fuse_rename_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_rename_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    in_flight.spawn(async move {
+                        debug!("getxattr unique {} inode {}", request.unique, inode);
 
-                            continue;
-                        }
+                        let response = match fs.getxattr(request, inode, &name, size).await {
+                            Err(err) => Response::Error(err),
+                            Ok(xattr) => Response::XAttr(xattr),
+                        };
 
-                        Ok(rename_in) => rename_in,
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                    data = &data[FUSE_RENAME_IN_SIZE..];
+                Operation::Listxattr { inode, size } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let (name, first_null_index) = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_rename_in body doesn't have null, request unique {}",
-                                request.unique
-                            );
+                    let fs = fs.clone();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    in_flight.spawn(async move {
+                        debug!(
+                            "listxattr unique {} inode {} size {}",
+                            request.unique, inode, size
+                        );
 
-                            continue;
-                        }
+                        let response = match fs.listxattr(request, inode, size).await {
+                            Err(err) => Response::Error(err),
+                            Ok(xattr) => Response::XAttr(xattr),
+                        };
 
-                        Some(index) => (OsString::from_vec((&data[..index]).to_vec()), index),
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                    data = &data[first_null_index + 1..];
+                Operation::Removexattr { inode, name } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let new_name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_rename_in body doesn't have null, request unique {}",
-                                request.unique
-                            );
+                    let name = name.to_owned();
+                    let fs = fs.clone();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    in_flight.spawn(async move {
+                        debug!("removexattr unique {} inode {}", request.unique, inode);
 
-                            continue;
-                        }
+                        let response = match fs.removexattr(request, inode, &name).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
+
+                Operation::Flush {
+                    inode,
+                    fh,
+                    lock_owner,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "rename unique {} parent {} name {:?} new parent {} new name {:?}",
-                            request.unique, in_header.nodeid, name, rename_in.newdir, new_name
+                            "flush unique {} inode {} fh {} lock_owner {}",
+                            request.unique, inode, fh, lock_owner
                         );
 
-                        let resp_value = if let Err(err) = fs
-                            .rename(
-                                request,
-                                in_header.nodeid,
-                                &name,
-                                rename_in.newdir,
-                                &new_name,
-                            )
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
+                        let response = match fs.flush(request, inode, fh, lock_owner).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
                         };
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_LINK => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Opendir { inode, flags } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let link_in = match BINARY.deserialize::<fuse_link_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_link_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    let fs = fs.clone();
+                    let open_handles = self.open_handles.clone();
 
-                            continue;
-                        }
+                    in_flight.spawn(async move {
+                        debug!(
+                            "opendir unique {} inode {} flags {}",
+                            request.unique, inode, flags
+                        );
 
-                        Ok(link_in) => link_in,
-                    };
+                        let response = match fs.opendir(request, inode, flags).await {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_open) => {
+                                open_handles.opened(reply_open.fh, true);
 
-                    data = &data[FUSE_LINK_IN_SIZE..];
+                                Response::Open(reply_open)
+                            }
+                        };
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_link_in body doesn't have null, request unique {}",
-                                request.unique
-                            );
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                Operation::Readdir {
+                    inode,
+                    fh,
+                    offset,
+                    size,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                            continue;
-                        }
+                    if self.mount_options.force_readdir_plus {
+                        reply_error(libc::ENOSYS.into(), request, resp_sender);
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                        continue;
+                    }
 
                     let fs = fs.clone();
+                    let readdir_cursors = self.readdir_cursors.clone();
+                    let readdir_unsupported = self.readdir_unsupported.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "link unique {} inode {} new parent {} new name {:?}",
-                            request.unique, link_in.oldnodeid, in_header.nodeid, name
+                            "readdir unique {} inode {} fh {} offset {}",
+                            request.unique, inode, fh, offset
                         );
 
-                        match fs
-                            .link(request, link_in.oldnodeid, in_header.nodeid, &name)
+                        // resume the stream of the last readdir if it stopped at this offset
+                        let mut cursor = match readdir_cursors.take(inode, fh, offset) {
+                            Some(cursor) => cursor,
+
+                            None => match readdir_entries(
+                                fs,
+                                request,
+                                inode,
+                                fh,
+                                offset,
+                                &readdir_unsupported,
+                            )
                             .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-                            }
+                            {
+                                Err(err) => {
+                                    reply_error_in_place(err, request, resp_sender).await;
 
-                            Ok(entry) => {
-                                let entry_out: fuse_entry_out = entry.into();
+                                    return;
+                                }
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                                Ok(entries) => DirCursor::new(entries),
+                            },
+                        };
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE);
+                        let max_size = size as usize;
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &entry_out)
-                                    .expect("won't happened");
+                        let mut entry_data = Vec::with_capacity(max_size);
 
-                                let _ = resp_sender.send(data).await;
-                            }
-                        }
-                    });
-                }
+                        let mut next_offset = offset;
 
-                fuse_opcode::FUSE_OPEN => {
-                    let mut resp_sender = self.response_sender.clone();
+                        while let Some(entry) = cursor.next().await {
+                            let entry = match entry {
+                                Err(err) if entry_data.is_empty() => {
+                                    reply_error_in_place(err, request, resp_sender).await;
 
-                    let open_in = match BINARY.deserialize::<fuse_open_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_open_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                                    return;
+                                }
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                                // reply the collected entries, the error is replied by the next
+                                // readdir
+                                Err(err) => {
+                                    cursor.push_back(Err(err));
 
-                            continue;
-                        }
+                                    readdir_cursors.put(inode, fh, next_offset, cursor);
 
-                        Ok(open_in) => open_in,
-                    };
+                                    break;
+                                }
 
-                    let fs = fs.clone();
+                                Ok(entry) => entry,
+                            };
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "open unique {} inode {} flags {}",
-                            request.unique, in_header.nodeid, open_in.flags
-                        );
+                            if entry_data.len() + dirent_size(&entry) > max_size {
+                                cursor.push_back(Ok(entry));
 
-                        let opened = match fs.open(request, in_header.nodeid, open_in.flags).await {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
+                                readdir_cursors.put(inode, fh, next_offset, cursor);
 
-                                return;
+                                break;
                             }
 
-                            Ok(opened) => opened,
-                        };
-
-                        let open_out: fuse_open_out = opened.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_OPEN_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_OPEN_OUT_SIZE);
+                            next_offset = entry.index;
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &open_out)
-                            .expect("won't happened");
+                            encode_dirent(&mut entry_data, &entry);
+                        }
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(out_entries(request.unique, entry_data))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_READ => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Releasedir { inode, fh, flags } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let read_in = match BINARY.deserialize::<fuse_read_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_read_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(read_in) => read_in,
-                    };
+                    self.open_handles.released(fh, true);
+                    self.readdir_cursors.release(inode, fh);
+                    self.readdirplus_cursors.release(inode, fh);
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "read unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, read_in
+                            "releasedir unique {} inode {} fh {} flags {}",
+                            request.unique, inode, fh, flags
                         );
 
-                        let reply_data = match fs
-                            .read(
-                                request,
-                                in_header.nodeid,
-                                read_in.fh,
-                                read_in.offset,
-                                read_in.size,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_data) => reply_data.data,
+                        let response = match fs.releasedir(request, inode, fh, flags).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
                         };
 
-                        let mut reply_data = reply_data.as_ref().as_ref();
-
-                        if reply_data.len() > read_in.size as _ {
-                            reply_data = &reply_data[..read_in.size as _];
-                        }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + reply_data.len()) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
+                Operation::Fsyncdir {
+                    inode,
+                    fh,
+                    datasync,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                        let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + reply_data.len());
+                    let fs = fs.clone();
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
+                    in_flight.spawn(async move {
+                        debug!(
+                            "fsyncdir unique {} inode {} fh {} datasync {}",
+                            request.unique, inode, fh, datasync
+                        );
 
-                        data.extend_from_slice(reply_data);
+                        let response = match fs.fsyncdir(request, inode, fh, datasync).await {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_WRITE => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let write_in = match BINARY.deserialize::<fuse_write_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_write_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(write_in) => write_in,
-                    };
+                #[cfg(feature = "file-lock")]
+                Operation::Getlk {
+                    inode,
+                    fh,
+                    lock_owner,
+                    start,
+                    end,
+                    r#type,
+                    pid,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    data = &data[FUSE_WRITE_IN_SIZE..];
+                    let fs = fs.clone();
 
-                    if write_in.size as usize != data.len() {
-                        error!("fuse_write_in body len is invalid");
+                    in_flight.spawn(async move {
+                        debug!(
+                            "getlk unique {} inode {} fh {} lock_owner {} start {} end {} type {} pid {}",
+                            request.unique, inode, fh, lock_owner, start, end, r#type, pid
+                        );
 
-                        reply_error(libc::EINVAL.into(), request, resp_sender);
+                        let response = match fs
+                            .getlk(
+                                request,
+                                inode,
+                                fh,
+                                lock_owner,
+                                start,
+                                end,
+                                r#type,
+                                pid,
+                            )
+                            .await
+                        {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_lock) => Response::Lock(reply_lock),
+                        };
 
-                        continue;
-                    }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                    let data = data.to_vec();
+                #[cfg(feature = "file-lock")]
+                Operation::Setlk {
+                    inode,
+                    fh,
+                    lock_owner,
+                    start,
+                    end,
+                    r#type,
+                    pid,
+                    block,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "write unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, write_in
+                            "setlk unique {} inode {} fh {} lock_owner {} start {} end {} type {} pid {} block {}",
+                            request.unique, inode, fh, lock_owner, start, end, r#type, pid, block
                         );
 
-                        let reply_write = match fs
-                            .write(
+                        let response = match fs
+                            .setlk(
                                 request,
-                                in_header.nodeid,
-                                write_in.fh,
-                                write_in.offset,
-                                &data,
-                                write_in.flags,
+                                inode,
+                                fh,
+                                lock_owner,
+                                start,
+                                end,
+                                r#type,
+                                pid,
+                                block,
                             )
                             .await
                         {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                                return;
-                            }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                            Ok(reply_write) => reply_write,
-                        };
+                Operation::Access { inode, mask } => {
+                    let mut resp_sender = response_sender.clone();
 
-                        let write_out: fuse_write_out = reply_write.into();
+                    let fs = fs.clone();
 
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_WRITE_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
+                    in_flight.spawn(async move {
+                        debug!(
+                            "access unique {} inode {} mask {}",
+                            request.unique, inode, mask
+                        );
 
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_WRITE_OUT_SIZE);
+                        let result = fs.access(request, inode, mask).await;
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &write_out)
-                            .expect("won't happened");
+                        debug!("access response {:?}", result);
+
+                        let response = match result {
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_STATFS => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::Create {
+                    parent,
+                    name,
+                    mode,
+                    flags,
+                    umask,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
                     let fs = fs.clone();
+                    let open_handles = self.open_handles.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "statfs unique {} inode {}",
-                            request.unique, in_header.nodeid
+                            "create unique {} parent {} name {:?} mode {} flags {} umask {}",
+                            request.unique, parent, name, mode, flags, umask
                         );
 
-                        let fs_stat = match fs.statsfs(request, in_header.nodeid).await {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
+                        let response = match fs.create(request, parent, &name, mode, flags).await {
+                            Err(err) => Response::Error(err),
+                            Ok(created) => {
+                                open_handles.opened(created.fh, false);
 
-                                return;
+                                Response::Created(created)
                             }
-
-                            Ok(fs_stat) => fs_stat,
                         };
 
-                        let statfs_out: fuse_statfs_out = fs_stat.into();
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_STATFS_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
+                Operation::Interrupt { unique } => {
+                    let mut resp_sender = response_sender.clone();
 
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_STATFS_OUT_SIZE);
+                    debug!(
+                        "interrupt_in unique {} interrupt unique {}",
+                        request.unique, unique
+                    );
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &statfs_out)
-                            .expect("won't happened");
+                    let in_flight_request = self
+                        .in_flight_requests
+                        .lock()
+                        .unwrap()
+                        .get(&unique)
+                        .cloned();
+
+                    match in_flight_request {
+                        // the request is finished, or it is read by another worker channel but
+                        // not spawned yet, reply EAGAIN so the kernel will queue the interrupt
+                        // again if the request is still running. Never reply ENOSYS, the kernel
+                        // will disable interrupt for the whole mount
+                        None => {
+                            debug!(
+                                "interrupted request unique {} is not in flight, reply EAGAIN",
+                                unique
+                            );
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+                            reply_error(libc::EAGAIN.into(), request, resp_sender);
 
-                fuse_opcode::FUSE_RELEASE => {
-                    let mut resp_sender = self.response_sender.clone();
+                            continue;
+                        }
 
-                    let release_in = match BINARY.deserialize::<fuse_release_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_release_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                        // the aborted request will reply EINTR by itself, interrupt request
+                        // doesn't need a reply
+                        Some(Some(abort_handle)) => {
+                            debug!("abort interrupted request unique {}", unique);
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                            abort_handle.abort();
 
                             continue;
                         }
 
-                        Ok(release_in) => release_in,
-                    };
+                        Some(None) => {}
+                    }
 
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
-                        let flush = release_in.release_flags & FUSE_RELEASE_FLUSH > 0;
+                    // the interrupt request itself can't be interrupted
+                    let in_flight = InFlight {
+                        interruptible: false,
+                        ..in_flight
+                    };
+
+                    in_flight.spawn(async move {
+                        let response = match fs.interrupt(request, unique).await {
+                            // the filesystem doesn't handle interrupt, ignore it
+                            Err(err) if err.0 == libc::ENOSYS => return,
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
+                        };
+
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
+
+                Operation::Bmap {
+                    inode,
+                    blocksize,
+                    idx,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let fs = fs.clone();
 
+                    in_flight.spawn(async move {
                         debug!(
-                            "release unique {} inode {} fh {} flags {} lock_owner {} flush {}",
-                            request.unique,
-                            in_header.nodeid,
-                            release_in.fh,
-                            release_in.flags,
-                            release_in.lock_owner,
-                            flush
+                            "bmap unique {} inode {} block size {} idx {}",
+                            request.unique, inode, blocksize, idx
                         );
 
-                        let resp_value = if let Err(err) = fs
-                            .release(
-                                request,
-                                in_header.nodeid,
-                                release_in.fh,
-                                release_in.flags,
-                                release_in.lock_owner,
-                                flush,
-                            )
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
+                        let response = match fs.bmap(request, inode, blocksize, idx).await {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_bmap) => Response::Bmap(reply_bmap),
                         };
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_FSYNC => {
-                    let mut resp_sender = self.response_sender.clone();
+                /*fuse_opcode::FUSE_IOCTL => {
+                    let mut resp_sender = response_sender.clone();
 
-                    let fsync_in = match BINARY.deserialize::<fuse_fsync_in>(data) {
+                    let ioctl_in = match BINARY.deserialize::<fuse_ioctl_in>(data) {
                         Err(err) => {
-                            error!(
-                                "deserialize fuse_fsync_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                            error!("deserialize fuse_ioctl_in failed {}", err);
 
                             reply_error(libc::EINVAL.into(), request, resp_sender);
 
                             continue;
                         }
 
-                        Ok(fsync_in) => fsync_in,
+                        Ok(ioctl_in) => ioctl_in,
                     };
 
+                    let ioctl_data = (&data[FUSE_IOCTL_IN_SIZE..]).to_vec();
+
                     let fs = fs.clone();
+                }*/
+                Operation::Poll {
+                    inode,
+                    fh,
+                    kh,
+                    flags,
+                    events,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    spawn_without_return(async move {
-                        let data_sync = fsync_in.fsync_flags & 1 > 0;
+                    let fs = fs.clone();
 
+                    in_flight.spawn(async move {
                         debug!(
-                            "fsync unique {} inode {} fh {} data_sync {}",
-                            request.unique, in_header.nodeid, fsync_in.fh, data_sync
+                            "poll unique {} inode {} fh {} kh {:?} flags {} events {}",
+                            request.unique, inode, fh, kh, flags, events
                         );
 
-                        let resp_value = if let Err(err) = fs
-                            .fsync(request, in_header.nodeid, fsync_in.fh, data_sync)
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
+                        let response = match fs.poll(request, inode, fh, kh, flags, events).await {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_poll) => Response::Poll(reply_poll),
                         };
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_SETXATTR => {
-                    let mut resp_sender = self.response_sender.clone();
+                Operation::NotifyReply {
+                    inode,
+                    offset,
+                    data,
+                } => {
+                    let resp_sender = response_sender.clone();
 
-                    let setxattr_in = match BINARY.deserialize::<fuse_setxattr_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_setxattr_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                    let data = request_data.slice_ref(data);
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                    let fs = fs.clone();
 
-                            continue;
+                    in_flight.spawn_without_reply(async move {
+                        if let Err(err) = fs.notify_reply(request, inode, offset, data).await {
+                            reply_error_in_place(err, request, resp_sender).await;
                         }
+                    });
+                }
 
-                        Ok(setxattr_in) => setxattr_in,
-                    };
-
-                    data = &data[FUSE_SETXATTR_IN_SIZE..];
+                Operation::BatchForget { forgets } => {
+                    let fs = fs.clone();
 
-                    if setxattr_in.size as usize != data.len() {
-                        error!(
-                            "fuse_setxattr_in body length is not right, request unique {}",
-                            request.unique
+                    in_flight.spawn_without_reply(async move {
+                        debug!(
+                            "batch_forget unique {} forgets {:?}",
+                            request.unique, forgets
                         );
 
-                        reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                        continue;
-                    }
-
-                    let (name, first_null_index) = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_setxattr_in body has no null, request unique {}",
-                                request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
+                        fs.batch_forget(request, &forgets).await
+                    });
+                }
 
-                        Some(index) => (OsString::from_vec((&data[..index]).to_vec()), index),
-                    };
+                Operation::Fallocate {
+                    inode,
+                    fh,
+                    offset,
+                    length,
+                    mode,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                    data = &data[first_null_index + 1..];
+                    let fs = fs.clone();
 
-                    let value = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_setxattr_in value has no second null unique {}",
-                                request.unique
-                            );
+                    in_flight.spawn(async move {
+                        debug!(
+                            "fallocate unique {} inode {} fh {} offset {} length {} mode {}",
+                            request.unique, inode, fh, offset, length, mode
+                        );
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                        let response =
+                            match fs.fallocate(request, inode, fh, offset, length, mode).await {
+                                Err(err) => Response::Error(err),
+                                Ok(()) => Response::Empty,
+                            };
 
-                            continue;
-                        }
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+                Operation::Readdirplus {
+                    parent,
+                    fh,
+                    offset,
+                    size,
+                    lock_owner,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
                     let fs = fs.clone();
+                    let readdirplus_cursors = self.readdirplus_cursors.clone();
+                    let readdirplus_unsupported = self.readdirplus_unsupported.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "setxattr unique {} inode {}",
-                            request.unique, in_header.nodeid
+                            "readdirplus unique {} parent {} fh {} offset {}",
+                            request.unique, parent, fh, offset
                         );
 
-                        // TODO handle os X argument
-                        let resp_value = if let Err(err) = fs
-                            .setxattr(
+                        // resume the stream of the last readdirplus if it stopped at this offset
+                        let mut cursor = match readdirplus_cursors.take(parent, fh, offset) {
+                            Some(cursor) => cursor,
+
+                            None => match readdirplus_entries(
+                                &*fs,
                                 request,
-                                in_header.nodeid,
-                                &name,
-                                &value,
-                                setxattr_in.flags,
-                                0,
+                                parent,
+                                fh,
+                                offset,
+                                lock_owner,
+                                &readdirplus_unsupported,
                             )
                             .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
+                            {
+                                Err(err) => {
+                                    reply_error_in_place(err, request, resp_sender).await;
+
+                                    return;
+                                }
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
+                                Ok(entries) => DirCursor::new(entries),
+                            },
                         };
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
+                        let max_size = size as usize;
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+                        let mut entry_data = Vec::with_capacity(max_size);
 
-                fuse_opcode::FUSE_GETXATTR => {
-                    let mut resp_sender = self.response_sender.clone();
+                        let mut next_offset = offset;
 
-                    let getxattr_in = match BINARY.deserialize::<fuse_getxattr_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_getxattr_in failed {}, request unique {}",
-                                err, request.unique
-                            );
+                        // only the entries in the reply are looked up by the kernel
+                        let mut lookups = vec![];
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                        while let Some(entry) = cursor.next().await {
+                            let entry = match entry {
+                                Err(err) if entry_data.is_empty() => {
+                                    reply_error_in_place(err, request, resp_sender).await;
 
-                            continue;
-                        }
+                                    return;
+                                }
 
-                        Ok(getxattr_in) => getxattr_in,
-                    };
+                                // reply the collected entries, the error is replied by the next
+                                // readdirplus
+                                Err(err) => {
+                                    cursor.push_back(Err(err));
 
-                    data = &data[FUSE_GETXATTR_IN_SIZE..];
+                                    readdirplus_cursors.put(parent, fh, next_offset, cursor);
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!("fuse_getxattr_in body has no null {}", request.unique);
+                                    break;
+                                }
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
-
-                    let fs = fs.clone();
+                                Ok(entry) => entry,
+                            };
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "getxattr unique {} inode {}",
-                            request.unique, in_header.nodeid
-                        );
+                            if entry_data.len() + direntplus_size(entry.name()) > max_size {
+                                cursor.push_back(Ok(entry));
 
-                        let xattr = match fs
-                            .getxattr(request, in_header.nodeid, &name, getxattr_in.size)
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
+                                readdirplus_cursors.put(parent, fh, next_offset, cursor);
 
-                                return;
+                                break;
                             }
 
-                            Ok(xattr) => xattr,
-                        };
+                            // the emulated entry is looked up after it fits in the reply, so the
+                            // entries which are not replied are not looked up
+                            let entry = match entry {
+                                ReaddirplusEntry::Entry(entry) => entry,
 
-                        let data = match xattr {
-                            ReplyXAttr::Size(size) => {
-                                let getxattr_out = fuse_getxattr_out { size, padding: 0 };
+                                ReaddirplusEntry::Readdir(entry) => {
+                                    match lookup_dirent(&*fs, request, parent, entry).await {
+                                        // the entry is removed after readdir
+                                        None => continue,
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_GETXATTR_OUT_SIZE) as u32,
-                                    error: libc::ERANGE,
-                                    unique: request.unique,
-                                };
+                                        Some(Ok(entry)) => entry,
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_STATFS_OUT_SIZE);
+                                        Some(Err(err)) if entry_data.is_empty() => {
+                                            reply_error_in_place(err, request, resp_sender).await;
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &getxattr_out)
-                                    .expect("won't happened");
+                                            return;
+                                        }
 
-                                data
-                            }
+                                        Some(Err(err)) => {
+                                            cursor.push_back(Err(err));
 
-                            ReplyXAttr::Data(xattr_data) => {
-                                // TODO check is right way or not
-                                // TODO should we check data length or not
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + xattr_data.len()) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                                            readdirplus_cursors.put(
+                                                parent,
+                                                fh,
+                                                next_offset,
+                                                cursor,
+                                            );
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + xattr_data.len());
+                                            break;
+                                        }
+                                    }
+                                }
+                            };
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
+                            next_offset = entry.index;
 
-                                data.extend_from_slice(&xattr_data);
+                            encode_direntplus(&mut entry_data, &entry);
 
-                                data
-                            }
-                        };
+                            lookups.extend(direntplus_lookup(&entry));
+                        }
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(out_entries(request.unique, entry_data).with_lookups(lookups))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_LISTXATTR => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let listxattr_in = match BINARY.deserialize::<fuse_getxattr_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_getxattr_in in listxattr failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(listxattr_in) => listxattr_in,
-                    };
-
+                Operation::Rename2 {
+                    parent,
+                    name,
+                    new_parent,
+                    new_name,
+                    flags,
+                } => {
+                    let mut resp_sender = response_sender.clone();
+
+                    let name = name.to_owned();
+                    let new_name = new_name.to_owned();
                     let fs = fs.clone();
 
-                    spawn_without_return(async move {
+                    in_flight.spawn(async move {
                         debug!(
-                            "listxattr unique {} inode {} size {}",
-                            request.unique, in_header.nodeid, listxattr_in.size
+                            "rename2 unique {} parent {} name {:?} new parent {} new name {:?} flags {}",
+                            request.unique,
+                            parent,
+                            name,
+                            new_parent,
+                            new_name,
+                            flags
                         );
 
-                        let xattr = match fs
-                            .listxattr(request, in_header.nodeid, listxattr_in.size)
+                        let response = match fs
+                            .rename2(
+                                request,
+                                parent,
+                                &name,
+                                new_parent,
+                                &new_name,
+                                flags,
+                            )
                             .await
                         {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(xattr) => xattr,
+                            Err(err) => Response::Error(err),
+                            Ok(()) => Response::Empty,
                         };
 
-                        let data = match xattr {
-                            ReplyXAttr::Size(size) => {
-                                let getxattr_out = fuse_getxattr_out { size, padding: 0 };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + FUSE_GETXATTR_OUT_SIZE) as u32,
-                                    error: libc::ERANGE,
-                                    unique: request.unique,
-                                };
+                Operation::Lseek {
+                    inode,
+                    fh,
+                    offset,
+                    whence,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_STATFS_OUT_SIZE);
+                    let fs = fs.clone();
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
-                                BINARY
-                                    .serialize_into(&mut data, &getxattr_out)
-                                    .expect("won't happened");
+                    in_flight.spawn(async move {
+                        debug!(
+                            "lseek unique {} inode {} fh {} offset {} whence {}",
+                            request.unique, inode, fh, offset, whence
+                        );
 
-                                data
-                            }
+                        let response = match fs.lseek(request, inode, fh, offset, whence).await {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_lseek) => Response::LSeek(reply_lseek),
+                        };
 
-                            ReplyXAttr::Data(xattr_data) => {
-                                // TODO check is right way or not
-                                // TODO should we check data length or not
-                                let out_header = fuse_out_header {
-                                    len: (FUSE_OUT_HEADER_SIZE + xattr_data.len()) as u32,
-                                    error: 0,
-                                    unique: request.unique,
-                                };
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
+                    });
+                }
 
-                                let mut data =
-                                    Vec::with_capacity(FUSE_OUT_HEADER_SIZE + xattr_data.len());
+                Operation::CopyFileRange {
+                    inode,
+                    fh_in,
+                    off_in,
+                    inode_out,
+                    fh_out,
+                    off_out,
+                    length,
+                    flags,
+                } => {
+                    let mut resp_sender = response_sender.clone();
 
-                                BINARY
-                                    .serialize_into(&mut data, &out_header)
-                                    .expect("won't happened");
+                    let fs = fs.clone();
 
-                                data.extend_from_slice(&xattr_data);
+                    in_flight.spawn(async move {
+                        debug!(
+                            "copy_file_range unique {} inode {} fh {} offset {} inode out {} fh out {} offset out {} length {}",
+                            request.unique, inode, fh_in, off_in, inode_out, fh_out, off_out, length
+                        );
 
-                                data
+                        let response = match fs
+                            .copy_file_range(
+                                request,
+                                inode,
+                                fh_in,
+                                off_in,
+                                inode_out,
+                                fh_out,
+                                off_out,
+                                length,
+                                flags,
+                            )
+                            .await
+                        {
+                            Err(err) => Response::Error(err),
+                            Ok(reply_copy_file_range) => {
+                                Response::CopyFileRange(reply_copy_file_range)
                             }
                         };
 
-                        let _ = resp_sender.send(data).await;
+                        let _ = resp_sender
+                            .send(response.into_reply_buf(request.unique, compat_sizes))
+                            .await;
                     });
                 }
 
-                fuse_opcode::FUSE_REMOVEXATTR => {
-                    let mut resp_sender = self.response_sender.clone();
+                #[cfg(target_os = "macos")]
+                Operation::Setvolname => {}
 
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse removexattr body has no null, request unique {}",
-                                request.unique
-                            );
+                #[cfg(target_os = "macos")]
+                Operation::Getxtimes => {}
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                #[cfg(target_os = "macos")]
+                Operation::Exchange => {} // fuse_opcode::CUSE_INIT => {}
+            }
+        }
+    }
+}
 
-                            continue;
-                        }
+/// the panic of a request handler, the session is aborted when it is received.
+struct HandlerPanic {
+    opcode: fuse_opcode,
+    unique: u64,
+}
 
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
+/// the in flight request, its handler can be aborted when the request is interrupted or its
+/// deadline expires.
+struct InFlight {
+    /// the permits of the handler, or the queued permits if the request waits for the previous
+    /// ordered requests.
+    permits: Permits,
+    /// the `permits` are the queued permits, they are exchanged for the running permits after
+    /// the ordering wait.
+    queued: bool,
+    concurrency_limits: Arc<ConcurrencyLimits>,
+    order_ticket: OrderTicket,
+    requests: Arc<Mutex<HashMap<u64, Option<AbortHandle>>>>,
+    request: Request,
+    opcode: fuse_opcode,
+    inode: u64,
+    interruptible: bool,
+    deadline: Option<Duration>,
+    deadline_errno: Errno,
+    response_sender: ReplySender,
+    panic_policy: PanicPolicy,
+    panic_sender: UnboundedSender<HandlerPanic>,
+    /// it is dropped when the request task finishes, the session waits for all of them.
+    running_task: Option<UnboundedSender<Infallible>>,
+    /// the span of the request, the handler runs in it.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
 
-                    let fs = fs.clone();
+impl InFlight {
+    fn spawn<F>(self, f: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let unique = self.request.unique;
+
+        let permits = self.permits;
+        let queued = self.queued;
+        let concurrency_limits = self.concurrency_limits;
+        let order_ticket = self.order_ticket;
+        let opcode = self.opcode;
+        let inode = self.inode;
+        let request = self.request;
+        let deadline = self.deadline;
+        let deadline_errno = self.deadline_errno;
+        let response_sender = self.response_sender.clone();
+        let reply_claim = response_sender.reply_claim();
+        let panic_policy = self.panic_policy;
+        let panic_sender = self.panic_sender.clone();
+        let running_task = self.running_task;
+        #[cfg(feature = "tracing")]
+        let span = self.span;
+
+        let f = async move {
+            // the ticket is dropped after the request is replied, then the next ordered request
+            // can run
+            let mut order_ticket = order_ticket;
+
+            order_ticket.wait().await;
+
+            // release the queued permits after the running permits are acquired, so the request
+            // is always counted by one of the limits
+            let _permits = if queued {
+                let running_permits = concurrency_limits.acquire(opcode).await;
+
+                drop(permits);
+
+                running_permits
+            } else {
+                permits
+            };
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "removexattr unique {} inode {}",
-                            request.unique, in_header.nodeid
-                        );
+            // the deadline starts when the handler runs, the time waiting for the ordered
+            // requests and the permits is not counted
+            let handler = catch_panic(f, opcode, unique, panic_policy, &panic_sender).fuse();
 
-                        let resp_value = if let Err(err) =
-                            fs.removexattr(request, in_header.nodeid, &name).await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
+            let timer = match deadline {
+                None => future::pending().left_future(),
+                Some(deadline) => sleep(deadline).right_future(),
+            }
+            .fuse();
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
+            pin_mut!(handler, timer);
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
+            // the handler future is dropped when the deadline expires, so it can't reply late
+            let errno = select! {
+                panicked = handler => {
+                    if !panicked {
+                        return;
+                    }
 
-                        let _ = resp_sender.send(data).await;
-                    });
+                    libc::EIO.into()
                 }
 
-                fuse_opcode::FUSE_FLUSH => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let flush_in = match BINARY.deserialize::<fuse_flush_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_flush_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error_in_place(libc::EINVAL.into(), request, resp_sender).await;
+                _ = timer => {
+                    warn!(
+                        "request deadline {:?} expired, opcode {} inode {} unique {}",
+                        deadline.unwrap(), opcode, inode, unique
+                    );
 
-                            continue;
-                        }
+                    deadline_errno
+                }
+            };
 
-                        Ok(flush_in) => flush_in,
-                    };
+            // the spliced read reply may be written already
+            if reply_claim.claim_error(errno.0) {
+                reply_error_in_place(errno, request, response_sender).await;
+            }
+        };
 
-                    let fs = fs.clone();
+        // the request which is not interruptible is also recorded, so an interrupt of it can be
+        // passed to the filesystem
+        let (abort_handle, abort_registration) = if self.interruptible {
+            let (abort_handle, abort_registration) = AbortHandle::new_pair();
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "flush unique {} inode {} fh {} lock_owner {}",
-                            request.unique, in_header.nodeid, flush_in.fh, flush_in.lock_owner
-                        );
+            (Some(abort_handle), Some(abort_registration))
+        } else {
+            (None, None)
+        };
 
-                        let resp_value = if let Err(err) = fs
-                            .flush(request, in_header.nodeid, flush_in.fh, flush_in.lock_owner)
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
+        let requests = self.requests;
+        let reply_claim = self.response_sender.reply_claim();
+        let response_sender = self.response_sender;
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
+        requests.lock().unwrap().insert(unique, abort_handle);
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
+        let task = async move {
+            let result = match abort_registration {
+                None => {
+                    f.await;
 
-                        let _ = resp_sender.send(data).await;
-                    });
+                    Ok(())
                 }
 
-                fuse_opcode::FUSE_OPENDIR => {
-                    let mut resp_sender = self.response_sender.clone();
+                Some(abort_registration) => Abortable::new(f, abort_registration).await,
+            };
 
-                    let open_in = match BINARY.deserialize::<fuse_open_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_open_in in opendir failed {}, request unique {}",
-                                err, request.unique
-                            );
+            requests.lock().unwrap().remove(&unique);
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+            if result.is_err() && reply_claim.claim_error(libc::EINTR) {
+                debug!("request unique {} is interrupted", unique);
 
-                            continue;
-                        }
+                reply_error_in_place(libc::EINTR.into(), request, response_sender).await;
+            }
 
-                        Ok(open_in) => open_in,
-                    };
+            drop(running_task);
+        };
 
-                    let fs = fs.clone();
+        #[cfg(feature = "tracing")]
+        let task = task.instrument(span);
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "opendir unique {} inode {} flags {}",
-                            request.unique, in_header.nodeid, open_in.flags
-                        );
+        spawn_without_return(task);
+    }
 
-                        let reply_open =
-                            match fs.opendir(request, in_header.nodeid, open_in.flags).await {
-                                Err(err) => {
-                                    reply_error_in_place(err, request, resp_sender).await;
+    /// spawn the handler of a request which doesn't need a reply, such as forget, it can't be
+    /// interrupted.
+    fn spawn_without_reply<F>(self, f: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        #[cfg(feature = "tracing")]
+        let span = self.span.clone();
+
+        let task = async move {
+            let _permits = self.permits;
+            let _running_task = self.running_task;
+
+            catch_panic(
+                f,
+                self.opcode,
+                self.request.unique,
+                self.panic_policy,
+                &self.panic_sender,
+            )
+            .await;
+        };
 
-                                    return;
-                                }
+        #[cfg(feature = "tracing")]
+        let task = task.instrument(span);
 
-                                Ok(reply_open) => reply_open,
-                            };
+        spawn_without_return(task);
+    }
+}
 
-                        let open_out: fuse_open_out = reply_open.into();
+/// run the request handler and catch its panic, return `true` if it panics and the panicked
+/// request should be replied `EIO`.
+async fn catch_panic<F>(
+    f: F,
+    opcode: fuse_opcode,
+    unique: u64,
+    panic_policy: PanicPolicy,
+    panic_sender: &UnboundedSender<HandlerPanic>,
+) -> bool
+where
+    F: Future<Output = ()>,
+{
+    let payload = match AssertUnwindSafe(f).catch_unwind().await {
+        Ok(()) => return false,
+        Err(payload) => payload,
+    };
 
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_OPEN_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
 
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_OPEN_OUT_SIZE);
+    error!(
+        "request handler panicked, opcode {} unique {}: {}",
+        opcode, unique, message
+    );
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &open_out)
-                            .expect("won't happened");
+    match panic_policy {
+        PanicPolicy::ReplyEio => true,
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+        PanicPolicy::Abort => {
+            let _ = panic_sender.unbounded_send(HandlerPanic { opcode, unique });
 
-                fuse_opcode::FUSE_READDIR => {
-                    let mut resp_sender = self.response_sender.clone();
+            false
+        }
+    }
+}
 
-                    if self.mount_options.force_readdir_plus {
-                        reply_error(libc::ENOSYS.into(), request, resp_sender);
+/// the pipe size to splice a request, the kernel needs a pipe buffer for every page of the
+/// request.
+fn splice_pipe_size(kernel_config: &KernelConfig) -> usize {
+    let page_size = unistd::sysconf(SysconfVar::PAGE_SIZE)
+        .ok()
+        .flatten()
+        .map_or(MIN_WRITE_SIZE, |page_size| page_size as usize);
+
+    let max_pages = if kernel_config.want & FUSE_MAX_PAGES > 0 {
+        kernel_config.max_pages.min(FUSE_MAX_MAX_PAGES)
+    } else {
+        FUSE_DEFAULT_MAX_PAGES_PER_REQ
+    };
 
-                        continue;
-                    }
+    let max_write = (kernel_config.max_write as usize).min(max_pages as usize * page_size);
 
-                    let read_in = match BINARY.deserialize::<fuse_read_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_read_in in readdir failed {}, request unique {}",
-                                err, request.unique
-                            );
+    // the header and args take a page, and the data may be unaligned
+    (max_write / page_size + 2) * page_size
+}
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+#[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
+/// splice a request from the kernel to the pipe, and read it into `buffer`. If it is a write
+/// request, the payload is left in the pipe and taken as `write_payload`, the pipe will be
+/// created again before next read.
+async fn splice_read_request(
+    fuse_connection: &FuseConnection,
+    splice_pipe: &mut Option<Pipe>,
+    buffer: &mut [u8],
+    write_in_size: usize,
+    write_payload: &mut Option<WriteData>,
+) -> IoResult<usize> {
+    // if any error happens, the pipe may contain dirty data, so drop it
+    let pipe = splice_pipe.take().expect("splice pipe not init");
+
+    let n = fuse_connection
+        .splice_read(pipe.write.as_raw_fd(), buffer.len())
+        .await?;
+
+    if n < FUSE_IN_HEADER_SIZE {
+        pipe.read_exact(&mut buffer[..n])?;
+
+        splice_pipe.replace(pipe);
+
+        return Ok(n);
+    }
 
-                            continue;
-                        }
+    pipe.read_exact(&mut buffer[..FUSE_IN_HEADER_SIZE])?;
 
-                        Ok(read_in) => read_in,
-                    };
+    let is_write = match decode_header(&buffer[..FUSE_IN_HEADER_SIZE]) {
+        Err(_) => false,
+        Ok(in_header) => in_header.opcode == fuse_opcode::FUSE_WRITE as u32,
+    };
 
-                    let fs = fs.clone();
+    let body_offset = FUSE_IN_HEADER_SIZE + write_in_size;
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "readdir unique {} inode {} fh {} offset {}",
-                            request.unique, in_header.nodeid, read_in.fh, read_in.offset
-                        );
+    if is_write && n > body_offset {
+        pipe.read_exact(&mut buffer[FUSE_IN_HEADER_SIZE..body_offset])?;
 
-                        let mut reply_readdir = match fs
-                            .readdir(request, in_header.nodeid, read_in.fh, read_in.offset as i64)
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
+        write_payload.replace(WriteData::from_pipe(pipe, n - body_offset));
 
-                                return;
-                            }
+        return Ok(body_offset);
+    }
 
-                            Ok(reply_readdir) => reply_readdir,
-                        };
+    pipe.read_exact(&mut buffer[FUSE_IN_HEADER_SIZE..n])?;
 
-                        let max_size = read_in.size as usize;
+    splice_pipe.replace(pipe);
 
-                        let mut entry_data = Vec::with_capacity(max_size);
+    Ok(n)
+}
 
-                        while let Some(entry) = reply_readdir.entries.next().await {
-                            let name = &entry.name;
+#[cfg(any(feature = "async-std-runtime", feature = "tokio-runtime"))]
+/// reply a read request by splicing the file to the kernel. Return `false` if the reply is not
+/// sent, the caller should reply it in the normal way.
+// the arguments are the read request and its reply, they are only grouped here
+#[allow(clippy::too_many_arguments)]
+async fn splice_reply(
+    fuse_connection: Arc<FuseConnection>,
+    pipes: Arc<PipePool>,
+    metrics: Metrics,
+    request_timer: Option<RequestTimer>,
+    reply_claim: Arc<ReplyClaim>,
+    request: Request,
+    fd: Arc<dyn AsRawFd + Send + Sync>,
+    offset: u64,
+    len: usize,
+) -> bool {
+    // the blocking task doesn't run in the request span
+    #[cfg(feature = "tracing")]
+    let span = tracing::Span::current();
+
+    // the blocking task can't be cancelled, it claims the reply before splicing, so the
+    // interrupt or the deadline can't reply the request again
+    spawn_blocking(move || {
+        let header = |data_len: usize| out_header(request.unique, 0, data_len);
+
+        let (pipe, size) = match splice::reply_pipe(&pipes, fd.as_raw_fd(), offset, len, header) {
+            Err(err) => {
+                warn!(
+                    "build splice reply failed {}, request unique {}",
+                    err, request.unique
+                );
+
+                return false;
+            }
 
-                            let dir_entry_size = FUSE_DIRENT_SIZE + name.len();
+            Ok(reply) => reply,
+        };
 
-                            let padding_size = get_padding_size(dir_entry_size);
+        // the request is already replied by the interrupt or the deadline
+        if !reply_claim.claim_splice() {
+            debug!("splice reply unique {} is cancelled", request.unique);
 
-                            if entry_data.len() + dir_entry_size > max_size {
-                                break;
-                            }
+            return true;
+        }
 
-                            let dir_entry = fuse_dirent {
-                                ino: entry.inode,
-                                off: entry.index,
-                                namelen: name.len() as u32,
-                                // learn from fuse-rs and golang bazil.org fuse DirentType
-                                r#type: mode_from_kind_and_perm(entry.kind, 0) >> 12,
-                            };
+        match splice::splice_reply(&pipe, fuse_connection.as_raw_fd(), size) {
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                warn!(
+                    "may reply interrupted fuse request, ignore this error {}",
+                    err
+                );
 
-                            BINARY
-                                .serialize_into(&mut entry_data, &dir_entry)
-                                .expect("won't happened");
+                reply_claim.splice_finished();
 
-                            entry_data.extend_from_slice(name.as_bytes());
+                metrics.spliced(request_timer, size - FUSE_OUT_HEADER_SIZE);
 
-                            // padding
-                            for _ in 0..padding_size {
-                                entry_data.push(0);
-                            }
-                        }
+                #[cfg(feature = "tracing")]
+                record_spliced(&span, size);
 
-                        // TODO find a way to avoid multi allocate
+                true
+            }
 
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + entry_data.len()) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
+            // nothing is written, reply it in the normal way
+            Err(err) => {
+                error!(
+                    "splice reply failed {}, request unique {}",
+                    err, request.unique
+                );
+
+                // the interrupt or the deadline leaves the reply to the splicer
+                let errno = match reply_claim.splice_failed() {
+                    None => return false,
+                    Some(errno) => errno,
+                };
+
+                let data = out_header(request.unique, -errno, 0);
+
+                if let Err(err) = unistd::write(fuse_connection.as_raw_fd(), &data) {
+                    error!(
+                        "reply error {} failed {}, request unique {}",
+                        errno, err, request.unique
+                    );
+                }
 
-                        let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + entry_data.len());
+                true
+            }
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
+            // the kernel took a part of the reply as the whole reply, it can't be replied again
+            Ok(n) if n < size => {
+                error!(
+                    "splice reply partially {}/{}, request unique {}",
+                    n, size, request.unique
+                );
 
-                        data.extend_from_slice(&entry_data);
+                reply_claim.splice_finished();
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+                true
+            }
 
-                fuse_opcode::FUSE_RELEASEDIR => {
-                    let mut resp_sender = self.response_sender.clone();
+            Ok(_) => {
+                debug!("splice reply unique {} size {}", request.unique, size);
 
-                    let release_in = match BINARY.deserialize::<fuse_release_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_release_in in releasedir failed {}, request unique {}",
-                                err, request.unique
-                            );
+                reply_claim.splice_finished();
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+                metrics.spliced(request_timer, size - FUSE_OUT_HEADER_SIZE);
 
-                            continue;
-                        }
+                #[cfg(feature = "tracing")]
+                record_spliced(&span, size);
 
-                        Ok(release_in) => release_in,
-                    };
+                // the reply is moved to the kernel, the pipe is empty
+                pipes.put(pipe);
 
-                    let fs = fs.clone();
+                true
+            }
+        }
+    })
+    .await
+}
 
-                    spawn_without_return(async move {
-                        debug!(
-                            "releasedir unique {} inode {} fh {} flags {}",
-                            request.unique, in_header.nodeid, release_in.fh, release_in.flags
-                        );
+#[cfg(feature = "tracing")]
+/// record the spliced read reply in the request span, `size` includes the `fuse_out_header`.
+fn record_spliced(span: &tracing::Span, size: usize) {
+    span.record("errno", 0);
+    span.record("reply_size", (size - FUSE_OUT_HEADER_SIZE) as u64);
+}
 
-                        let resp_value = if let Err(err) = fs
-                            .releasedir(request, in_header.nodeid, release_in.fh, release_in.flags)
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
+/// get the data of the reply, a [`ReplyData::Fd`] is read into memory, the data is at most
+/// `max_len` bytes.
+async fn read_reply_data(
+    reply_data: ReplyData,
+    max_len: u32,
+) -> IoResult<Box<dyn AsRef<[u8]> + Send>> {
+    match reply_data {
+        ReplyData::Data(data) => Ok(data),
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
+        ReplyData::Fd { fd, offset, len } => {
+            let len = len.min(max_len) as usize;
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
+            let data = spawn_blocking(move || splice::read_at(fd.as_raw_fd(), offset, len)).await?;
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+            Ok(Box::new(data))
+        }
+    }
+}
 
-                fuse_opcode::FUSE_FSYNCDIR => {
-                    let mut resp_sender = self.response_sender.clone();
+/// read the directory entries by readdir, if the filesystem doesn't implement readdir, emulate it
+/// by readdirplus, the readdirplus entries are not remembered because the kernel doesn't look up
+/// the readdir entries.
+async fn readdir_entries<FS: Filesystem + Send + Sync + 'static>(
+    fs: Arc<FS>,
+    request: Request,
+    inode: u64,
+    fh: u64,
+    offset: u64,
+    readdir_unsupported: &AtomicBool,
+) -> Result<Pin<Box<dyn Stream<Item = Result<DirectoryEntry>> + Send>>> {
+    if !readdir_unsupported.load(Ordering::Relaxed) {
+        match fs.readdir(request, inode, fh, offset as i64).await {
+            Err(err) if err.0 == libc::ENOSYS => {
+                debug!("readdir is not implemented, emulate it by readdirplus");
+
+                readdir_unsupported.store(true, Ordering::Relaxed);
+            }
 
-                    let fsync_in = match BINARY.deserialize::<fuse_fsync_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_fsync_in in fsyncdir failed {}, request unique {}",
-                                err, request.unique
-                            );
+            result => return result.map(|reply_readdir| reply_readdir.entries),
+        }
+    }
 
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
+    let directory_plus = fs.readdirplus(request, inode, fh, offset, 0).await?;
 
-                            continue;
-                        }
+    let entries = directory_plus.entries.map(|entry| {
+        let entry = entry?;
 
-                        Ok(fsync_in) => fsync_in,
-                    };
+        Ok(DirectoryEntry {
+            inode: entry.inode,
+            index: entry.index,
+            kind: entry.kind,
+            name: entry.name,
+        })
+    });
 
-                    let fs = fs.clone();
+    Ok(Box::pin(entries))
+}
 
-                    spawn_without_return(async move {
-                        let data_sync = fsync_in.fsync_flags & 1 > 0;
+/// a readdirplus entry, or a readdir entry when readdirplus is emulated.
+enum ReaddirplusEntry {
+    Entry(DirectoryEntryPlus),
+    /// the entry is looked up when it is put in the reply.
+    Readdir(DirectoryEntry),
+}
 
-                        debug!(
-                            "fsyncdir unique {} inode {} fh {} data_sync {}",
-                            request.unique, in_header.nodeid, fsync_in.fh, data_sync
-                        );
+impl ReaddirplusEntry {
+    fn name(&self) -> &OsStr {
+        match self {
+            ReaddirplusEntry::Entry(entry) => &entry.name,
+            ReaddirplusEntry::Readdir(entry) => &entry.name,
+        }
+    }
+}
 
-                        let resp_value = if let Err(err) = fs
-                            .fsyncdir(request, in_header.nodeid, fsync_in.fh, data_sync)
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
+/// read the directory entries by readdirplus, if the filesystem doesn't implement readdirplus,
+/// emulate it by readdir, the entries are looked up by [`lookup_dirent`] when they are put in the
+/// reply.
+async fn readdirplus_entries<FS: Filesystem + Send + Sync + 'static>(
+    fs: &FS,
+    request: Request,
+    parent: u64,
+    fh: u64,
+    offset: u64,
+    lock_owner: u64,
+    readdirplus_unsupported: &AtomicBool,
+) -> Result<Pin<Box<dyn Stream<Item = Result<ReaddirplusEntry>> + Send>>> {
+    if !readdirplus_unsupported.load(Ordering::Relaxed) {
+        match fs
+            .readdirplus(request, parent, fh, offset, lock_owner)
+            .await
+        {
+            Err(err) if err.0 == libc::ENOSYS => {
+                debug!("readdirplus is not implemented, emulate it by readdir and lookup");
 
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
+                readdirplus_unsupported.store(true, Ordering::Relaxed);
+            }
 
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
+            Err(err) => return Err(err),
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+            Ok(directory_plus) => {
+                let entries = directory_plus
+                    .entries
+                    .map(|entry| entry.map(ReaddirplusEntry::Entry));
 
-                #[cfg(feature = "file-lock")]
-                fuse_opcode::FUSE_GETLK => {
-                    let mut resp_sender = self.response_sender.clone();
+                return Ok(Box::pin(entries));
+            }
+        }
+    }
 
-                    let getlk_in = match BINARY.deserialize::<fuse_lk_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_lk_in in getlk failed {}, request unique {}",
-                                err, request.unique
-                            );
+    let directory = fs.readdir(request, parent, fh, offset as i64).await?;
 
-                            reply_error_in_place(libc::EINVAL.into(), request, resp_sender).await;
+    let entries = directory
+        .entries
+        .map(|entry| entry.map(ReaddirplusEntry::Readdir));
 
-                            continue;
-                        }
+    Ok(Box::pin(entries))
+}
 
-                        Ok(getlk_in) => getlk_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "getlk unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, getlk_in
-                        );
-
-                        let reply_lock = match fs
-                            .getlk(
-                                request,
-                                in_header.nodeid,
-                                getlk_in.fh,
-                                getlk_in.owner,
-                                getlk_in.lk.start,
-                                getlk_in.lk.end,
-                                getlk_in.lk.r#type,
-                                getlk_in.lk.pid,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_lock) => reply_lock,
-                        };
-
-                        let getlk_out: fuse_lk_out = reply_lock.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_LK_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_LK_OUT_SIZE);
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &getlk_out)
-                            .expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                #[cfg(feature = "file-lock")]
-                fuse_opcode::FUSE_SETLK | fuse_opcode::FUSE_SETLKW => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let setlk_in = match BINARY.deserialize::<fuse_lk_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_lk_in in {:?} failed {}, request unique {}",
-                                opcode, err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(setlk_in) => setlk_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        let block = opcode == fuse_opcode::FUSE_SETLKW;
-
-                        debug!(
-                            "setlk unique {} inode {} block {} {:?}",
-                            request.unique, in_header.nodeid, block, setlk_in
-                        );
-
-                        let resp = if let Err(err) = fs
-                            .setlk(
-                                request,
-                                in_header.nodeid,
-                                setlk_in.fh,
-                                setlk_in.owner,
-                                setlk_in.lk.start,
-                                setlk_in.lk.end,
-                                setlk_in.lk.r#type,
-                                setlk_in.lk.pid,
-                                block,
-                            )
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp,
-                            unique: request.unique,
-                        };
-
-                        let data = BINARY
-                            .serialize(&out_header)
-                            .expect("can't serialize into vec");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_ACCESS => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let access_in = match BINARY.deserialize::<fuse_access_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_access_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(access_in) => access_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "access unique {} inode {} mask {}",
-                            request.unique, in_header.nodeid, access_in.mask
-                        );
-
-                        let resp_value = if let Err(err) =
-                            fs.access(request, in_header.nodeid, access_in.mask).await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
-
-                        debug!("access response {}", resp_value);
-
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_CREATE => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let create_in = match BINARY.deserialize::<fuse_create_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_create_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(create_in) => create_in,
-                    };
-
-                    data = &data[FUSE_CREATE_IN_SIZE..];
-
-                    let name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_create_in body has no null, request unique {}",
-                                request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "create unique {} parent {} name {:?} mode {} flags {}",
-                            request.unique, in_header.nodeid, name, create_in.mode, create_in.flags
-                        );
-
-                        let created = match fs
-                            .create(
-                                request,
-                                in_header.nodeid,
-                                &name,
-                                create_in.mode,
-                                create_in.flags,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(created) => created,
-                        };
-
-                        let (entry_out, open_out): (fuse_entry_out, fuse_open_out) = created.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE + FUSE_OPEN_OUT_SIZE)
-                                as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data = Vec::with_capacity(
-                            FUSE_OUT_HEADER_SIZE + FUSE_ENTRY_OUT_SIZE + FUSE_OPEN_OUT_SIZE,
-                        );
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &entry_out)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &open_out)
-                            .expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_INTERRUPT => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let interrupt_in = match BINARY.deserialize::<fuse_interrupt_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_interrupt_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(interrupt_in) => interrupt_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "interrupt_in unique {} interrupt unique {}",
-                            request.unique, interrupt_in.unique
-                        );
-
-                        let resp_value =
-                            if let Err(err) = fs.interrupt(request, interrupt_in.unique).await {
-                                err.into()
-                            } else {
-                                0
-                            };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
-
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_BMAP => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let bmap_in = match BINARY.deserialize::<fuse_bmap_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_bmap_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(bmap_in) => bmap_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "bmap unique {} inode {} block size {} idx {}",
-                            request.unique, in_header.nodeid, bmap_in.blocksize, bmap_in.block
-                        );
-
-                        let reply_bmap = match fs
-                            .bmap(request, in_header.nodeid, bmap_in.blocksize, bmap_in.block)
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_bmap) => reply_bmap,
-                        };
-
-                        let bmap_out: fuse_bmap_out = reply_bmap.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_BMAP_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_BMAP_OUT_SIZE);
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &bmap_out)
-                            .expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                /*fuse_opcode::FUSE_IOCTL => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let ioctl_in = match BINARY.deserialize::<fuse_ioctl_in>(data) {
-                        Err(err) => {
-                            error!("deserialize fuse_ioctl_in failed {}", err);
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(ioctl_in) => ioctl_in,
-                    };
-
-                    let ioctl_data = (&data[FUSE_IOCTL_IN_SIZE..]).to_vec();
-
-                    let fs = fs.clone();
-                }*/
-                fuse_opcode::FUSE_POLL => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let poll_in = match BINARY.deserialize::<fuse_poll_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_poll_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(poll_in) => poll_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "poll unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, poll_in
-                        );
-
-                        let kh = if poll_in.flags & FUSE_POLL_SCHEDULE_NOTIFY > 0 {
-                            Some(poll_in.kh)
-                        } else {
-                            None
-                        };
-
-                        let reply_poll = match fs
-                            .poll(
-                                request,
-                                in_header.nodeid,
-                                poll_in.fh,
-                                kh,
-                                poll_in.flags,
-                                poll_in.events,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_poll) => reply_poll,
-                        };
-
-                        let poll_out: fuse_poll_out = reply_poll.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_POLL_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_POLL_OUT_SIZE);
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &poll_out)
-                            .expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_NOTIFY_REPLY => {
-                    let resp_sender = self.response_sender.clone();
-
-                    let notify_retrieve_in =
-                        match BINARY.deserialize::<fuse_notify_retrieve_in>(data) {
-                            Err(err) => {
-                                error!(
-                                "deserialize fuse_notify_retrieve_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                                // TODO need to reply or not?
-                                continue;
-                            }
-
-                            Ok(notify_retrieve_in) => notify_retrieve_in,
-                        };
-
-                    data = &data[FUSE_NOTIFY_RETRIEVE_IN_SIZE..];
-
-                    if data.len() < notify_retrieve_in.size as usize {
-                        error!(
-                            "fuse_notify_retrieve unique {} data size is not right",
-                            request.unique
-                        );
-
-                        // TODO need to reply or not?
-                        continue;
-                    }
-
-                    let data = (&data[..notify_retrieve_in.size as usize]).to_vec();
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        if let Err(err) = fs
-                            .notify_reply(
-                                request,
-                                in_header.nodeid,
-                                notify_retrieve_in.offset,
-                                data,
-                            )
-                            .await
-                        {
-                            reply_error_in_place(err, request, resp_sender).await;
-                        }
-                    });
-                }
-
-                fuse_opcode::FUSE_BATCH_FORGET => {
-                    let batch_forget_in = match BINARY.deserialize::<fuse_batch_forget_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_batch_forget_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            // no need to reply
-                            continue;
-                        }
-
-                        Ok(batch_forget_in) => batch_forget_in,
-                    };
-
-                    let mut forgets = vec![];
-
-                    data = &data[FUSE_BATCH_FORGET_IN_SIZE..];
-
-                    // TODO if has less data, should I return error?
-                    while data.len() >= FUSE_FORGET_ONE_SIZE {
-                        match BINARY.deserialize::<fuse_forget_one>(data) {
-                            Err(err) => {
-                                error!("deserialize fuse_batch_forget_in body fuse_forget_one failed {}, request unique {}", err, request.unique);
-
-                                // no need to reply
-                                continue 'dispatch_loop;
-                            }
-
-                            Ok(forget_one) => {
-                                data = &data[FUSE_FORGET_ONE_SIZE..];
-
-                                forgets.push(forget_one);
-                            }
-                        }
-                    }
-
-                    if forgets.len() != batch_forget_in.count as usize {
-                        error!("fuse_forget_one count != fuse_batch_forget_in.count, request unique {}", request.unique);
-
-                        continue;
-                    }
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        let inodes = forgets
-                            .into_iter()
-                            .map(|forget_one| forget_one.nodeid)
-                            .collect::<Vec<_>>();
-
-                        debug!("batch_forget unique {} inodes {:?}", request.unique, inodes);
-
-                        fs.batch_forget(request, &inodes).await
-                    });
-                }
-
-                fuse_opcode::FUSE_FALLOCATE => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let fallocate_in = match BINARY.deserialize::<fuse_fallocate_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_fallocate_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(fallocate_in) => fallocate_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "fallocate unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, fallocate_in
-                        );
-
-                        let resp_value = if let Err(err) = fs
-                            .fallocate(
-                                request,
-                                in_header.nodeid,
-                                fallocate_in.fh,
-                                fallocate_in.offset,
-                                fallocate_in.length,
-                                fallocate_in.mode,
-                            )
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
-
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_READDIRPLUS => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let readdirplus_in = match BINARY.deserialize::<fuse_read_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_read_in in readdirplus failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(readdirplus_in) => readdirplus_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "readdirplus unique {} parent {} {:?}",
-                            request.unique, in_header.nodeid, readdirplus_in
-                        );
-
-                        let mut directory_plus = match fs
-                            .readdirplus(
-                                request,
-                                in_header.nodeid,
-                                readdirplus_in.fh,
-                                readdirplus_in.offset,
-                                readdirplus_in.lock_owner,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(directory_plus) => directory_plus,
-                        };
-
-                        let max_size = readdirplus_in.size as usize;
-
-                        let mut entry_data = Vec::with_capacity(max_size);
-
-                        while let Some(entry) = directory_plus.entries.next().await {
-                            let name = &entry.name;
-
-                            let dir_entry_size = FUSE_DIRENTPLUS_SIZE + name.len();
-
-                            let padding_size = get_padding_size(dir_entry_size);
-
-                            if entry_data.len() + dir_entry_size > max_size {
-                                break;
-                            }
-
-                            let attr = entry.attr;
-
-                            let dir_entry = fuse_direntplus {
-                                entry_out: fuse_entry_out {
-                                    nodeid: attr.ino,
-                                    generation: entry.generation,
-                                    entry_valid: entry.entry_ttl.as_secs(),
-                                    attr_valid: entry.attr_ttl.as_secs(),
-                                    entry_valid_nsec: entry.entry_ttl.subsec_nanos(),
-                                    attr_valid_nsec: entry.attr_ttl.subsec_nanos(),
-                                    attr: attr.into(),
-                                },
-                                dirent: fuse_dirent {
-                                    ino: entry.inode,
-                                    off: entry.index,
-                                    namelen: name.len() as u32,
-                                    // learn from fuse-rs and golang bazil.org fuse DirentType
-                                    r#type: mode_from_kind_and_perm(entry.kind, 0) >> 12,
-                                },
-                            };
-
-                            BINARY
-                                .serialize_into(&mut entry_data, &dir_entry)
-                                .expect("won't happened");
-
-                            entry_data.extend_from_slice(name.as_bytes());
-
-                            // padding
-                            for _ in 0..padding_size {
-                                entry_data.push(0);
-                            }
-                        }
-
-                        // TODO find a way to avoid multi allocate
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + entry_data.len()) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data = Vec::with_capacity(FUSE_OUT_HEADER_SIZE + entry_data.len());
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-
-                        data.extend_from_slice(&entry_data);
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_RENAME2 => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let rename2_in = match BINARY.deserialize::<fuse_rename2_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_rename2_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(rename2_in) => rename2_in,
-                    };
-
-                    data = &data[FUSE_RENAME2_IN_SIZE..];
-
-                    let (old_name, index) = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_rename2_in body doesn't have null, request unique {}",
-                                request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Some(index) => (OsString::from_vec((&data[..index]).to_vec()), index),
-                    };
-
-                    data = &data[index + 1..];
-
-                    let new_name = match get_first_null_position(data) {
-                        None => {
-                            error!(
-                                "fuse_rename2_in body doesn't have second null, request unique {}",
-                                request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Some(index) => OsString::from_vec((&data[..index]).to_vec()),
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!("rename2 unique {} parent {} name {:?} new parent {} new name {:?} flags {}", request.unique, in_header.nodeid, old_name, rename2_in.newdir, new_name, rename2_in.flags);
-
-                        let resp_value = if let Err(err) = fs
-                            .rename2(
-                                request,
-                                in_header.nodeid,
-                                &old_name,
-                                rename2_in.newdir,
-                                &new_name,
-                                rename2_in.flags,
-                            )
-                            .await
-                        {
-                            err.into()
-                        } else {
-                            0
-                        };
-
-                        let out_header = fuse_out_header {
-                            len: FUSE_OUT_HEADER_SIZE as u32,
-                            error: resp_value,
-                            unique: request.unique,
-                        };
-
-                        let data = BINARY.serialize(&out_header).expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_LSEEK => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let lseek_in = match BINARY.deserialize::<fuse_lseek_in>(data) {
-                        Err(err) => {
-                            error!(
-                                "deserialize fuse_lseek_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                            reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                            continue;
-                        }
-
-                        Ok(lseek_in) => lseek_in,
-                    };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "lseek unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, lseek_in
-                        );
-
-                        let reply_lseek = match fs
-                            .lseek(
-                                request,
-                                in_header.nodeid,
-                                lseek_in.fh,
-                                lseek_in.offset,
-                                lseek_in.whence,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_lseek) => reply_lseek,
-                        };
-
-                        let lseek_out: fuse_lseek_out = reply_lseek.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_LSEEK_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_OPEN_OUT_SIZE);
-
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &lseek_out)
-                            .expect("won't happened");
-
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
-
-                fuse_opcode::FUSE_COPY_FILE_RANGE => {
-                    let mut resp_sender = self.response_sender.clone();
-
-                    let copy_file_range_in =
-                        match BINARY.deserialize::<fuse_copy_file_range_in>(data) {
-                            Err(err) => {
-                                error!(
-                                "deserialize fuse_copy_file_range_in failed {}, request unique {}",
-                                err, request.unique
-                            );
-
-                                reply_error(libc::EINVAL.into(), request, resp_sender);
-
-                                continue;
-                            }
-
-                            Ok(copy_file_range_in) => copy_file_range_in,
-                        };
-
-                    let fs = fs.clone();
-
-                    spawn_without_return(async move {
-                        debug!(
-                            "reply_copy_file_range unique {} inode {} {:?}",
-                            request.unique, in_header.nodeid, copy_file_range_in
-                        );
-
-                        let reply_copy_file_range = match fs
-                            .copy_file_range(
-                                request,
-                                in_header.nodeid,
-                                copy_file_range_in.fh_in,
-                                copy_file_range_in.off_in,
-                                copy_file_range_in.nodeid_out,
-                                copy_file_range_in.fh_out,
-                                copy_file_range_in.off_out,
-                                copy_file_range_in.len,
-                                copy_file_range_in.flags,
-                            )
-                            .await
-                        {
-                            Err(err) => {
-                                reply_error_in_place(err, request, resp_sender).await;
-
-                                return;
-                            }
-
-                            Ok(reply_copy_file_range) => reply_copy_file_range,
-                        };
-
-                        let write_out: fuse_write_out = reply_copy_file_range.into();
-
-                        let out_header = fuse_out_header {
-                            len: (FUSE_OUT_HEADER_SIZE + FUSE_WRITE_OUT_SIZE) as u32,
-                            error: 0,
-                            unique: request.unique,
-                        };
-
-                        let mut data =
-                            Vec::with_capacity(FUSE_OUT_HEADER_SIZE + FUSE_WRITE_OUT_SIZE);
+/// look up the readdir entry of the emulated readdirplus except `.` and `..`, return `None` if
+/// the entry is removed after readdir. Like the real readdirplus, the entry is remembered when it
+/// is replied.
+async fn lookup_dirent<FS: Filesystem + Send + Sync>(
+    fs: &FS,
+    request: Request,
+    parent: u64,
+    entry: DirectoryEntry,
+) -> Option<Result<DirectoryEntryPlus>> {
+    // the kernel doesn't look up `.` and `..`, the zero nodeid means no entry attribute
+    if entry.name == "." || entry.name == ".." {
+        return Some(Ok(DirectoryEntryPlus {
+            inode: entry.inode,
+            generation: 0,
+            index: entry.index,
+            kind: entry.kind,
+            name: entry.name,
+            attr: FileAttr {
+                ino: 0,
+                generation: 0,
+                size: 0,
+                blocks: 0,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                #[cfg(target_os = "macos")]
+                crtime: UNIX_EPOCH,
+                kind: entry.kind,
+                perm: 0,
+                nlink: 0,
+                uid: 0,
+                gid: 0,
+                rdev: 0,
+                #[cfg(target_os = "macos")]
+                flags: 0,
+                blksize: 0,
+            },
+            entry_ttl: Duration::from_secs(0),
+            attr_ttl: Duration::from_secs(0),
+        }));
+    }
 
-                        BINARY
-                            .serialize_into(&mut data, &out_header)
-                            .expect("won't happened");
-                        BINARY
-                            .serialize_into(&mut data, &write_out)
-                            .expect("won't happened");
+    match fs.lookup(request, parent, &entry.name).await {
+        Err(err) if err.0 == libc::ENOENT => None,
+
+        Err(err) => Some(Err(err)),
+
+        Ok(reply_entry) => Some(Ok(DirectoryEntryPlus {
+            inode: entry.inode,
+            generation: reply_entry.generation,
+            index: entry.index,
+            kind: entry.kind,
+            name: entry.name,
+            attr: reply_entry.attr,
+            entry_ttl: reply_entry.ttl,
+            attr_ttl: reply_entry.ttl,
+        })),
+    }
+}
 
-                        let _ = resp_sender.send(data).await;
-                    });
-                }
+async fn canonicalize(path: &Path) -> IoResult<PathBuf> {
+    let path = path.to_path_buf();
 
-                #[cfg(target_os = "macos")]
-                fuse_opcode::FUSE_SETVOLNAME => {}
+    spawn_blocking(move || std::fs::canonicalize(path)).await
+}
 
-                #[cfg(target_os = "macos")]
-                fuse_opcode::FUSE_GETXTIMES => {}
+async fn is_empty_dir(path: &Path) -> IoResult<bool> {
+    #[cfg(feature = "async-std-runtime")]
+    return Ok(read_dir(path).await?.next().await.is_none());
 
-                #[cfg(target_os = "macos")]
-                fuse_opcode::FUSE_EXCHANGE => {} // fuse_opcode::CUSE_INIT => {}
-            }
-        }
-    }
+    #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+    return Ok(read_dir(path).await?.next_entry().await?.is_none());
 }
 
 fn reply_error<S>(err: Errno, request: Request, sender: S)
 where
-    S: Sink<Vec<u8>> + Send + Sync + 'static + Unpin,
+    S: Sink<ReplyBuf> + Send + Sync + 'static + Unpin,
 {
     spawn_without_return(reply_error_in_place(err, request, sender));
 }
 
 async fn reply_error_in_place<S>(err: Errno, request: Request, mut sender: S)
 where
-    S: Sink<Vec<u8>> + Send + Sync + 'static + Unpin,
+    S: Sink<ReplyBuf> + Send + Sync + 'static + Unpin,
 {
-    let out_header = fuse_out_header {
-        len: FUSE_OUT_HEADER_SIZE as u32,
-        error: err.into(),
-        unique: request.unique,
-    };
-
-    let data = BINARY.serialize(&out_header).expect("won't happened");
+    let data = Response::Error(err).into_reply_buf(request.unique, CompatSizes::new(0));
 
     let _ = sender.send(data).await;
 }
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::time::Duration;
 
 #[inline]
 pub fn spawn_without_return<F>(f: F)
@@ -24,3 +25,23 @@ where
     #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
     return async { tokio::task::spawn_blocking(f).await.unwrap() };
 }
+
+pub fn spawn<F>(f: F) -> impl Future<Output = F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "async-std-runtime")]
+    return async_std::task::spawn(f);
+
+    #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+    return async { tokio::spawn(f).await.unwrap() };
+}
+
+pub async fn sleep(duration: Duration) {
+    #[cfg(feature = "async-std-runtime")]
+    async_std::task::sleep(duration).await;
+
+    #[cfg(all(not(feature = "async-std-runtime"), feature = "tokio-runtime"))]
+    tokio::time::sleep(duration).await;
+}
@@ -0,0 +1,374 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, ErrorKind};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use nix::fcntl::{self, FcntlArg, OFlag, SpliceFFlags};
+use nix::sys::uio;
+use nix::unistd;
+
+use crate::helper::io_error_from_nix_error;
+
+/// the default capacity of a pipe.
+const DEFAULT_PIPE_SIZE: usize = 64 * 1024;
+
+/// the max number of the idle pipes kept by a [`PipePool`].
+const MAX_POOLED_PIPES: usize = 8;
+
+pub(crate) struct Pipe {
+    pub(crate) read: OwnedFd,
+    pub(crate) write: OwnedFd,
+    /// the bytes the pipe can hold at least.
+    capacity: usize,
+}
+
+impl Pipe {
+    /// create a pipe which can hold at least `size` bytes.
+    pub(crate) fn new(size: usize) -> io::Result<Self> {
+        let (read, write) = unistd::pipe2(OFlag::O_CLOEXEC).map_err(io_error_from_nix_error)?;
+
+        let pipe = unsafe {
+            Self {
+                read: OwnedFd::from_raw_fd(read),
+                write: OwnedFd::from_raw_fd(write),
+                capacity: size.max(DEFAULT_PIPE_SIZE),
+            }
+        };
+
+        if size > DEFAULT_PIPE_SIZE {
+            fcntl::fcntl(
+                pipe.write.as_raw_fd(),
+                FcntlArg::F_SETPIPE_SZ(size as c_int),
+            )
+            .map_err(io_error_from_nix_error)?;
+        }
+
+        Ok(pipe)
+    }
+
+    /// read exactly `buf.len()` bytes from the pipe, the data must be in the pipe already.
+    pub(crate) fn read_exact(&self, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match unistd::read(self.read.as_raw_fd(), &mut buf[filled..]) {
+                Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+                Err(err) => return Err(io_error_from_nix_error(err)),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// the empty pipes to build the splice replies, so a pipe is not created for every reply.
+#[derive(Default)]
+pub(crate) struct PipePool {
+    pipes: Mutex<Vec<Pipe>>,
+}
+
+impl PipePool {
+    /// get an empty pipe which can hold at least `size` bytes.
+    pub(crate) fn get(&self, size: usize) -> io::Result<Pipe> {
+        let mut pipes = self.pipes.lock().unwrap();
+
+        match pipes.iter().position(|pipe| pipe.capacity >= size) {
+            None => {
+                drop(pipes);
+
+                Pipe::new(size)
+            }
+
+            Some(index) => Ok(pipes.swap_remove(index)),
+        }
+    }
+
+    /// put back a pipe, it must be empty.
+    pub(crate) fn put(&self, pipe: Pipe) {
+        let mut pipes = self.pipes.lock().unwrap();
+
+        if pipes.len() < MAX_POOLED_PIPES {
+            pipes.push(pipe);
+        }
+    }
+}
+
+/// splice `len` bytes from `fd_in` to `fd_out`, stop early if `fd_in` reaches EOF. One of the
+/// fds must be a pipe.
+pub(crate) fn splice_all(
+    fd_in: RawFd,
+    mut off_in: Option<u64>,
+    fd_out: RawFd,
+    mut off_out: Option<u64>,
+    len: usize,
+) -> io::Result<usize> {
+    let mut spliced = 0;
+
+    while spliced < len {
+        let mut in_offset = off_in.map(|offset| offset as libc::loff_t);
+        let mut out_offset = off_out.map(|offset| offset as libc::loff_t);
+
+        let n = match fcntl::splice(
+            fd_in,
+            in_offset.as_mut(),
+            fd_out,
+            out_offset.as_mut(),
+            len - spliced,
+            SpliceFFlags::SPLICE_F_MOVE,
+        ) {
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+            Err(err) => return Err(io_error_from_nix_error(err)),
+            Ok(0) => break,
+            Ok(n) => n,
+        };
+
+        off_in = off_in.map(|offset| offset + n as u64);
+        off_out = off_out.map(|offset| offset + n as u64);
+
+        spliced += n;
+    }
+
+    Ok(spliced)
+}
+
+/// write all of `buf` to `fd`.
+fn write_all(fd: RawFd, buf: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+
+    while written < buf.len() {
+        match unistd::write(fd, &buf[written..]) {
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+            Err(err) => return Err(io_error_from_nix_error(err)),
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+        }
+    }
+
+    Ok(())
+}
+
+/// read `len` bytes of `fd` at `offset`, the returned data is shorter than `len` if `fd` reaches
+/// EOF.
+pub(crate) fn read_at(fd: RawFd, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let mut filled = 0;
+
+    while filled < len {
+        match uio::pread(
+            fd,
+            &mut buf[filled..],
+            (offset + filled as u64) as libc::off_t,
+        ) {
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+            Err(err) => return Err(io_error_from_nix_error(err)),
+            Ok(0) => break,
+            Ok(n) => filled += n,
+        }
+    }
+
+    buf.truncate(filled);
+
+    Ok(buf)
+}
+
+/// build a reply in a pipe, the reply data is `len` bytes of `fd` at `offset`, `header` builds
+/// the serialized reply header by the actual data size. Return the pipe and the reply size, the
+/// pipe should be put back to `pipes` after the reply is spliced.
+pub(crate) fn reply_pipe<H>(
+    pipes: &PipePool,
+    fd: RawFd,
+    offset: u64,
+    len: usize,
+    header: H,
+) -> io::Result<(Pipe, usize)>
+where
+    H: FnOnce(usize) -> Vec<u8>,
+{
+    let data_pipe = pipes.get(len)?;
+
+    let data_len = splice_all(fd, Some(offset), data_pipe.write.as_raw_fd(), None, len)?;
+
+    let header = header(data_len);
+
+    let reply_pipe = pipes.get(header.len() + data_len)?;
+
+    write_all(reply_pipe.write.as_raw_fd(), &header)?;
+
+    if splice_all(
+        data_pipe.read.as_raw_fd(),
+        None,
+        reply_pipe.write.as_raw_fd(),
+        None,
+        data_len,
+    )? != data_len
+    {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    // all data is moved to the reply pipe
+    pipes.put(data_pipe);
+
+    Ok((reply_pipe, header.len() + data_len))
+}
+
+/// splice the `size` bytes reply in `pipe` to the fuse connection `fd`. The kernel takes a
+/// splice as one reply, so it is never split, the returned size is less than `size` if the
+/// kernel only takes a part of it.
+pub(crate) fn splice_reply(pipe: &Pipe, fd: RawFd, size: usize) -> io::Result<usize> {
+    loop {
+        match fcntl::splice(
+            pipe.read.as_raw_fd(),
+            None,
+            fd,
+            None,
+            size,
+            SpliceFFlags::SPLICE_F_MOVE,
+        ) {
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+            Err(err) => return Err(io_error_from_nix_error(err)),
+            Ok(n) => return Ok(n),
+        }
+    }
+}
+
+/// the data of a write request.
+///
+/// In normal mode, the data is a [`Bytes`] which shares the request buffer, it can be kept by
+/// [`into_bytes`] without copying. In splice read mode, the data is kept in a pipe, so it can be
+/// spliced to a fd by [`splice_to`] without copying through userspace.
+///
+/// # Notes:
+///
+/// the request buffer is not given back to the buffer pool until the [`Bytes`] is dropped, if
+/// the data is kept for a long time, copy it to release the request buffer.
+///
+/// [`into_bytes`]: WriteData::into_bytes
+/// [`splice_to`]: WriteData::splice_to
+pub struct WriteData {
+    inner: WriteDataInner,
+}
+
+enum WriteDataInner {
+    Buffer(Bytes),
+    Pipe { pipe: Pipe, len: usize },
+}
+
+impl WriteData {
+    pub(crate) fn from_pipe(pipe: Pipe, len: usize) -> Self {
+        Self {
+            inner: WriteDataInner::Pipe { pipe, len },
+        }
+    }
+
+    /// the length of the data.
+    pub fn len(&self) -> usize {
+        match &self.inner {
+            WriteDataInner::Buffer(buf) => buf.len(),
+            WriteDataInner::Pipe { len, .. } => *len,
+        }
+    }
+
+    /// return `true` if the data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// return the data if it is in memory, return `None` if it is in a pipe.
+    pub fn as_slice(&self) -> Option<&[u8]> {
+        match &self.inner {
+            WriteDataInner::Buffer(buf) => Some(buf),
+            WriteDataInner::Pipe { .. } => None,
+        }
+    }
+
+    /// get the data as [`Bytes`], if the data is in a pipe, it will be read into memory.
+    pub fn into_bytes(self) -> io::Result<Bytes> {
+        match self.inner {
+            WriteDataInner::Buffer(buf) => Ok(buf),
+            WriteDataInner::Pipe { pipe, len } => {
+                let mut buf = vec![0; len];
+
+                pipe.read_exact(&mut buf)?;
+
+                Ok(buf.into())
+            }
+        }
+    }
+
+    /// read the data into memory.
+    pub fn into_vec(self) -> io::Result<Vec<u8>> {
+        self.into_bytes().map(Vec::from)
+    }
+
+    /// write the data to `fd` at `offset`, if `offset` is `None`, write at the current position
+    /// of `fd`. Return the written size.
+    ///
+    /// # Notes:
+    ///
+    /// it is a blocking operation. If the data is in a pipe, it is spliced to `fd` without copying
+    /// through userspace.
+    pub fn splice_to(self, fd: &impl AsRawFd, offset: Option<u64>) -> io::Result<usize> {
+        let fd = fd.as_raw_fd();
+
+        match self.inner {
+            WriteDataInner::Pipe { pipe, len } => {
+                splice_all(pipe.read.as_raw_fd(), None, fd, offset, len)
+            }
+
+            WriteDataInner::Buffer(buf) => match offset {
+                None => write_all(fd, &buf).map(|_| buf.len()),
+
+                Some(offset) => {
+                    let mut written = 0;
+
+                    while written < buf.len() {
+                        match uio::pwrite(
+                            fd,
+                            &buf[written..],
+                            (offset + written as u64) as libc::off_t,
+                        ) {
+                            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+                            Err(err) => return Err(io_error_from_nix_error(err)),
+                            Ok(0) => break,
+                            Ok(n) => written += n,
+                        }
+                    }
+
+                    Ok(written)
+                }
+            },
+        }
+    }
+}
+
+impl From<Bytes> for WriteData {
+    fn from(buf: Bytes) -> Self {
+        Self {
+            inner: WriteDataInner::Buffer(buf),
+        }
+    }
+}
+
+impl From<Vec<u8>> for WriteData {
+    fn from(buf: Vec<u8>) -> Self {
+        Bytes::from(buf).into()
+    }
+}
+
+impl Debug for WriteData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let kind = match &self.inner {
+            WriteDataInner::Buffer(_) => "buffer",
+            WriteDataInner::Pipe { .. } => "pipe",
+        };
+
+        f.debug_struct("WriteData")
+            .field("kind", &kind)
+            .field("len", &self.len())
+            .finish()
+    }
+}